@@ -7,12 +7,206 @@ fn build_cli() -> Command {
     Command::new("splinch")
         .version(env!("CARGO_PKG_VERSION"))
         .about("Split a file into two XOR-complementary parts for secure transport, or combine them back")
+        .args_conflicts_with_subcommands(true)
+        .subcommand_negates_reqs(true)
+        .subcommand(
+            Command::new("pad")
+                .about("Generate and manage pre-shared one-time pads for --one-time-pad")
+                .subcommand_required(true)
+                .subcommand(
+                    Command::new("generate")
+                        .about("Generate a pad file, or with --pages a directory of numbered pages, of random bytes")
+                        .arg(
+                            Arg::new("path")
+                                .help("Pad file, or pad book directory with --pages, to create")
+                                .required(true)
+                                .value_name("PATH"),
+                        )
+                        .arg(
+                            Arg::new("size")
+                                .long("size")
+                                .help("Total pad size, such as 64M or 1G")
+                                .required(true)
+                                .value_name("SIZE"),
+                        )
+                        .arg(
+                            Arg::new("pages")
+                                .long("pages")
+                                .help("Split the pad into this many numbered pages")
+                                .value_name("N"),
+                        )
+                        .arg(
+                            Arg::new("random-source")
+                                .long("random-source")
+                                .help("Where the pad bytes come from: os, a device or file such as /dev/hwrng, or seed:HEX (tests only)")
+                                .value_name("SOURCE"),
+                        )
+                        .arg(
+                            Arg::new("insecure-deterministic")
+                                .long("insecure-deterministic")
+                                .help("Allow a seed:HEX random source, whose bytes anyone who knows the seed can reproduce")
+                                .action(clap::ArgAction::SetTrue),
+                        ),
+                )
+                .subcommand(
+                    Command::new("list")
+                        .about("List the used and remaining bytes of a pad or of each page of a pad book")
+                        .arg(
+                            Arg::new("path")
+                                .help("Pad file or pad book directory")
+                                .required(true)
+                                .value_name("PATH"),
+                        ),
+                )
+                .subcommand(
+                    Command::new("destroy")
+                        .about("Securely delete the used pages of a pad book, or the given pad files")
+                        .arg(
+                            Arg::new("paths")
+                                .help("Pad book directory or pad files")
+                                .required(true)
+                                .action(clap::ArgAction::Append)
+                                .value_name("PATHS"),
+                        )
+                        .arg(
+                            Arg::new("passes")
+                                .short('p')
+                                .long("passes")
+                                .help("Number of overwrite passes (default: 1)")
+                                .default_value("1")
+                                .value_name("PASSES"),
+                        )
+                        .arg(
+                            Arg::new("random-source")
+                                .long("random-source")
+                                .help("Where the overwrite bytes come from: os, a device or file, or seed:HEX (tests only)")
+                                .value_name("SOURCE"),
+                        )
+                        .arg(
+                            Arg::new("insecure-deterministic")
+                                .long("insecure-deterministic")
+                                .help("Allow a seed:HEX random source, whose bytes anyone who knows the seed can reproduce")
+                                .action(clap::ArgAction::SetTrue),
+                        ),
+                ),
+        )
+        .subcommand(
+            Command::new("audit")
+                .about("Run statistical randomness tests over share payloads and report p-values")
+                .arg(
+                    Arg::new("paths")
+                        .help("Share files, or pads, to test")
+                        .required(true)
+                        .action(clap::ArgAction::Append)
+                        .value_name("PATHS"),
+                ),
+        )
+        .subcommand(
+            Command::new("refresh")
+                .about("Replace the shares of an XOR split with fresh ones for the same file, without combining them")
+                .arg(
+                    Arg::new("shares")
+                        .help("Share files of the split; given one, the others are found as when combining")
+                        .required(true)
+                        .action(clap::ArgAction::Append)
+                        .value_name("SHARES"),
+                )
+                .arg(
+                    Arg::new("output")
+                        .short('o')
+                        .long("output")
+                        .help("Path for each refreshed share, in share order (repeat; default: replace the shares)")
+                        .action(clap::ArgAction::Append)
+                        .value_name("PATH"),
+                )
+                .arg(
+                    Arg::new("secure-delete")
+                        .short('s')
+                        .long("secure-delete")
+                        .help("Securely delete the old shares once the refreshed ones are written")
+                        .action(clap::ArgAction::SetTrue),
+                )
+                .arg(
+                    Arg::new("passes")
+                        .short('p')
+                        .long("passes")
+                        .help("Number of overwrite passes for secure delete (default: 1)")
+                        .default_value("1")
+                        .value_name("PASSES"),
+                )
+                .arg(
+                    Arg::new("random-source")
+                        .long("random-source")
+                        .help("Where the masks come from: os, a device or file, or seed:HEX (tests only)")
+                        .value_name("SOURCE"),
+                )
+                .arg(
+                    Arg::new("insecure-deterministic")
+                        .long("insecure-deterministic")
+                        .help("Allow a seed:HEX random source, whose bytes anyone who knows the seed can reproduce")
+                        .action(clap::ArgAction::SetTrue),
+                ),
+        )
+        .subcommand(
+            Command::new("resplit")
+                .about("Split one share again into sub-shares that must all be present to rebuild it")
+                .arg(
+                    Arg::new("share")
+                        .help("Share file, or sub-share, to split again")
+                        .required(true)
+                        .value_name("SHARE"),
+                )
+                .arg(
+                    Arg::new("shares")
+                        .short('n')
+                        .long("shares")
+                        .help("Number of sub-shares (default: 2)")
+                        .default_value("2")
+                        .value_name("SHARES"),
+                )
+                .arg(
+                    Arg::new("output-dir")
+                        .short('d')
+                        .long("output-dir")
+                        .help("Directory for the sub-shares: give once for all sub-shares or once per sub-share")
+                        .action(clap::ArgAction::Append)
+                        .value_name("DIR"),
+                )
+                .arg(
+                    Arg::new("secure-delete")
+                        .short('s')
+                        .long("secure-delete")
+                        .help("Securely delete the share once the sub-shares are written")
+                        .action(clap::ArgAction::SetTrue),
+                )
+                .arg(
+                    Arg::new("passes")
+                        .short('p')
+                        .long("passes")
+                        .help("Number of overwrite passes for secure delete (default: 1)")
+                        .default_value("1")
+                        .value_name("PASSES"),
+                )
+                .arg(
+                    Arg::new("random-source")
+                        .long("random-source")
+                        .help("Where the sub-share pads come from: os, a device or file, or seed:HEX (tests only)")
+                        .value_name("SOURCE"),
+                )
+                .arg(
+                    Arg::new("insecure-deterministic")
+                        .long("insecure-deterministic")
+                        .help("Allow a seed:HEX random source, whose bytes anyone who knows the seed can reproduce")
+                        .action(clap::ArgAction::SetTrue),
+                ),
+        )
         .arg(
             Arg::new("input")
                 .short('i')
                 .long("input")
-                .help("Path to the input file to split or a .xor1/.xor2 file to combine")
-                .required(true)
+                .help("Path to the input file to split (\"-\" for stdin) or a .xorN/.sssN file to combine (repeat to list shares)")
+                .required_unless_present("check-manifest")
+                .action(clap::ArgAction::Append)
                 .value_name("FILE"),
         )
         .arg(
@@ -26,9 +220,24 @@ fn build_cli() -> Command {
             Arg::new("combine")
                 .short('c')
                 .long("combine")
-                .help("Combine two XOR files back into the original")
+                .help("Combine XOR files back into the original")
                 .action(clap::ArgAction::SetTrue),
         )
+        .arg(
+            Arg::new("shares")
+                .short('n')
+                .long("shares")
+                .help("Number of shares to split into (default: 2)")
+                .default_value("2")
+                .value_name("N"),
+        )
+        .arg(
+            Arg::new("threshold")
+                .short('k')
+                .long("threshold")
+                .help("Use Shamir threshold sharing: any K of the shares rebuild the file")
+                .value_name("K"),
+        )
         .arg(
             Arg::new("secure-delete")
                 .short('s')
@@ -44,10 +253,196 @@ fn build_cli() -> Command {
                 .default_value("1")
                 .value_name("N"),
         )
+        .arg(
+            Arg::new("mac")
+                .short('m')
+                .long("mac")
+                .help("Add a one-time MAC so that combining detects damaged or tampered shares")
+                .action(clap::ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("check")
+                .long("check")
+                .help("Check the block checksums of share files and report corrupt blocks")
+                .action(clap::ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("manifest")
+                .long("manifest")
+                .help("Write <input>.sha256, a sha256sum-compatible manifest of the shares")
+                .action(clap::ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("check-manifest")
+                .long("check-manifest")
+                .help("Check received shares against a manifest (combine afterwards with -c)")
+                .value_name("FILE"),
+        )
+        .arg(
+            Arg::new("share-cmd")
+                .long("share-cmd")
+                .help("Stream each share into a shell command instead of a file (repeat once per share)")
+                .action(clap::ArgAction::Append)
+                .value_name("CMD"),
+        )
+        .arg(
+            Arg::new("output")
+                .short('o')
+                .long("output")
+                .help("Split: path for each share, repeated once per share. Combine: path for the output (\"-\" for stdout)")
+                .action(clap::ArgAction::Append)
+                .value_name("PATH"),
+        )
+        .arg(
+            Arg::new("output-dir")
+                .short('d')
+                .long("output-dir")
+                .help("Directory for the shares: give once for all shares or once per share")
+                .action(clap::ArgAction::Append)
+                .value_name("DIR"),
+        )
+        .arg(
+            Arg::new("recursive")
+                .short('r')
+                .long("recursive")
+                .help("Split a directory tree (names, permissions, mtimes, symlinks) as a streamed archive")
+                .action(clap::ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("strict-devices")
+                .long("strict-devices")
+                .help("Refuse to split when every share would land on the same device")
+                .action(clap::ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("random-names")
+                .long("random-names")
+                .help("Give the shares random names; combining restores the original name")
+                .action(clap::ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("hybrid")
+                .long("hybrid")
+                .help("Encrypt the input with XChaCha20-Poly1305 into one <input>.enc and split only the key")
+                .action(clap::ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("seeded-pad")
+                .long("seeded-pad")
+                .help("Make the first share a 256-bit seed for a ChaCha20 pad (computational security only)")
+                .action(clap::ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("one-time-pad")
+                .long("one-time-pad")
+                .value_name("PAD")
+                .help("Take the first share from a pre-shared one-time pad file, tracked in <PAD>.ledger, and do not write it"),
+        )
+        .arg(
+            Arg::new("random-source")
+                .long("random-source")
+                .value_name("SOURCE")
+                .help("Where random bytes come from: os, a device or file such as /dev/hwrng, or seed:HEX (tests only)"),
+        )
+        .arg(
+            Arg::new("insecure-deterministic")
+                .long("insecure-deterministic")
+                .help("Allow a seed:HEX random source, whose bytes anyone who knows the seed can reproduce")
+                .action(clap::ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("pad-registry")
+                .long("pad-registry")
+                .value_name("FILE")
+                .help("Record pad fingerprints in this registry, and warn about pads it has seen in another split"),
+        )
+        .arg(
+            Arg::new("health-report")
+                .long("health-report")
+                .help("Report the SP 800-90B health test statistics of the random bytes after splitting")
+                .action(clap::ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("compress")
+                .long("compress")
+                .value_name("ALGO")
+                .help("Compress the input before splitting: none, zstd or deflate (smaller shares, but the size leaks more)"),
+        )
+        .arg(
+            Arg::new("pad")
+                .long("pad")
+                .value_name("MODE")
+                .help("Pad the shares to hide the input's size: pow2, block:N or a target size such as 64M"),
+        )
+        .arg(
+            Arg::new("stdout")
+                .long("stdout")
+                .help("Write the combined file to stdout instead of next to the shares")
+                .action(clap::ArgAction::SetTrue),
+        )
 }
 
 fn custom_troff_sections() -> &'static str {
     r#"
+.SH PAD COMMANDS
+.TP
+\fBsplinch pad generate\fR \fIPATH\fR \fB\-\-size\fR \fISIZE\fR [\fB\-\-pages\fR \fIN\fR]
+Write a pad of \fISIZE\fR bytes (with an optional K, M or G suffix) from
+the OS random number generator, or the source given with
+\fB\-\-random\-source\fR, to \fIPATH\fR, which must not exist. With
+\fB\-\-pages\fR, \fIPATH\fR becomes a pad book directory of \fIN\fR
+numbered pages sharing the size between them.
+.TP
+\fBsplinch pad list\fR \fIPATH\fR
+Show the size, used bytes and remaining bytes of a pad, or of every page of
+a pad book, as recorded in the ledgers.
+.TP
+\fBsplinch pad destroy\fR \fIPATH\fR... [\fB\-p\fR \fIPASSES\fR]
+Securely delete, as with \fB\-s\fR, every used page of a pad book, or the
+pad files given, along with their ledgers.
+.SH AUDIT COMMAND
+.TP
+\fBsplinch audit\fR \fIFILE\fR...
+Stream the payload of each share, skipping its header, block checksums and
+MAC tag, through five statistical tests and print each verdict with its
+p\-value and statistic: the monobit and runs tests of NIST SP 800\-22, a
+chi\-square test of the byte counts, the serial correlation of adjacent
+bytes, and the most\-common\-value min\-entropy estimate of NIST SP
+800\-90B. A test passes when its p\-value is at least 0.01; the entropy
+estimate passes at 4 bits per byte or more. Files without a share header,
+such as pads, are tested whole, and tests that need more data than the file
+holds are reported as too short. Exits with status 1 if any test fails,
+which happens by chance in about one audit of a random file in 25.
+.SH REFRESH COMMAND
+.TP
+\fBsplinch refresh\fR \fISHARE\fR... [\fB\-o\fR \fIPATH\fR]... [\fB\-s\fR [\fB\-p\fR \fIPASSES\fR]]
+Replace every part of an XOR split with a fresh part that combines to the
+same file, without ever rebuilding the file. Each part but the last is
+XOR\-ed with a new random mask and the last with all the masks, block by
+block, and the MAC key shares are refreshed the same way, so the MAC still
+authenticates. Given one part, the others are found as with \fB\-c\fR.
+The refreshed parts get a new split\-set ID and replace the old ones under
+their names, or go to the paths given with \fB\-o\fR, one per part in part
+order. In place, every refreshed part is written before any takes its
+name, and the old parts are deleted only once all have been replaced; if
+a rename fails, the old parts are put back. With \fB\-s\fR, the old
+parts are securely deleted. Threshold,
+seeded and one\-time pad splits cannot be refreshed, and neither can parts
+that were split again with \fBsplinch resplit\fR.
+.SH RESPLIT COMMAND
+.TP
+\fBsplinch resplit\fR \fISHARE\fR [\fB\-n\fR \fIN\fR] [\fB\-d\fR \fIDIR\fR]... [\fB\-s\fR [\fB\-p\fR \fIPASSES\fR]]
+Split one part again with XOR into \fIN\fR sub\-shares (default 2), all of
+which are needed to rebuild it, for a part that a sub\-team must hold
+together. The sub\-shares are written as \fISHARE\fR.sub1 to
+\fISHARE\fR.sub\fIN\fR, or into the \fB\-d\fR directories, and record
+the re\-split in their headers; a sub\-share can be split again in turn.
+The part's MAC key share is split the same way and every sub\-share carries
+its tag. With \fB\-s\fR, the part is securely deleted afterwards. A
+seeded part cannot be split again. \fB\-c\fR joins complete sets of
+sub\-shares back into their parts, so the leaves of the whole tree can be
+given in one call, and finds sub\-shares under their default names as it
+finds parts.
 .SH EXAMPLES
 .PP
 Split a file into two XOR-complementary parts:
@@ -64,6 +459,14 @@ splinch \-i secret.pdf \-v
 .fi
 .RE
 .PP
+Split a file with a one\-time MAC so that combining detects damaged or
+tampered parts:
+.RS 4
+.nf
+splinch \-i secret.pdf \-m
+.fi
+.RE
+.PP
 Split a file and securely delete the original (3 overwrite passes):
 .RS 4
 .nf
@@ -71,43 +474,453 @@ splinch \-i secret.pdf \-s \-p 3
 .fi
 .RE
 .PP
+Split a file into three parts, one for each courier:
+.RS 4
+.nf
+splinch \-i secret.pdf \-n 3
+.fi
+.RE
+.PP
 Combine two parts back into the original:
 .RS 4
 .nf
 splinch \-i secret.pdf.xor1 \-c
 .fi
 .RE
+.PP
+Combine three parts back into the original (the share count is read from
+the share headers):
+.RS 4
+.nf
+splinch \-i secret.pdf.xor1 \-c
+.fi
+.RE
+.PP
+Split a file into five parts, any three of which rebuild it:
+.RS 4
+.nf
+splinch \-i secret.pdf \-k 3 \-n 5
+.fi
+.RE
+.PP
+Combine any three of the five parts:
+.RS 4
+.nf
+splinch \-c \-i secret.pdf.sss1 \-i secret.pdf.sss4 \-i secret.pdf.sss5
+.fi
+.RE
+.PP
+Check received parts for damage before combining:
+.RS 4
+.nf
+splinch \-\-check \-i secret.pdf.xor1 \-i secret.pdf.xor2
+.fi
+.RE
+.PP
+Split a whole directory, and combine it back into a directory:
+.RS 4
+.nf
+splinch \-r \-i photos
+splinch \-c \-i photos.xor1
+.fi
+.RE
+.PP
+Split the output of another program without writing it to disk (the parts
+are named \fIstdin.xor1\fR and \fIstdin.xor2\fR):
+.RS 4
+.nf
+tar c dir | splinch \-i \-
+.fi
+.RE
+.PP
+Combine the parts and unpack them straight from the pipe:
+.RS 4
+.nf
+splinch \-c \-\-stdout \-i stdin.xor1 | tar x
+.fi
+.RE
+.PP
+Hand each part straight to its courier instead of writing it next to the
+original (the part's file name is in \fBSPLINCH_SHARE_NAME\fR):
+.RS 4
+.nf
+splinch \-i secret.pdf \e
+  \-\-share\-cmd 'ssh host1 "cat > $SPLINCH_SHARE_NAME"' \e
+  \-\-share\-cmd 'cat > /media/usb/"$SPLINCH_SHARE_NAME"'
+.fi
+.RE
+.PP
+Write each part to its own removable drive instead of next to the original:
+.RS 4
+.nf
+splinch \-i secret.pdf \-d /media/usb1 \-d /media/usb2
+.fi
+.RE
+.PP
+Name each part explicitly, then combine them into a chosen file:
+.RS 4
+.nf
+splinch \-i secret.pdf \-o /media/usb1/part \-o /media/usb2/part
+splinch \-c \-i /media/usb1/part \-i /media/usb2/part \-o secret.pdf
+.fi
+.RE
+.PP
+Give the parts meaningless names; combining finds the rest of the split
+next to any one part and restores \fIsecret.pdf\fR:
+.RS 4
+.nf
+splinch \-i secret.pdf \-\-random\-names \-d /media/usb
+splinch \-c \-i /media/usb/3f9c0d7e51a2b8c4e6f1a9d0b7c35e28
+.fi
+.RE
+.PP
+Encrypt a large file once and split only its key, then combine it with
+the ciphertext next to the parts:
+.RS 4
+.nf
+splinch \-i backup.img \-\-hybrid
+splinch \-c \-i backup.img.xor1
+.fi
+.RE
+.PP
+Make the first part a seed small enough to read over the phone, and
+combine once it has been typed back in:
+.RS 4
+.nf
+splinch \-i notes.txt \-\-seeded\-pad
+base64 notes.txt.xor1
+base64 \-d > notes.txt.xor1
+splinch \-c \-i notes.txt.xor2
+.fi
+.RE
+.PP
+Generate a pad book of 100 pages to copy to the other party in person,
+check what is left of it, and destroy the pages that have been used:
+.RS 4
+.nf
+splinch pad generate /media/usb/book \-\-size 1G \-\-pages 100
+splinch pad list /media/usb/book
+splinch pad destroy /media/usb/book
+.fi
+.RE
+.PP
+Keep a registry of pad fingerprints and check received parts against it:
+.RS 4
+.nf
+splinch \-i secret.pdf \-\-pad\-registry ~/.splinch\-pads
+splinch \-c \-i secret.pdf.xor1 \-i secret.pdf.xor2 \-\-pad\-registry ~/.splinch\-pads
+.fi
+.RE
+.PP
+Show an auditor that the parts look random:
+.RS 4
+.nf
+splinch audit secret.pdf.xor1 secret.pdf.xor2
+.fi
+.RE
+.PP
+Refresh long\-stored parts in place, overwriting the old ones:
+.RS 4
+.nf
+splinch refresh \-s secret.pdf.xor1
+.fi
+.RE
+.PP
+Hand the second part to two people who must both be present, and combine
+from the leaves:
+.RS 4
+.nf
+splinch resplit \-s secret.pdf.xor2
+splinch \-c \-i secret.pdf.xor1 \-i secret.pdf.xor2.sub1 \-i secret.pdf.xor2.sub2
+.fi
+.RE
+.PP
+Take the first part from a pad exchanged in person, send only the second,
+and combine it with the receiver's copy of the pad:
+.RS 4
+.nf
+splinch \-i report.pdf \-\-one\-time\-pad /media/usb/pad.bin
+splinch \-c \-i report.pdf.xor2 \-i /media/usb/pad.bin
+.fi
+.RE
+.PP
+Draw a pad book's bytes from a hardware random number generator instead of
+the OS:
+.RS 4
+.nf
+splinch pad generate /media/usb/book \-\-size 1G \-\-pages 100 \-\-random\-source /dev/hwrng
+.fi
+.RE
+.PP
+Compress a large text file before splitting it, so every part is smaller:
+.RS 4
+.nf
+splinch \-i logs.txt \-\-compress zstd
+.fi
+.RE
+.PP
+Hide the exact size of the original by padding the parts up to the next
+power of two, or to a whole number of megabytes:
+.RS 4
+.nf
+splinch \-i secret.pdf \-\-pad pow2
+splinch \-i secret.pdf \-\-pad block:1M
+.fi
+.RE
+.PP
+Split a file and write a manifest of the parts for the recipients:
+.RS 4
+.nf
+splinch \-i secret.pdf \-\-manifest
+.fi
+.RE
+.PP
+Check the received parts against the manifest, then combine them:
+.RS 4
+.nf
+splinch \-\-check\-manifest secret.pdf.sha256 \-c \-i secret.pdf.xor1
+.fi
+.RE
+.SH ENVIRONMENT
+Commands given with \fB\-\-share\-cmd\fR are run with \fBsh \-c\fR, receive
+their part on stdin and see:
+.TP
+.B SPLINCH_SHARE_NAME
+The file name the part would have had, e.g. \fIsecret.pdf.xor1\fR.
+.TP
+.B SPLINCH_SHARE_INDEX
+The part's 1\-based index.
+.TP
+.B SPLINCH_SHARE_COUNT
+The number of parts.
+.TP
+.B SPLINCH_SPLIT_ID
+The split\-set ID in hex.
+.PP
+If a command fails or stops reading, the split is aborted, commands that are
+still running are killed before their input is closed, part files that were
+already finished are deleted, and the outcome for each part is reported.
 .SH EXIT STATUS
 .TP
 .B 0
 Successful operation.
 .TP
 .B 1
-An error occurred (missing files, verification failure, invalid arguments, etc.).
+An error occurred (missing files, verification failure, corrupt blocks, invalid arguments, etc.).
 .SH FILES
 .TP
-.I <input>.xor1
-Random byte stream (one-time pad) generated during splitting.
+.I <input>.xor1 ... <input>.xor(N\-1)
+Independent random byte streams (one-time pads) generated during splitting.
+Every share file starts with a small header recording the scheme, share
+index, share count, threshold and a random split\-set ID. Combining refuses
+shares whose split\-set IDs differ, for example parts from two separate
+splits of the same file. Headerless
+\fI.xor1\fR/\fI.xor2\fR pairs written by splinch 0.2 and earlier are still
+combined as raw bytes.
+.PP
+The payload of each share is stored in 64\ KB blocks, each followed by a
+CRC\-32 of that block's share bytes. The checksums cover random\-looking share
+bytes only and reveal nothing about the original. \fB\-\-check\fR and
+\fB\-c\fR report exactly which blocks of which share are corrupt; \fB\-c\fR
+does not keep an output built from a corrupt block.
+.TP
+.I <input>.xorN
+XOR of the original file and all of the pads. With the default of two
+shares this is \fI<input>.xor2\fR.
+.TP
+.I <input>.sss1 ... <input>.sssN
+Shamir threshold shares written when \fB\-k\fR is given. Each share's
+x\-coordinate is recorded in its header.
+.TP
+.I <share>.sub1 ... <share>.subN
+Sub\-shares written by \fBsplinch resplit\fR. Each keeps the header of the
+part it came from and adds the path of re\-splits down to itself: for each
+re\-split, its sub\-share index, the sub\-share count and a random
+re\-split ID.
+.TP
+.I <input>.enc
+Ciphertext written with \fB\-\-hybrid\fR: the original encrypted with
+XChaCha20\-Poly1305 in 64\ KB chunks, each with its own authentication tag.
+The parts then hold only the key and nonce. It is written to the first
+\fB\-d\fR directory if one is given, and named \fI<split ID>.enc\fR with
+\fB\-\-random\-names\fR. \fB\-c\fR looks for it next to the first part
+and refuses an output built from a chunk that does not authenticate.
+.TP
+.I <book>/001.pad ... <book>/N.pad
+Pages of a pad book written by \fBsplinch pad generate \-\-pages\fR, each a
+pad of its own with its own ledger. \fBsplinch pad destroy\fR on the book
+securely deletes every page its ledger shows was used, along with the
+ledger; given a page, it deletes that page, as the receiver keeps no
+ledger.
 .TP
-.I <input>.xor2
-XOR of the original file and the .xor1 pad.
+.I <pad>.ledger
+Written next to a pad given with \fB\-\-one\-time\-pad\fR, one line per
+split: the offset and length of the pad range it used and its split\-set
+ID. Each split starts after every recorded range, and reserves the rest of
+the pad until it finishes, so no pad byte is used twice. Deleting or
+restoring an old copy of the ledger allows reuse. The parts record the
+offset of their range, and \fB\-c\fR reads the pad there.
+.TP
+.I <registry>
+The pad registry given with \fB\-\-pad\-registry\fR: a random key on a
+\fBkey\fR line, then one line per XOR pad a split produced (shares 1 to
+N\-1, a seeded or one\-time pad included) with keyed SHA\-256 fingerprints
+of its first and last 32 bytes, its split\-set ID and its share index. It
+is created on first use. Without the key, the fingerprints reveal nothing
+about the pads.
+.TP
+.I <input>.sha256
+Manifest written with \fB\-\-manifest\fR, listing the size and SHA\-256 of
+every share file. It is a \fBsha256sum\fR(1) checksum file, so a recipient
+can check a share with \fBsha256sum \-c \-\-ignore\-missing\fR. Sizes and the
+split\-set ID are stored on comment lines. The hashes cover share files only.
+.PP
+With \fB\-d\fR, parts keep these names but are written to the given
+directories, and \fB\-\-manifest\fR writes a copy of the manifest into each
+of them. With \fB\-o\fR, each part is written to exactly the given path.
+Parts are never written over an existing file or over the input.
+\fB\-c \-o\fR refuses to overwrite an existing file.
+.PP
+With \fB\-r\fR, the directory is archived in tar format while it is
+split, keeping names, permissions, ownership, mtimes and symlinks (which are
+stored as links, not followed). Neither the archive nor the tree is written
+to disk in plaintext along the way. The split metadata records that the
+parts hold a directory, and \fB\-c\fR unpacks it into a new directory named like
+the original, removing it again if a checksum or the MAC does not match.
+\fB\-c \-\-stdout\fR writes the tar archive itself.
+.PP
+The original's file name, mode, mtime and extended attributes are split
+along with its content, in a metadata block at the start of every share's
+payload, so a share on its own reveals none of them. \fB\-c\fR names the
+output after the original, in the directory of the first part, and restores
+its mode, mtime and extended attributes (the latter where the filesystem
+allows). The setuid, setgid and sticky bits are only restored when the split
+has a MAC that authenticated them. With \fB\-\-random\-names\fR the parts get 32 random hex digits
+as names and the manifest is named \fI<split ID>.sha256\fR; given one such
+part, \fB\-c\fR finds the others in its directory by split\-set ID.
+.PP
+With \fB\-\-pad\fR, the split data (metadata, content and the true content
+length) is padded with zeros before splitting: \fBpow2\fR rounds it up to
+the next power of two, \fBblock:\fR\fIN\fR to a multiple of \fIN\fR bytes,
+and a plain size pads it to exactly that many bytes, refusing inputs that do
+not fit. Sizes take an optional \fBK\fR, \fBM\fR or \fBG\fR suffix. The true
+length is stored at the end of the split data, and \fB\-c\fR trims the
+padding again. Each part is the padded size plus its header, block checksums
+and MAC tag.
+.PP
+With \fB\-\-compress\fR \fBzstd\fR or \fBdeflate\fR, the content is
+compressed while it is read, before any padding, and the algorithm is
+recorded in the split metadata; \fB\-c\fR decompresses it automatically.
+\fB\-\-compress none\fR, the default, turns compression off.
+.PP
+When \fB\-i \-\fR reads the original from stdin, the parts are named
+\fIstdin.xorN\fR (or \fIstdin.sssN\fR) in the current directory.
 .PP
 If the output filename already exists, a numeric suffix is inserted
 (e.g., \fIsecret.1.pdf\fR) to avoid overwriting.
 .SH SECURITY CONSIDERATIONS
 .PP
 \fBsplinch\fR implements one-time pad (OTP) file splitting. Each output
-file is statistically indistinguishable from random data. Neither file alone
-reveals any information about the original.
+file is statistically indistinguishable from random data. Any set of fewer
+than all N files reveals no information about the original.
+.PP
+With \fB\-k\fR K, \fBsplinch\fR uses Shamir secret sharing over GF(256)
+instead. Any K of the N files rebuild the original, so up to N\-K files may be
+lost, and any K\-1 files reveal nothing about it.
 .PP
-For secure transport, the two output files \fBmust\fR be sent over separate,
-independent channels. Sending both files over the same channel defeats the
+With \fB\-m\fR, each split draws a fresh Poly1305 one\-time MAC key and
+stores only shares of it, split the same way as the file, in the share
+headers. The tag is masked by the secret half of the key, so it is stored
+in the clear after every share without revealing anything about the file.
+When combining, the output is authenticated and deleted again if any share
+was damaged or tampered with. A plain hash of the original is deliberately
+not stored, since it would leak information from a single share.
+.PP
+For secure transport, the output files \fBmust\fR be sent over separate,
+independent channels. Sending all of them over the same channel defeats the
 security guarantee.
 .PP
+After splitting, \fBsplinch\fR warns when every part was written to the same
+device. Devices are told apart by \fBstat\fR(2) device number and, on Linux,
+by the mount source in \fI/proc/self/mountinfo\fR, so that two btrfs
+subvolumes or bind mounts of one disk count as one device. With
+\fB\-\-strict\-devices\fR, \fBsplinch\fR refuses to split instead.
+.PP
+Without \fB\-\-pad\fR, every part reveals the exact size of the original.
+With it, a part reveals only the size bucket the original falls into.
+.PP
+With \fB\-\-hybrid\fR, the parts are shares of a 256\-bit key and reveal
+nothing, but the ciphertext is only as strong as XChaCha20\-Poly1305: its
+security is computational, not information\-theoretic, and it reveals the
+size of the original. The ciphertext may travel over any channel; the parts
+still need separate ones.
+.PP
+With \fB\-\-seeded\-pad\fR, the first part holds a 256\-bit seed instead of
+a random pad, and the pad is the ChaCha20 keystream of that seed. The
+first part is then about a hundred bytes, but the other parts are only as
+hidden as ChaCha20 is strong: the security is computational, not
+information\-theoretic, and \fBsplinch\fR warns about this after every
+seeded split.
+.PP
+Compression with \fB\-\-compress\fR makes the size of the parts depend on
+the content as well as its length: a file that compresses well, such as one
+full of zeros or repeated records, yields small parts. An observer who can
+guess candidate contents, or who sees several splits of related data, can
+learn from that. Leave compression off for data where that matters, or
+combine it with \fB\-\-pad\fR to blur the compressed size.
+.PP
 For files larger than 10\ MB, the \fB\-v\fR flag uses sampled verification
 (10 random 64\ KB chunks) rather than a full byte-by-byte comparison.
 This is fast but not exhaustive.
 .PP
+With \fB\-\-stdout\fR, output stops before the first corrupt block, but the
+MAC can only be checked after the whole file has been written. If
+\fBsplinch\fR then exits with an error, whatever was read from the pipe must
+be discarded.
+.PP
+Using one pad for two files is fatal: XOR\-ing the shares of both splits
+cancels the pad and leaves the XOR of the two originals. With
+\fB\-\-pad\-registry\fR, every split fingerprints its pads in a local
+registry and warns if one was recorded before, which happens when a seed or
+random file is reused as a random source or a ledger is lost. Combining with
+\fB\-\-pad\-registry\fR warns when a share carries a pad recorded for a
+different split, such as a \fI.xor1\fR copied into another split set. The
+registry only knows the splits made with it on this machine.
+.PP
+\fBsplinch refresh\fR limits the damage of parts that leak one at a time
+over years: an old part reveals nothing together with refreshed parts, but
+the old parts still combine with each other. Every copy of the old parts
+must be destroyed, and in place without \fB\-s\fR the old parts are only
+unlinked. Manifests written for the old parts no longer match.
+.PP
+Sub\-shares from \fBsplinch resplit\fR protect a part exactly as the parts
+protect the file: any set short of all the sub\-shares of a part reveals
+nothing about it, so every member of the sub\-team must be present. Each
+sub\-share needs a channel of its own, just as each part does.
+.PP
+All pads, keys, seeds and split\-set IDs come from the OS random number
+generator unless \fB\-\-random\-source\fR names another source: a
+device or file such as \fI/dev/hwrng\fR, read from its start and failing
+the command if it runs out, or \fBseed:\fR and 64 hex digits for a ChaCha20
+generator. A seeded source makes every split reproducible, so anyone who
+knows the seed can rebuild the pads; it exists for tests, is refused
+without \fB\-\-insecure\-deterministic\fR, and \fBsplinch\fR warns
+whenever one is used. A file source must hold fresh
+random bytes and must not be used twice. Each command opens the source
+once, so the pages of a pad book, the shares of a split and the passes of
+\fB\-s\fR all get different bytes.
+.PP
+Every random byte a split, \fBsplinch refresh\fR, \fBsplinch resplit\fR or
+\fBsplinch pad generate\fR uses first passes
+the continuous health tests of NIST SP 800\-90B: the repetition count test
+fails on 11 identical bytes in a row, and the adaptive proportion test on a
+byte that recurs 78 times in a 512\-byte window. The cutoffs assume 4 bits
+of min\-entropy per byte, so a healthy source practically never trips them.
+A failure aborts the split and deletes the partial parts, or the partial
+pad. \fB\-\-health\-report\fR prints the longest run and the highest
+window count seen. The tests catch a stuck or badly broken source, not a
+subtly weak one.
+.PP
 The \fB\-s\fR (secure delete) option overwrites the original file with
 cryptographically random data before removing it. Each pass is flushed to
 physical storage with \fBfsync\fR(2). However:
@@ -125,6 +938,7 @@ effective for destroying the original data.
 .SH SEE ALSO
 .BR xor (1),
 .BR split (1),
+.BR sha256sum (1),
 .BR openssl (1)
 .SH AUTHORS
 Brian Tabone
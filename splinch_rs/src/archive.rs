@@ -0,0 +1,111 @@
+//! Directory mode: a directory tree streamed through the split as a tar
+//! archive.
+//!
+//! Splitting archives the tree on a background thread into a pipe that feeds
+//! the split loop, and combining unpacks from a pipe fed by the combine loop,
+//! so the archive never exists on disk in plaintext. Entries keep their
+//! names, permissions, ownership, mtimes and symlinks; symlinks are stored as
+//! links, not followed.
+
+use std::io::{self, PipeReader, PipeWriter, Read};
+use std::path::{Path, PathBuf};
+use std::thread::{self, JoinHandle};
+
+use anyhow::{Context, Result};
+
+/// Reads the tar archive of a directory as it is being built.
+///
+/// An error while archiving is returned from `read` at the point where the
+/// archive would otherwise end, so a failed archive is never mistaken for a
+/// complete one.
+pub struct ArchiveReader {
+    pipe: PipeReader,
+    archiver: Option<JoinHandle<io::Result<()>>>,
+}
+
+impl Read for ArchiveReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.pipe.read(buf)?;
+        if n == 0
+            && !buf.is_empty()
+            && let Some(archiver) = self.archiver.take()
+        {
+            archiver
+                .join()
+                .unwrap_or_else(|_| Err(io::Error::other("archiver thread panicked")))?;
+        }
+        Ok(n)
+    }
+}
+
+/// Start archiving `dir` on a background thread.
+pub fn archive_dir(dir: &Path) -> Result<ArchiveReader> {
+    let (pipe, writer) = io::pipe().context("failed to create a pipe")?;
+    let dir = dir.to_path_buf();
+
+    let archiver = thread::spawn(move || {
+        let mut builder = tar::Builder::new(writer);
+        builder.follow_symlinks(false);
+        builder.mode(tar::HeaderMode::Complete);
+        builder
+            .append_dir_all(".", &dir)
+            .and_then(|()| builder.finish())
+            .map_err(|e| {
+                io::Error::new(
+                    e.kind(),
+                    format!("failed to archive {}: {}", dir.display(), e),
+                )
+            })
+    });
+
+    Ok(ArchiveReader {
+        pipe,
+        archiver: Some(archiver),
+    })
+}
+
+/// Unpacks an archive written to the returned pipe into `dest`, which must
+/// already exist.
+///
+/// Call [`Unpacker::finish`] after closing the pipe to learn whether the
+/// archive was unpacked completely.
+pub struct Unpacker {
+    dest: PathBuf,
+    thread: JoinHandle<io::Result<()>>,
+}
+
+impl Unpacker {
+    pub fn start(dest: &Path) -> Result<(PipeWriter, Self)> {
+        let (mut pipe, writer) = io::pipe().context("failed to create a pipe")?;
+        let target = dest.to_path_buf();
+
+        let thread = thread::spawn(move || {
+            let mut archive = tar::Archive::new(&mut pipe);
+            archive.set_preserve_permissions(true);
+            archive.set_preserve_mtime(true);
+            archive.set_overwrite(false);
+            let unpacked = archive.unpack(&target);
+
+            // Keep reading to the end even if unpacking failed, so the
+            // combine loop never fails on a closed pipe and can report its
+            // own checksum and MAC errors first.
+            io::copy(&mut pipe, &mut io::sink())?;
+            unpacked
+        });
+
+        Ok((
+            writer,
+            Self {
+                dest: dest.to_path_buf(),
+                thread,
+            },
+        ))
+    }
+
+    pub fn finish(self) -> Result<()> {
+        self.thread
+            .join()
+            .unwrap_or_else(|_| Err(io::Error::other("unpacking thread panicked")))
+            .with_context(|| format!("failed to unpack into {}", self.dest.display()))
+    }
+}
@@ -0,0 +1,375 @@
+//! Statistical randomness tests over the payload of a share.
+//!
+//! Every share should be indistinguishable from random bytes. The audit
+//! streams a share's payload in `CHUNK_SIZE` blocks, skipping the header,
+//! block checksums and MAC tag, and runs:
+//!
+//! | test               | statistic                                 | from                  |
+//! |--------------------|-------------------------------------------|-----------------------|
+//! | monobit            | excess of one bits over zero bits         | NIST SP 800-22 2.1    |
+//! | runs               | number of runs of identical bits          | NIST SP 800-22 2.3    |
+//! | byte chi-square    | chi-square of the byte counts, 255 d.o.f. | Knuth, TAOCP 3.3.1    |
+//! | serial correlation | correlation of each byte with the next    | `ent`                 |
+//! | entropy            | most-common-value min-entropy per byte    | NIST SP 800-90B 6.3.1 |
+//!
+//! The first four pass when their p-value is at least [`AUDIT_ALPHA`], so a
+//! truly random share still fails one of them in about one audit in 25. The
+//! entropy estimate has no p-value and passes when it is at least the
+//! [`MIN_ENTROPY`] that the health tests assume. Passing shows only that
+//! the share has no gross statistical flaw, not that it is secure.
+//!
+//! [`MIN_ENTROPY`]: crate::MIN_ENTROPY
+
+use std::path::{Path, PathBuf};
+
+use anyhow::Result;
+
+use crate::CHUNK_SIZE;
+use crate::health::MIN_ENTROPY;
+use crate::share::ShareInput;
+
+/// Significance level of the tests with a p-value.
+pub const AUDIT_ALPHA: f64 = 0.01;
+
+/// The outcome of one test.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Verdict {
+    Pass,
+    Fail,
+    /// The payload is too short for the test to mean anything.
+    TooShort,
+}
+
+/// One test run over a share.
+#[derive(Debug, Clone)]
+pub struct AuditTest {
+    pub name: &'static str,
+    /// The test statistic, for reports.
+    pub statistic: String,
+    pub p_value: Option<f64>,
+    pub verdict: Verdict,
+}
+
+/// Every test run over one share.
+#[derive(Debug, Clone)]
+pub struct AuditReport {
+    pub path: PathBuf,
+    /// Payload bytes tested.
+    pub bytes: u64,
+    pub tests: Vec<AuditTest>,
+}
+
+impl AuditReport {
+    /// Whether no test failed.
+    pub fn passed(&self) -> bool {
+        self.tests.iter().all(|test| test.verdict != Verdict::Fail)
+    }
+}
+
+/// Run the statistical tests over the payload of the share at `path`.
+///
+/// A file without a share header, such as a one-time pad, is tested as a
+/// whole.
+pub fn audit_share(path: &Path) -> Result<AuditReport> {
+    let mut share = ShareInput::open(path)?;
+    let mut counts = Counts::default();
+    let mut buf = vec![0u8; CHUNK_SIZE];
+    loop {
+        let n = share.read_payload(&mut buf)?;
+        if n == 0 {
+            break;
+        }
+        counts.update(&buf[..n]);
+    }
+
+    Ok(AuditReport {
+        path: path.to_path_buf(),
+        bytes: counts.bytes,
+        tests: vec![
+            counts.monobit(),
+            counts.runs(),
+            counts.chi_square(),
+            counts.serial_correlation(),
+            counts.entropy(),
+        ],
+    })
+}
+
+/// Running totals that every test is computed from.
+#[derive(Debug, Clone)]
+struct Counts {
+    bytes: u64,
+    byte_counts: [u64; 256],
+    /// Adjacent bits that differ, most significant bit first.
+    bit_changes: u64,
+    first: u8,
+    last: u8,
+    sum: u128,
+    sum_squares: u128,
+    /// Sum of each byte times the next, wrapping around to the first.
+    sum_products: u128,
+}
+
+impl Default for Counts {
+    fn default() -> Self {
+        Self {
+            bytes: 0,
+            byte_counts: [0; 256],
+            bit_changes: 0,
+            first: 0,
+            last: 0,
+            sum: 0,
+            sum_squares: 0,
+            sum_products: 0,
+        }
+    }
+}
+
+impl Counts {
+    fn update(&mut self, buf: &[u8]) {
+        for &byte in buf {
+            if self.bytes == 0 {
+                self.first = byte;
+            } else {
+                self.bit_changes += u64::from((self.last & 1) != (byte >> 7));
+                self.sum_products += u128::from(self.last) * u128::from(byte);
+            }
+            self.bit_changes += u64::from(((byte ^ (byte >> 1)) & 0x7f).count_ones());
+            self.byte_counts[byte as usize] += 1;
+            self.sum += u128::from(byte);
+            self.sum_squares += u128::from(byte) * u128::from(byte);
+            self.last = byte;
+            self.bytes += 1;
+        }
+    }
+
+    fn bits(&self) -> f64 {
+        self.bytes as f64 * 8.0
+    }
+
+    fn ones(&self) -> u64 {
+        (0..=255u8)
+            .map(|byte| self.byte_counts[byte as usize] * u64::from(byte.count_ones()))
+            .sum()
+    }
+
+    fn monobit(&self) -> AuditTest {
+        let name = "monobit";
+        if self.bytes < 13 {
+            return too_short(name);
+        }
+        let n = self.bits();
+        let excess = 2.0 * self.ones() as f64 - n;
+        let p_value = erfc(excess.abs() / n.sqrt() / std::f64::consts::SQRT_2);
+        with_p_value(name, format!("ones - zeros = {}", excess), p_value)
+    }
+
+    fn runs(&self) -> AuditTest {
+        let name = "runs";
+        if self.bytes < 13 {
+            return too_short(name);
+        }
+        let n = self.bits();
+        let pi = self.ones() as f64 / n;
+        let runs = self.bit_changes + 1;
+        let statistic = format!("{} runs", runs);
+        // The runs test assumes the monobit test passed; far from half ones
+        // it is not applicable and fails outright.
+        if (pi - 0.5).abs() >= 2.0 / n.sqrt() {
+            return with_p_value(name, statistic, 0.0);
+        }
+        let expected = 2.0 * n * pi * (1.0 - pi);
+        let p_value =
+            erfc((runs as f64 - expected).abs() / (2.0 * (2.0 * n).sqrt() * pi * (1.0 - pi)));
+        with_p_value(name, statistic, p_value)
+    }
+
+    fn chi_square(&self) -> AuditTest {
+        let name = "byte chi-square";
+        // At least 5 expected occurrences of every byte value.
+        if self.bytes < 5 * 256 {
+            return too_short(name);
+        }
+        let expected = self.bytes as f64 / 256.0;
+        let chi_square: f64 = self
+            .byte_counts
+            .iter()
+            .map(|&count| (count as f64 - expected).powi(2) / expected)
+            .sum();
+        let p_value = igamc(255.0 / 2.0, chi_square / 2.0);
+        with_p_value(name, format!("chi-square = {:.2}", chi_square), p_value)
+    }
+
+    fn serial_correlation(&self) -> AuditTest {
+        let name = "serial correlation";
+        if self.bytes < 100 {
+            return too_short(name);
+        }
+        // In integers, as the two terms of each difference nearly cancel.
+        let n = self.bytes as i128;
+        let sum = self.sum as i128;
+        let products = (self.sum_products + u128::from(self.last) * u128::from(self.first)) as i128;
+        let numerator = n * products - sum * sum;
+        let denominator = n * self.sum_squares as i128 - sum * sum;
+        let correlation = if denominator == 0 {
+            1.0
+        } else {
+            numerator as f64 / denominator as f64
+        };
+        let n = self.bytes as f64;
+        // Under the null hypothesis the coefficient times sqrt(n) is
+        // standard normal.
+        let p_value = erfc(correlation.abs() * n.sqrt() / std::f64::consts::SQRT_2);
+        with_p_value(name, format!("r = {:.6}", correlation), p_value)
+    }
+
+    fn entropy(&self) -> AuditTest {
+        let name = "entropy";
+        if self.bytes < 5 * 256 {
+            return too_short(name);
+        }
+        let n = self.bytes as f64;
+        let shannon: f64 = self
+            .byte_counts
+            .iter()
+            .filter(|&&count| count > 0)
+            .map(|&count| {
+                let p = count as f64 / n;
+                -p * p.log2()
+            })
+            .sum::<f64>()
+            .abs();
+
+        // The most common value estimate: the upper 99% confidence bound on
+        // the probability of the most common byte.
+        let max = *self.byte_counts.iter().max().expect("256 counts") as f64;
+        let p_max = max / n;
+        let p_upper = (p_max + 2.576 * (p_max * (1.0 - p_max) / (n - 1.0)).sqrt()).min(1.0);
+        let min_entropy = p_upper.log2().abs();
+
+        AuditTest {
+            name,
+            statistic: format!(
+                "{:.4} bits/byte, min-entropy {:.4} bits/byte",
+                shannon, min_entropy
+            ),
+            p_value: None,
+            verdict: if min_entropy >= f64::from(MIN_ENTROPY) {
+                Verdict::Pass
+            } else {
+                Verdict::Fail
+            },
+        }
+    }
+}
+
+fn too_short(name: &'static str) -> AuditTest {
+    AuditTest {
+        name,
+        statistic: String::new(),
+        p_value: None,
+        verdict: Verdict::TooShort,
+    }
+}
+
+fn with_p_value(name: &'static str, statistic: String, p_value: f64) -> AuditTest {
+    AuditTest {
+        name,
+        statistic,
+        p_value: Some(p_value),
+        verdict: if p_value >= AUDIT_ALPHA {
+            Verdict::Pass
+        } else {
+            Verdict::Fail
+        },
+    }
+}
+
+/// The complementary error function, with a relative error below 1.2e-7
+/// (Numerical Recipes' `erfcc`).
+fn erfc(x: f64) -> f64 {
+    let z = x.abs();
+    let t = 1.0 / (1.0 + 0.5 * z);
+    let poly = -z * z - 1.265_512_23
+        + t * (1.000_023_68
+            + t * (0.374_091_96
+                + t * (0.096_784_18
+                    + t * (-0.186_288_06
+                        + t * (0.278_868_07
+                            + t * (-1.135_203_98
+                                + t * (1.488_515_87 + t * (-0.822_152_23 + t * 0.170_872_77))))))));
+    let result = t * poly.exp();
+    if x >= 0.0 { result } else { 2.0 - result }
+}
+
+/// The natural log of the gamma function, by the Lanczos approximation.
+fn ln_gamma(x: f64) -> f64 {
+    const COEFFICIENTS: [f64; 6] = [
+        76.180_091_729_471_46,
+        -86.505_320_329_416_77,
+        24.014_098_240_830_91,
+        -1.231_739_572_450_155,
+        0.001_208_650_973_866_179,
+        -0.000_005_395_239_384_953,
+    ];
+    let tmp = x + 5.5 - (x + 0.5) * (x + 5.5).ln();
+    let series = COEFFICIENTS
+        .iter()
+        .enumerate()
+        .fold(1.000_000_000_190_015, |acc, (i, c)| {
+            acc + c / (x + 1.0 + i as f64)
+        });
+    -tmp + (2.506_628_274_631_000_5 * series / x).ln()
+}
+
+/// The regularized upper incomplete gamma function Q(a, x), by its series
+/// below a + 1 and its continued fraction above.
+fn igamc(a: f64, x: f64) -> f64 {
+    const EPSILON: f64 = 1e-15;
+    const MAX_ITERATIONS: usize = 10_000;
+    if x <= 0.0 {
+        return 1.0;
+    }
+    let prefix = (-x + a * x.ln() - ln_gamma(a)).exp();
+
+    if x < a + 1.0 {
+        let mut term = 1.0 / a;
+        let mut sum = term;
+        let mut ap = a;
+        for _ in 0..MAX_ITERATIONS {
+            ap += 1.0;
+            term *= x / ap;
+            sum += term;
+            if term.abs() < sum.abs() * EPSILON {
+                break;
+            }
+        }
+        return (1.0 - sum * prefix).max(0.0);
+    }
+
+    // Lentz's method.
+    let tiny = f64::MIN_POSITIVE / EPSILON;
+    let mut b = x + 1.0 - a;
+    let mut c = 1.0 / tiny;
+    let mut d = 1.0 / b;
+    let mut h = d;
+    for i in 1..MAX_ITERATIONS {
+        let an = -(i as f64) * (i as f64 - a);
+        b += 2.0;
+        d = an * d + b;
+        if d.abs() < tiny {
+            d = tiny;
+        }
+        c = b + an / c;
+        if c.abs() < tiny {
+            c = tiny;
+        }
+        d = 1.0 / d;
+        let delta = d * c;
+        h *= delta;
+        if (delta - 1.0).abs() < EPSILON {
+            break;
+        }
+    }
+    prefix * h
+}
@@ -0,0 +1,126 @@
+//! Optional compression of the content before it is split.
+//!
+//! The content is compressed as it is read, ahead of any padding, and the
+//! algorithm is recorded in the metadata block so that combining can
+//! decompress without being told. Compression makes the shares smaller, but
+//! how well a file compresses says something about what is in it, so the
+//! size of a compressed split leaks more than the size of a plain one.
+
+use std::io::{self, Read};
+use std::str::FromStr;
+
+use anyhow::{Result, bail};
+
+/// How the content is compressed before splitting.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Compression {
+    #[default]
+    None,
+    Zstd,
+    Deflate,
+}
+
+impl Compression {
+    /// The code stored in the metadata block.
+    pub fn id(self) -> u8 {
+        match self {
+            Compression::None => 0,
+            Compression::Zstd => 1,
+            Compression::Deflate => 2,
+        }
+    }
+
+    pub fn from_id(id: u8) -> Result<Self> {
+        match id {
+            0 => Ok(Compression::None),
+            1 => Ok(Compression::Zstd),
+            2 => Ok(Compression::Deflate),
+            _ => bail!("unknown compression {} in metadata", id),
+        }
+    }
+
+    /// Wrap `reader` so that reading it yields the compressed content.
+    pub fn encoder<'a>(self, reader: impl Read + 'a) -> io::Result<Box<dyn Read + 'a>> {
+        Ok(match self {
+            Compression::None => Box::new(reader),
+            Compression::Zstd => Box::new(zstd::stream::read::Encoder::new(reader, 0)?),
+            Compression::Deflate => Box::new(flate2::read::DeflateEncoder::new(
+                reader,
+                flate2::Compression::default(),
+            )),
+        })
+    }
+
+    /// Wrap `reader` so that reading it yields the decompressed content.
+    pub fn decoder<'a>(self, reader: impl Read + 'a) -> io::Result<Box<dyn Read + 'a>> {
+        Ok(match self {
+            Compression::None => Box::new(reader),
+            Compression::Zstd => Box::new(zstd::stream::read::Decoder::new(reader)?),
+            Compression::Deflate => Box::new(flate2::read::DeflateDecoder::new(reader)),
+        })
+    }
+}
+
+impl FromStr for Compression {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "none" => Ok(Compression::None),
+            "zstd" => Ok(Compression::Zstd),
+            "deflate" => Ok(Compression::Deflate),
+            _ => bail!("expected none, zstd or deflate, got {:?}", s),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn compress(compression: Compression, content: &[u8]) -> Vec<u8> {
+        let mut compressed = Vec::new();
+        compression
+            .encoder(content)
+            .unwrap()
+            .read_to_end(&mut compressed)
+            .unwrap();
+        compressed
+    }
+
+    fn decompress(compression: Compression, compressed: &[u8]) -> io::Result<Vec<u8>> {
+        let mut content = Vec::new();
+        compression.decoder(compressed)?.read_to_end(&mut content)?;
+        Ok(content)
+    }
+
+    #[test]
+    fn framing_round_trips() {
+        let text = b"the same line, over and over\n".repeat(10_000);
+        for compression in [Compression::None, Compression::Zstd, Compression::Deflate] {
+            assert_eq!(Compression::from_id(compression.id()).unwrap(), compression);
+            for content in [&b""[..], b"x", &text] {
+                let compressed = compress(compression, content);
+                assert_eq!(decompress(compression, &compressed).unwrap(), content);
+            }
+            if compression != Compression::None {
+                assert!(compress(compression, &text).len() < text.len() / 10);
+            }
+        }
+        assert!(Compression::from_id(3).is_err());
+    }
+
+    #[test]
+    fn truncated_streams_fail_to_decompress() {
+        let text = b"the same line, over and over\n".repeat(10_000);
+        for compression in [Compression::Zstd, Compression::Deflate] {
+            let compressed = compress(compression, &text);
+            let truncated = &compressed[..compressed.len() / 2];
+            assert!(
+                decompress(compression, truncated).is_err(),
+                "{:?}",
+                compression
+            );
+        }
+    }
+}
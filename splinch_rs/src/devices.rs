@@ -0,0 +1,148 @@
+//! Detecting shares that would all end up on the same storage device.
+//!
+//! Each share's directory is identified by the filesystem it lives on. The
+//! device number from `stat` is the primary key, but on Linux the mount table
+//! in `/proc/self/mountinfo` is also consulted: btrfs subvolumes and similar
+//! report a distinct device number per mount while sharing one block device,
+//! so mounts backed by the same `/dev` node are treated as one device.
+
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+
+/// The storage a set of shares was found to share.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SharedDevice {
+    /// The mount point containing the shares, if the mount table is readable.
+    pub mount_point: Option<PathBuf>,
+    /// The mount source (such as `/dev/sda1`), or the device number.
+    pub device: String,
+}
+
+impl std::fmt::Display for SharedDevice {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.mount_point {
+            Some(mount_point) => write!(f, "{} on {}", self.device, mount_point.display()),
+            None => write!(f, "{}", self.device),
+        }
+    }
+}
+
+struct Mount {
+    mount_point: PathBuf,
+    source: String,
+}
+
+/// If every path in `paths` is in a directory on the same device, describe
+/// that device.
+///
+/// Only the parent directories are examined, so the files need not exist
+/// yet. Returns `None` when there are fewer than two paths, when they span
+/// more than one device, or when the platform gives no way to tell.
+pub fn shared_device(paths: &[PathBuf]) -> Result<Option<SharedDevice>> {
+    if paths.len() < 2 {
+        return Ok(None);
+    }
+    let mounts = read_mounts();
+    let mut shared: Option<SharedDevice> = None;
+
+    for path in paths {
+        let Some(device) = device_of(path, &mounts)? else {
+            return Ok(None);
+        };
+        match &shared {
+            None => shared = Some(device),
+            Some(first) if first.device == device.device => {}
+            Some(_) => return Ok(None),
+        }
+    }
+
+    Ok(shared)
+}
+
+fn device_of(path: &Path, mounts: &[Mount]) -> Result<Option<SharedDevice>> {
+    let dir = match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+    let dir = dir
+        .canonicalize()
+        .with_context(|| format!("cannot access {}", dir.display()))?;
+
+    let mount = mounts
+        .iter()
+        .filter(|mount| dir.starts_with(&mount.mount_point))
+        .max_by_key(|mount| mount.mount_point.as_os_str().len());
+
+    if let Some(mount) = mount
+        && mount.source.starts_with("/dev/")
+    {
+        return Ok(Some(SharedDevice {
+            mount_point: Some(mount.mount_point.clone()),
+            device: mount.source.clone(),
+        }));
+    }
+
+    let Some(dev) = device_number(&dir)? else {
+        return Ok(None);
+    };
+    Ok(Some(SharedDevice {
+        mount_point: mount.map(|mount| mount.mount_point.clone()),
+        device: format!("device {}", dev),
+    }))
+}
+
+#[cfg(unix)]
+fn device_number(dir: &Path) -> Result<Option<u64>> {
+    use std::os::unix::fs::MetadataExt;
+
+    let metadata = std::fs::metadata(dir)
+        .with_context(|| format!("failed to read metadata for {}", dir.display()))?;
+    Ok(Some(metadata.dev()))
+}
+
+#[cfg(not(unix))]
+fn device_number(_dir: &Path) -> Result<Option<u64>> {
+    Ok(None)
+}
+
+/// Parse the mount table, or return nothing where it is unavailable.
+fn read_mounts() -> Vec<Mount> {
+    let Ok(mountinfo) = std::fs::read_to_string("/proc/self/mountinfo") else {
+        return Vec::new();
+    };
+
+    mountinfo
+        .lines()
+        .filter_map(|line| {
+            // id parent major:minor root mount-point options [optional...] - type source super-options
+            let fields: Vec<&str> = line.split(' ').collect();
+            let separator = fields.iter().position(|&field| field == "-")?;
+            Some(Mount {
+                mount_point: PathBuf::from(unescape(fields.get(4)?)),
+                source: unescape(fields.get(separator + 2)?),
+            })
+        })
+        .collect()
+}
+
+/// Undo the octal escapes (`\040` for a space) used in the mount table.
+fn unescape(field: &str) -> String {
+    let bytes = field.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'\\'
+            && let Some(byte) = field
+                .get(i + 1..i + 4)
+                .and_then(|digits| u8::from_str_radix(digits, 8).ok())
+        {
+            out.push(byte);
+            i += 4;
+        } else {
+            out.push(bytes[i]);
+            i += 1;
+        }
+    }
+    String::from_utf8_lossy(&out).into_owned()
+}
@@ -0,0 +1,701 @@
+//! The self-describing header at the start of every share file.
+//!
+//! All integers are little-endian. Version 1 is laid out as:
+//!
+//! | offset | size | field                                          |
+//! |--------|------|------------------------------------------------|
+//! | 0      | 8    | magic `SPLINCH\0`                              |
+//! | 8      | 2    | format version                                 |
+//! | 10     | 2    | header length in bytes, including this prefix  |
+//! | 12     | 1    | scheme (0 = XOR, 1 = Shamir threshold)         |
+//! | 13     | 1    | flags, see below                               |
+//! | 14     | 2    | share index, 1-based (Shamir x-coordinate)     |
+//! | 16     | 2    | share count                                    |
+//! | 18     | 2    | threshold (equal to the share count for XOR)   |
+//! | 20     | 16   | split-set ID, random and common to all shares  |
+//!
+//! Optional fields follow in flag-bit order, each present only when its flag
+//! is set:
+//!
+//! | flag   | size   | field                                          |
+//! |--------|--------|------------------------------------------------|
+//! | `0x01` | 32     | this share's share of the one-time MAC key     |
+//! | `0x02` | 4      | checksum block length in payload bytes         |
+//! | `0x04` | 0      | the payload starts with a metadata block       |
+//! | `0x08` | 32     | seed of the keystream standing in for the pad  |
+//! | `0x10` | 8      | offset of the split's range in a one-time pad  |
+//! | `0x20` | 1+20d  | the path of a sub-share through `d` re-splits  |
+//!
+//! The share payload follows immediately after `header length` bytes, so a
+//! later version may append fields that older readers skip over. Flags change
+//! the layout of the file, so readers reject flags they do not know. With
+//! `0x01` set, a 16-byte MAC tag follows the payload at the end of the file.
+//! With `0x02` set, the payload is stored in blocks of the given length (the
+//! last may be shorter), each followed by the CRC-32 of that block's share
+//! bytes. The checksums cover share bytes only, so they reveal nothing about
+//! the original file. With `0x04` set, the original's name, mode, mtime and
+//! kind are not stored here but in a block at the start of the split data,
+//! so the header reveals nothing about them. With `0x08` set, the share is
+//! the first of an XOR split whose random pad is not stored but regenerated
+//! as the ChaCha20 keystream of the seed, so the file ends after the header
+//! and any trailer. With `0x10` set, share 1 of the XOR split was not
+//! written but taken from a pre-shared one-time pad file, starting at the
+//! given offset; see the `otp` module. With `0x20` set, the file is a
+//! sub-share: the share described by the fixed fields was split again with
+//! XOR, perhaps repeatedly, and this file is one leaf of that tree. The field
+//! is a depth byte `d` followed by one 20-byte level per re-split, from the
+//! share down to the leaf: the sub-share index (2), sub-share count (2) and
+//! sub-split ID (16). The MAC key share is then this leaf's share of the
+//! share's key share, and the tag is the share's.
+//!
+//! Files written by splinch 0.2 and earlier carry no header at all. Readers
+//! that do not find the magic treat the file as a legacy raw share.
+
+use std::io::{self, Read, Write};
+
+use anyhow::{Context, Result, bail};
+
+use crate::{mac, seed, shamir};
+
+/// Identifies a share file written with a header.
+pub const MAGIC: [u8; 8] = *b"SPLINCH\0";
+
+/// The newest header version this build reads and the one it writes.
+pub const VERSION: u16 = 1;
+
+/// Length of the fixed part of a version 1 header.
+pub const FIXED_LEN: usize = 36;
+
+/// The share carries a MAC key share in its header and a tag after its payload.
+pub const FLAG_MAC: u8 = 0x01;
+
+/// The payload is stored in blocks, each followed by a CRC-32.
+pub const FLAG_CHECKSUMS: u8 = 0x02;
+
+/// The payload starts with a metadata block describing the original, split
+/// along with the content. See the `metadata` module.
+pub const FLAG_METADATA: u8 = 0x04;
+
+/// The share stores a seed in place of its payload. See the `seed` module.
+pub const FLAG_SEED: u8 = 0x08;
+
+/// Share 1 of the split is a range of a one-time pad file. See the `otp`
+/// module.
+pub const FLAG_ONE_TIME_PAD: u8 = 0x10;
+
+/// The file is a sub-share of a share that was split again. See the
+/// `resplit` module.
+pub const FLAG_SUB_SHARE: u8 = 0x20;
+
+/// Length of one level of a sub-share's path.
+const SUB_SHARE_LEVEL_LEN: usize = 20;
+
+/// Length of the CRC-32 after each checksummed block.
+pub const CHECKSUM_LEN: usize = 4;
+
+const KNOWN_FLAGS: u8 =
+    FLAG_MAC | FLAG_CHECKSUMS | FLAG_METADATA | FLAG_SEED | FLAG_ONE_TIME_PAD | FLAG_SUB_SHARE;
+
+/// Length of the random split-set ID.
+pub const SPLIT_ID_LEN: usize = 16;
+
+/// How the shares in a split set were produced.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SchemeKind {
+    /// N-1 random pads plus the XOR of the input with all of them.
+    Xor,
+    /// Shamir k-of-n sharing over GF(256).
+    Threshold,
+}
+
+impl SchemeKind {
+    fn to_byte(self) -> u8 {
+        match self {
+            SchemeKind::Xor => 0,
+            SchemeKind::Threshold => 1,
+        }
+    }
+
+    fn from_byte(b: u8) -> Result<Self> {
+        match b {
+            0 => Ok(SchemeKind::Xor),
+            1 => Ok(SchemeKind::Threshold),
+            _ => bail!("unknown sharing scheme {}", b),
+        }
+    }
+
+    /// File extension prefix used for shares of this scheme.
+    pub fn extension_prefix(self) -> &'static str {
+        match self {
+            SchemeKind::Xor => "xor",
+            SchemeKind::Threshold => "sss",
+        }
+    }
+}
+
+/// One re-split on the way from a share down to one of its sub-shares.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SubShare {
+    /// This sub-share's index, 1-based.
+    pub index: u16,
+    /// How many sub-shares the share above was split into.
+    pub count: u16,
+    /// Random ID common to the sub-shares of one re-split.
+    pub split_id: [u8; SPLIT_ID_LEN],
+}
+
+/// The parsed header of one share.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ShareHeader {
+    pub scheme: SchemeKind,
+    pub index: u16,
+    pub count: u16,
+    pub threshold: u16,
+    pub split_id: [u8; SPLIT_ID_LEN],
+    /// This share's share of the one-time MAC key, if the split has a MAC.
+    pub mac_key_share: Option<[u8; mac::KEY_LEN]>,
+    /// Payload bytes per checksummed block, if the share has block checksums.
+    pub block_len: Option<u32>,
+    /// Whether the payload starts with a metadata block.
+    pub metadata: bool,
+    /// The seed that regenerates this share's payload, for the seeded first
+    /// share of an XOR split.
+    pub seed: Option<[u8; seed::SEED_LEN]>,
+    /// Where the split's range starts in the one-time pad file that stands
+    /// in for share 1, if it drew that share from one.
+    pub one_time_pad: Option<u64>,
+    /// For a sub-share, the re-splits from the share down to this file,
+    /// outermost first; empty for a share as split.
+    pub sub_share: Vec<SubShare>,
+}
+
+impl ShareHeader {
+    /// The split-set ID as lowercase hex, for messages.
+    pub fn split_id_hex(&self) -> String {
+        crate::to_hex(&self.split_id)
+    }
+
+    fn flags(&self) -> u8 {
+        let mut flags = 0;
+        if self.mac_key_share.is_some() {
+            flags |= FLAG_MAC;
+        }
+        if self.block_len.is_some() {
+            flags |= FLAG_CHECKSUMS;
+        }
+        if self.metadata {
+            flags |= FLAG_METADATA;
+        }
+        if self.seed.is_some() {
+            flags |= FLAG_SEED;
+        }
+        if self.one_time_pad.is_some() {
+            flags |= FLAG_ONE_TIME_PAD;
+        }
+        if !self.sub_share.is_empty() {
+            flags |= FLAG_SUB_SHARE;
+        }
+        flags
+    }
+
+    /// Length of the trailer that follows this share's payload.
+    pub fn trailer_len(&self) -> u64 {
+        if self.mac_key_share.is_some() {
+            mac::TAG_LEN as u64
+        } else {
+            0
+        }
+    }
+
+    /// Serialize the header.
+    pub fn write_to(&self, writer: &mut impl Write) -> io::Result<()> {
+        let mut buf = Vec::with_capacity(
+            FIXED_LEN
+                + mac::KEY_LEN
+                + 4
+                + seed::SEED_LEN
+                + 8
+                + 1
+                + self.sub_share.len() * SUB_SHARE_LEVEL_LEN,
+        );
+        buf.extend_from_slice(&MAGIC);
+        buf.extend_from_slice(&VERSION.to_le_bytes());
+        buf.extend_from_slice(&[0, 0]); // header length, filled in below
+        buf.push(self.scheme.to_byte());
+        buf.push(self.flags());
+        buf.extend_from_slice(&self.index.to_le_bytes());
+        buf.extend_from_slice(&self.count.to_le_bytes());
+        buf.extend_from_slice(&self.threshold.to_le_bytes());
+        buf.extend_from_slice(&self.split_id);
+        if let Some(key_share) = &self.mac_key_share {
+            buf.extend_from_slice(key_share);
+        }
+        if let Some(block_len) = self.block_len {
+            buf.extend_from_slice(&block_len.to_le_bytes());
+        }
+        if let Some(seed) = &self.seed {
+            buf.extend_from_slice(seed);
+        }
+        if let Some(offset) = self.one_time_pad {
+            buf.extend_from_slice(&offset.to_le_bytes());
+        }
+        if !self.sub_share.is_empty() {
+            buf.push(self.sub_share.len() as u8);
+            for level in &self.sub_share {
+                buf.extend_from_slice(&level.index.to_le_bytes());
+                buf.extend_from_slice(&level.count.to_le_bytes());
+                buf.extend_from_slice(&level.split_id);
+            }
+        }
+
+        let header_len = buf.len() as u16;
+        buf[10..12].copy_from_slice(&header_len.to_le_bytes());
+        writer.write_all(&buf)
+    }
+
+    /// Parse a header from the start of a share.
+    ///
+    /// Returns the header and its length on disk, or `None` if the share does
+    /// not start with the magic and so is a legacy raw share. In that case the
+    /// reader has been advanced and must be rewound by the caller.
+    pub fn read_from(reader: &mut impl Read) -> Result<Option<(Self, u64)>> {
+        let mut prefix = [0u8; 12];
+        let n = crate::read_exact_or_eof(reader, &mut prefix)?;
+        if n < MAGIC.len() || prefix[..8] != MAGIC {
+            return Ok(None);
+        }
+        if n < prefix.len() {
+            bail!("share header is truncated");
+        }
+
+        let version = u16::from_le_bytes([prefix[8], prefix[9]]);
+        if version == 0 || version > VERSION {
+            bail!(
+                "unsupported share format version {} (this build reads up to {})",
+                version,
+                VERSION
+            );
+        }
+
+        let header_len = u16::from_le_bytes([prefix[10], prefix[11]]) as usize;
+        if header_len < FIXED_LEN {
+            bail!("share header length {} is too short", header_len);
+        }
+
+        let mut rest = vec![0u8; header_len - prefix.len()];
+        reader
+            .read_exact(&mut rest)
+            .context("share header is truncated")?;
+
+        let scheme = SchemeKind::from_byte(rest[0])?;
+        let flags = rest[1];
+        if flags & !KNOWN_FLAGS != 0 {
+            bail!(
+                "share uses features this build does not understand (flags {:#04x})",
+                flags
+            );
+        }
+        let index = u16::from_le_bytes([rest[2], rest[3]]);
+        let count = u16::from_le_bytes([rest[4], rest[5]]);
+        let threshold = u16::from_le_bytes([rest[6], rest[7]]);
+        let mut split_id = [0u8; SPLIT_ID_LEN];
+        split_id.copy_from_slice(&rest[8..8 + SPLIT_ID_LEN]);
+
+        if index == 0 || index > count {
+            bail!("share index {} is outside 1..={}", index, count);
+        }
+        if threshold < 2 || threshold > count {
+            bail!("threshold {} is invalid for {} shares", threshold, count);
+        }
+        if scheme == SchemeKind::Xor && threshold != count {
+            bail!("XOR share has threshold {} but {} shares", threshold, count);
+        }
+        if scheme == SchemeKind::Threshold && count as usize > shamir::MAX_SHARES {
+            bail!(
+                "threshold share count {} is above the maximum of {}",
+                count,
+                shamir::MAX_SHARES
+            );
+        }
+
+        let mut optional = &rest[FIXED_LEN - prefix.len()..];
+        let mac_key_share = if flags & FLAG_MAC != 0 {
+            Some(take_field::<{ mac::KEY_LEN }>(&mut optional)?)
+        } else {
+            None
+        };
+        let block_len = if flags & FLAG_CHECKSUMS != 0 {
+            let block_len = u32::from_le_bytes(take_field(&mut optional)?);
+            if block_len == 0 {
+                bail!("checksum block length must be non-zero");
+            }
+            Some(block_len)
+        } else {
+            None
+        };
+        let seed = if flags & FLAG_SEED != 0 {
+            if scheme != SchemeKind::Xor || index != 1 || block_len.is_some() {
+                bail!("only the first share of an XOR split can be seeded");
+            }
+            Some(take_field::<{ seed::SEED_LEN }>(&mut optional)?)
+        } else {
+            None
+        };
+        let one_time_pad = if flags & FLAG_ONE_TIME_PAD != 0 {
+            if scheme != SchemeKind::Xor || index == 1 || seed.is_some() {
+                bail!("only shares 2 and up of an XOR split can refer to a one-time pad");
+            }
+            Some(u64::from_le_bytes(take_field(&mut optional)?))
+        } else {
+            None
+        };
+        let mut sub_share = Vec::new();
+        if flags & FLAG_SUB_SHARE != 0 {
+            if seed.is_some() {
+                bail!("a seeded share cannot be split into sub-shares");
+            }
+            let [depth] = take_field(&mut optional)?;
+            if depth == 0 {
+                bail!("sub-share path is empty");
+            }
+            for _ in 0..depth {
+                let level: [u8; SUB_SHARE_LEVEL_LEN] = take_field(&mut optional)?;
+                let index = u16::from_le_bytes([level[0], level[1]]);
+                let count = u16::from_le_bytes([level[2], level[3]]);
+                if count < 2 || index == 0 || index > count {
+                    bail!(
+                        "sub-share index {} is invalid for {} sub-shares",
+                        index,
+                        count
+                    );
+                }
+                let mut split_id = [0u8; SPLIT_ID_LEN];
+                split_id.copy_from_slice(&level[4..]);
+                sub_share.push(SubShare {
+                    index,
+                    count,
+                    split_id,
+                });
+            }
+        }
+
+        let header = ShareHeader {
+            scheme,
+            index,
+            count,
+            threshold,
+            split_id,
+            mac_key_share,
+            block_len,
+            metadata: flags & FLAG_METADATA != 0,
+            seed,
+            one_time_pad,
+            sub_share,
+        };
+        Ok(Some((header, header_len as u64)))
+    }
+}
+
+/// Split a fixed-size optional field off the front of `fields`.
+fn take_field<const N: usize>(fields: &mut &[u8]) -> Result<[u8; N]> {
+    if fields.len() < N {
+        bail!("share header is shorter than its flags require");
+    }
+    let (field, rest) = fields.split_at(N);
+    *fields = rest;
+    Ok(field.try_into().expect("field length checked above"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn encode(header: &ShareHeader) -> Vec<u8> {
+        let mut buf = Vec::new();
+        header.write_to(&mut buf).unwrap();
+        buf
+    }
+
+    fn decode(bytes: &[u8]) -> Result<Option<(ShareHeader, u64)>> {
+        ShareHeader::read_from(&mut &bytes[..])
+    }
+
+    fn xor_header() -> ShareHeader {
+        ShareHeader {
+            scheme: SchemeKind::Xor,
+            index: 1,
+            count: 3,
+            threshold: 3,
+            split_id: [0x42; SPLIT_ID_LEN],
+            mac_key_share: None,
+            block_len: None,
+            metadata: false,
+            seed: None,
+            one_time_pad: None,
+            sub_share: Vec::new(),
+        }
+    }
+
+    fn threshold_header(index: u16, count: u16) -> ShareHeader {
+        ShareHeader {
+            scheme: SchemeKind::Threshold,
+            index,
+            count,
+            threshold: 2,
+            ..xor_header()
+        }
+    }
+
+    /// The header with the optional fields of `flags` filled in.
+    fn with_flags(flags: u8) -> ShareHeader {
+        let mut header = xor_header();
+        if flags & FLAG_MAC != 0 {
+            header.mac_key_share = Some([0x11; mac::KEY_LEN]);
+        }
+        if flags & FLAG_CHECKSUMS != 0 {
+            header.block_len = Some(65536);
+        }
+        header.metadata = flags & FLAG_METADATA != 0;
+        if flags & FLAG_SEED != 0 {
+            header.seed = Some([0x22; seed::SEED_LEN]);
+        }
+        if flags & FLAG_ONE_TIME_PAD != 0 {
+            header.index = 2;
+            header.one_time_pad = Some(0x0102_0304_0506);
+        }
+        if flags & FLAG_SUB_SHARE != 0 {
+            header.sub_share = vec![
+                SubShare {
+                    index: 1,
+                    count: 2,
+                    split_id: [0x33; SPLIT_ID_LEN],
+                },
+                SubShare {
+                    index: 3,
+                    count: 3,
+                    split_id: [0x44; SPLIT_ID_LEN],
+                },
+            ];
+        }
+        header
+    }
+
+    /// Encode `header`, let `patch` change the bytes, and decode the result.
+    fn patched(header: &ShareHeader, patch: impl FnOnce(&mut Vec<u8>)) -> Result<ShareHeader> {
+        let mut bytes = encode(header);
+        patch(&mut bytes);
+        Ok(decode(&bytes)?.expect("header has the magic").0)
+    }
+
+    fn assert_rejected(result: Result<ShareHeader>, message: &str) {
+        let err = result.expect_err(message);
+        assert!(
+            format!("{:#}", err).contains(message),
+            "expected {:?}, got {:#}",
+            message,
+            err
+        );
+    }
+
+    #[test]
+    fn every_flag_combination_round_trips() {
+        for flags in 0..=KNOWN_FLAGS {
+            let header = with_flags(flags);
+            let bytes = encode(&header);
+            let seed_conflict = flags & FLAG_SEED != 0
+                && flags & (FLAG_CHECKSUMS | FLAG_ONE_TIME_PAD | FLAG_SUB_SHARE) != 0;
+            match decode(&bytes) {
+                Ok(Some((decoded, len))) => {
+                    assert!(!seed_conflict, "flags {:#04x} should be rejected", flags);
+                    assert_eq!(decoded, header, "flags {:#04x}", flags);
+                    assert_eq!(len, bytes.len() as u64);
+                    assert_eq!(bytes[13], flags);
+                }
+                Ok(None) => panic!("flags {:#04x}: magic not found", flags),
+                Err(e) => assert!(seed_conflict, "flags {:#04x}: {:#}", flags, e),
+            }
+        }
+        let header = threshold_header(7, 9);
+        assert_eq!(decode(&encode(&header)).unwrap().unwrap().0, header);
+    }
+
+    #[test]
+    fn later_fields_are_skipped() {
+        let header = with_flags(FLAG_MAC);
+        let mut bytes = encode(&header);
+        let len = bytes.len() as u16 + 5;
+        bytes[10..12].copy_from_slice(&len.to_le_bytes());
+        bytes.extend_from_slice(&[0xee; 5]);
+        bytes.extend_from_slice(b"payload");
+        let (decoded, header_len) = decode(&bytes).unwrap().unwrap();
+        assert_eq!(decoded, header);
+        assert_eq!(header_len, len as u64);
+    }
+
+    #[test]
+    fn files_without_the_magic_are_legacy_shares() {
+        assert!(decode(b"").unwrap().is_none());
+        assert!(decode(b"SPLINCH").unwrap().is_none());
+        assert!(
+            decode(b"splinch\0 and some raw share bytes")
+                .unwrap()
+                .is_none()
+        );
+    }
+
+    #[test]
+    fn malformed_prefixes_are_rejected() {
+        let bytes = encode(&xor_header());
+        assert!(decode(&bytes[..10]).is_err());
+        assert!(decode(&bytes[..FIXED_LEN - 1]).is_err());
+        let header = xor_header();
+        assert_rejected(
+            patched(&header, |b| b[8..10].copy_from_slice(&0u16.to_le_bytes())),
+            "unsupported share format version 0",
+        );
+        assert_rejected(
+            patched(&header, |b| b[8..10].copy_from_slice(&2u16.to_le_bytes())),
+            "unsupported share format version 2",
+        );
+        assert_rejected(
+            patched(&header, |b| b[10..12].copy_from_slice(&20u16.to_le_bytes())),
+            "is too short",
+        );
+        assert_rejected(
+            patched(&header, |b| {
+                b[10..12].copy_from_slice(&100u16.to_le_bytes())
+            }),
+            "truncated",
+        );
+    }
+
+    #[test]
+    fn bad_fixed_fields_are_rejected() {
+        let header = xor_header();
+        assert_rejected(patched(&header, |b| b[12] = 2), "unknown sharing scheme 2");
+        assert_rejected(patched(&header, |b| b[13] = 0x40), "flags 0x40");
+        assert_rejected(patched(&header, |b| b[13] = 0x80), "flags 0x80");
+        assert_rejected(
+            patched(&header, |b| b[14..16].copy_from_slice(&0u16.to_le_bytes())),
+            "share index 0 is outside 1..=3",
+        );
+        assert_rejected(
+            patched(&header, |b| b[14..16].copy_from_slice(&4u16.to_le_bytes())),
+            "share index 4 is outside 1..=3",
+        );
+        assert_rejected(
+            patched(&threshold_header(1, 3), |b| {
+                b[18..20].copy_from_slice(&1u16.to_le_bytes())
+            }),
+            "threshold 1 is invalid for 3 shares",
+        );
+        assert_rejected(
+            patched(&threshold_header(1, 3), |b| {
+                b[18..20].copy_from_slice(&4u16.to_le_bytes())
+            }),
+            "threshold 4 is invalid for 3 shares",
+        );
+        assert_rejected(
+            patched(&header, |b| b[18..20].copy_from_slice(&2u16.to_le_bytes())),
+            "XOR share has threshold 2 but 3 shares",
+        );
+    }
+
+    #[test]
+    fn bad_optional_fields_are_rejected() {
+        for flag in [
+            FLAG_MAC,
+            FLAG_CHECKSUMS,
+            FLAG_SEED,
+            FLAG_ONE_TIME_PAD,
+            FLAG_SUB_SHARE,
+        ] {
+            // The flag is set but its field is missing.
+            let index = if flag == FLAG_ONE_TIME_PAD { 2 } else { 1 };
+            assert_rejected(
+                patched(
+                    &ShareHeader {
+                        index,
+                        ..xor_header()
+                    },
+                    |b| b[13] = flag,
+                ),
+                "shorter than its flags require",
+            );
+        }
+        assert_rejected(
+            patched(&with_flags(FLAG_CHECKSUMS), |b| {
+                b[FIXED_LEN..FIXED_LEN + 4].copy_from_slice(&0u32.to_le_bytes())
+            }),
+            "checksum block length must be non-zero",
+        );
+
+        let seeded = with_flags(FLAG_SEED);
+        assert_rejected(
+            patched(&seeded, |b| b[14..16].copy_from_slice(&2u16.to_le_bytes())),
+            "only the first share of an XOR split can be seeded",
+        );
+        assert_rejected(
+            patched(
+                &ShareHeader {
+                    seed: Some([0x22; seed::SEED_LEN]),
+                    ..threshold_header(1, 3)
+                },
+                |_| {},
+            ),
+            "only the first share of an XOR split can be seeded",
+        );
+
+        let one_time_pad = with_flags(FLAG_ONE_TIME_PAD);
+        assert_rejected(
+            patched(&one_time_pad, |b| {
+                b[14..16].copy_from_slice(&1u16.to_le_bytes())
+            }),
+            "only shares 2 and up of an XOR split can refer to a one-time pad",
+        );
+        assert_rejected(
+            patched(
+                &ShareHeader {
+                    one_time_pad: Some(0),
+                    ..threshold_header(2, 3)
+                },
+                |_| {},
+            ),
+            "only shares 2 and up of an XOR split can refer to a one-time pad",
+        );
+
+        let sub_share = with_flags(FLAG_SUB_SHARE);
+        assert_rejected(
+            patched(&sub_share, |b| b[FIXED_LEN] = 0),
+            "sub-share path is empty",
+        );
+        assert_rejected(
+            patched(&sub_share, |b| b[FIXED_LEN] = 3),
+            "shorter than its flags require",
+        );
+        assert_rejected(
+            patched(&sub_share, |b| {
+                b[FIXED_LEN + 1..FIXED_LEN + 3].copy_from_slice(&0u16.to_le_bytes())
+            }),
+            "sub-share index 0 is invalid for 2 sub-shares",
+        );
+        assert_rejected(
+            patched(&sub_share, |b| {
+                b[FIXED_LEN + 3..FIXED_LEN + 5].copy_from_slice(&1u16.to_le_bytes())
+            }),
+            "sub-share index 1 is invalid for 1 sub-shares",
+        );
+        assert_rejected(
+            patched(&with_flags(FLAG_SEED | FLAG_SUB_SHARE), |_| {}),
+            "a seeded share cannot be split into sub-shares",
+        );
+    }
+
+    #[test]
+    fn threshold_shares_beyond_gf256_are_rejected() {
+        assert!(decode(&encode(&threshold_header(255, 255))).is_ok());
+        let err = decode(&encode(&threshold_header(256, 256))).unwrap_err();
+        assert!(err.to_string().contains("above the maximum"), "{}", err);
+        assert!(decode(&encode(&threshold_header(1, 300))).is_err());
+    }
+}
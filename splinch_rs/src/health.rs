@@ -0,0 +1,138 @@
+//! Continuous health tests on random bytes, from NIST SP 800-90B section 4.4.
+//!
+//! Every byte a split, `refresh`, `resplit` or `pad generate` draws from its
+//! [`RandomSource`] goes through two tests before it is used, so that a
+//! stuck or badly biased source stops the split instead of quietly
+//! producing weak pads:
+//!
+//! | test                | fails when                                             |
+//! |---------------------|--------------------------------------------------------|
+//! | repetition count    | a byte value repeats 11 times in a row                 |
+//! | adaptive proportion | a window's first byte recurs 78 times in its 512 bytes |
+//!
+//! The cutoffs assume a conservative [`MIN_ENTROPY`] bits of min-entropy per
+//! byte and a false positive rate of 2^-40 per test, so a healthy source
+//! practically never trips them. They catch gross failures only; passing
+//! them does not prove the bytes are random.
+//!
+//! [`RandomSource`]: crate::RandomSource
+
+use anyhow::{Result, bail};
+
+use crate::RandomSource;
+
+/// Min-entropy per byte that the cutoffs assume.
+pub const MIN_ENTROPY: u32 = 4;
+
+/// Identical bytes in a row that fail the repetition count test:
+/// 1 + ceil(40 / [`MIN_ENTROPY`]).
+pub const REPETITION_CUTOFF: u32 = 11;
+
+/// Bytes in each window of the adaptive proportion test.
+pub const PROPORTION_WINDOW: u32 = 512;
+
+/// Occurrences of a window's first byte that fail the adaptive proportion
+/// test: 1 + CRITBINOM([`PROPORTION_WINDOW`], 2^-[`MIN_ENTROPY`], 1 - 2^-40).
+pub const PROPORTION_CUTOFF: u32 = 78;
+
+/// What the health tests saw, for reports.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct HealthStats {
+    /// Bytes tested.
+    pub samples: u64,
+    /// Longest run of one byte value.
+    pub longest_run: u32,
+    /// Adaptive proportion windows completed.
+    pub windows: u64,
+    /// Most occurrences of a window's first byte in any window.
+    pub max_proportion: u32,
+}
+
+/// The state of both tests over one stream of bytes.
+#[derive(Debug, Clone, Default)]
+struct HealthTests {
+    stats: HealthStats,
+    last: u8,
+    run: u32,
+    window_first: u8,
+    window_pos: u32,
+    window_count: u32,
+}
+
+impl HealthTests {
+    fn check(&mut self, buf: &[u8]) -> Result<()> {
+        for &byte in buf {
+            // Repetition count test.
+            if self.stats.samples > 0 && byte == self.last {
+                self.run += 1;
+            } else {
+                self.last = byte;
+                self.run = 1;
+            }
+            self.stats.longest_run = self.stats.longest_run.max(self.run);
+            if self.run >= REPETITION_CUTOFF {
+                bail!(
+                    "the random source failed the repetition count health test: \
+                     byte {:#04x} repeated {} times in a row after {} bytes",
+                    byte,
+                    self.run,
+                    self.stats.samples
+                );
+            }
+
+            // Adaptive proportion test.
+            if self.window_pos == 0 {
+                self.window_first = byte;
+                self.window_count = 1;
+            } else if byte == self.window_first {
+                self.window_count += 1;
+            }
+            self.stats.max_proportion = self.stats.max_proportion.max(self.window_count);
+            if self.window_count >= PROPORTION_CUTOFF {
+                bail!(
+                    "the random source failed the adaptive proportion health test: \
+                     byte {:#04x} appeared {} times in a window of {} after {} bytes",
+                    self.window_first,
+                    self.window_count,
+                    PROPORTION_WINDOW,
+                    self.stats.samples
+                );
+            }
+            self.window_pos += 1;
+            if self.window_pos == PROPORTION_WINDOW {
+                self.window_pos = 0;
+                self.stats.windows += 1;
+            }
+
+            self.stats.samples += 1;
+        }
+        Ok(())
+    }
+}
+
+/// A [`RandomSource`] whose every byte passes the health tests.
+pub struct HealthTested<'a> {
+    source: &'a mut dyn RandomSource,
+    tests: HealthTests,
+}
+
+impl<'a> HealthTested<'a> {
+    pub fn new(source: &'a mut dyn RandomSource) -> Self {
+        Self {
+            source,
+            tests: HealthTests::default(),
+        }
+    }
+
+    /// What the tests have seen so far.
+    pub fn stats(&self) -> HealthStats {
+        self.tests.stats
+    }
+}
+
+impl RandomSource for HealthTested<'_> {
+    fn fill(&mut self, buf: &mut [u8]) -> Result<()> {
+        self.source.fill(buf)?;
+        self.tests.check(buf)
+    }
+}
@@ -0,0 +1,262 @@
+//! Hybrid mode: the content is encrypted once and only the key is split.
+//!
+//! The content is encrypted with XChaCha20-Poly1305 under a fresh random key,
+//! in the STREAM construction so that files of any size are encrypted and
+//! decrypted a chunk at a time, and every chunk is authenticated. The key
+//! and the nonce prefix are then split like any other input, so the shares
+//! are small and the ciphertext, written once, can travel over any channel.
+//! The ciphertext file is laid out as follows, integers little-endian:
+//!
+//! | size | field                                                  |
+//! |------|--------------------------------------------------------|
+//! | 8    | magic `SPLINCHE`                                       |
+//! | 1    | format version (1)                                     |
+//! | 4    | plaintext bytes per chunk                              |
+//! | ...  | chunks, each sealed with a 16-byte tag; the last one   |
+//! |      | may be shorter and is sealed as the last               |
+//!
+//! Security rests on XChaCha20-Poly1305, so it is computational rather than
+//! information-theoretic: the shares of the key are perfectly hiding, but the
+//! ciphertext is only as strong as the cipher.
+
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result, bail};
+use chacha20poly1305::aead::stream::{DecryptorBE32, EncryptorBE32};
+use chacha20poly1305::{KeyInit, XChaCha20Poly1305};
+use sha2::{Digest, Sha256};
+
+use crate::RandomSource;
+
+pub const MAGIC: [u8; 8] = *b"SPLINCHE";
+
+const VERSION: u8 = 1;
+
+const KEY_LEN: usize = 32;
+
+/// Length of the STREAM nonce prefix; the rest of the 24-byte nonce is the
+/// chunk counter and the last-chunk flag.
+const NONCE_LEN: usize = 19;
+
+/// Length of the key and nonce prefix that are split.
+pub const KEY_MATERIAL_LEN: usize = KEY_LEN + NONCE_LEN;
+
+const TAG_LEN: usize = 16;
+
+const CHUNK_LEN: usize = 64 * 1024;
+
+/// Largest chunk length accepted when reading, as a guard against
+/// allocating whatever a damaged header claims.
+const MAX_CHUNK_LEN: u32 = 16 * 1024 * 1024;
+
+/// A fresh random key and nonce prefix.
+pub fn generate_key(random: &mut dyn RandomSource) -> Result<[u8; KEY_MATERIAL_LEN]> {
+    let mut key = [0u8; KEY_MATERIAL_LEN];
+    random.fill(&mut key)?;
+    Ok(key)
+}
+
+fn cipher(key: &[u8; KEY_MATERIAL_LEN]) -> (XChaCha20Poly1305, &[u8]) {
+    let (key, nonce) = key.split_at(KEY_LEN);
+    let cipher = XChaCha20Poly1305::new_from_slice(key).expect("key length");
+    (cipher, nonce)
+}
+
+/// The ciphertext file written by [`encrypt_file`].
+pub struct Ciphertext {
+    pub path: PathBuf,
+    pub len: u64,
+    pub sha256: Option<[u8; 32]>,
+}
+
+/// Encrypt everything read from `reader` into a new ciphertext file at
+/// `path`, hashing it as it is written if `hash` is set.
+pub fn encrypt_file(
+    reader: &mut impl Read,
+    path: &Path,
+    key: &[u8; KEY_MATERIAL_LEN],
+    hash: bool,
+) -> Result<Ciphertext> {
+    let file =
+        File::create(path).with_context(|| format!("failed to create {}", path.display()))?;
+    let mut out = HashingWriter {
+        writer: BufWriter::new(file),
+        hasher: hash.then(Sha256::new),
+        len: 0,
+    };
+
+    let (cipher, nonce) = cipher(key);
+    let mut encryptor = EncryptorBE32::from_aead(cipher, nonce.into());
+    let write_err = || format!("failed to write to {}", path.display());
+
+    out.write_all(&MAGIC)
+        .and_then(|()| out.write_all(&[VERSION]))
+        .and_then(|()| out.write_all(&(CHUNK_LEN as u32).to_le_bytes()))
+        .with_context(write_err)?;
+
+    // Read one chunk ahead, since the last chunk is sealed differently.
+    let mut chunk = vec![0u8; CHUNK_LEN];
+    let mut next = vec![0u8; CHUNK_LEN];
+    let mut n = crate::read_exact_or_eof(reader, &mut chunk)?;
+    loop {
+        let next_n = crate::read_exact_or_eof(reader, &mut next)?;
+        if next_n == 0 {
+            break;
+        }
+        let sealed = encryptor
+            .encrypt_next(&chunk[..n])
+            .map_err(|_| anyhow::anyhow!("encryption failed"))?;
+        out.write_all(&sealed).with_context(write_err)?;
+        std::mem::swap(&mut chunk, &mut next);
+        n = next_n;
+    }
+    let sealed = encryptor
+        .encrypt_last(&chunk[..n])
+        .map_err(|_| anyhow::anyhow!("encryption failed"))?;
+    out.write_all(&sealed).with_context(write_err)?;
+
+    out.writer.flush().with_context(write_err)?;
+    out.writer
+        .get_ref()
+        .sync_all()
+        .with_context(|| format!("failed to sync {}", path.display()))?;
+
+    Ok(Ciphertext {
+        path: path.to_path_buf(),
+        len: out.len,
+        sha256: out.hasher.map(|hasher| hasher.finalize().into()),
+    })
+}
+
+struct HashingWriter {
+    writer: BufWriter<File>,
+    hasher: Option<Sha256>,
+    len: u64,
+}
+
+impl Write for HashingWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let n = self.writer.write(buf)?;
+        if let Some(hasher) = &mut self.hasher {
+            hasher.update(&buf[..n]);
+        }
+        self.len += n as u64;
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+}
+
+/// Reads the decrypted content of a ciphertext file.
+///
+/// A read fails as soon as a chunk does not authenticate, so nothing from a
+/// damaged or tampered chunk is ever returned, and a ciphertext cut short
+/// fails at its end instead of passing for a complete one.
+pub struct Decryptor {
+    path: PathBuf,
+    reader: BufReader<File>,
+    decryptor: Option<DecryptorBE32<XChaCha20Poly1305>>,
+    chunk_len: usize,
+    index: u64,
+    sealed: Vec<u8>,
+    plain: Vec<u8>,
+    pos: usize,
+}
+
+impl Decryptor {
+    pub fn open(path: &Path, key: &[u8; KEY_MATERIAL_LEN]) -> Result<Self> {
+        let file = File::open(path).with_context(|| {
+            format!(
+                "failed to open the ciphertext {}; it must be next to the first share",
+                path.display()
+            )
+        })?;
+        let mut reader = BufReader::new(file);
+
+        let mut header = [0u8; MAGIC.len() + 5];
+        reader
+            .read_exact(&mut header)
+            .with_context(|| format!("{} is truncated", path.display()))?;
+        if header[..MAGIC.len()] != MAGIC {
+            bail!("{} is not a splinch ciphertext", path.display());
+        }
+        let version = header[MAGIC.len()];
+        if version != VERSION {
+            bail!(
+                "{} has unsupported ciphertext version {} (this build reads {})",
+                path.display(),
+                version,
+                VERSION
+            );
+        }
+        let chunk_len = u32::from_le_bytes(header[MAGIC.len() + 1..].try_into().expect("u32"));
+        if chunk_len == 0 || chunk_len > MAX_CHUNK_LEN {
+            bail!("{} has invalid chunk length {}", path.display(), chunk_len);
+        }
+
+        let (cipher, nonce) = cipher(key);
+        Ok(Self {
+            path: path.to_path_buf(),
+            reader,
+            decryptor: Some(DecryptorBE32::from_aead(cipher, nonce.into())),
+            chunk_len: chunk_len as usize,
+            index: 0,
+            sealed: Vec::new(),
+            plain: Vec::new(),
+            pos: 0,
+        })
+    }
+
+    /// Decrypt the next chunk, which is the last if the file ends after it.
+    fn next_chunk(&mut self) -> io::Result<()> {
+        self.sealed.resize(self.chunk_len + TAG_LEN, 0);
+        let n = crate::read_exact_or_eof(&mut self.reader, &mut self.sealed)
+            .map_err(io::Error::other)?;
+        if n < TAG_LEN {
+            return Err(io::Error::other(format!(
+                "{} is truncated",
+                self.path.display()
+            )));
+        }
+        let last = n < self.sealed.len() || self.reader.fill_buf()?.is_empty();
+
+        let sealed = &self.sealed[..n];
+        let opened = if last {
+            let decryptor = self.decryptor.take().expect("decrypting");
+            decryptor.decrypt_last(sealed)
+        } else {
+            let decryptor = self.decryptor.as_mut().expect("decrypting");
+            decryptor.decrypt_next(sealed)
+        };
+        self.plain = opened.map_err(|_| {
+            io::Error::other(format!(
+                "authentication failed: chunk {} of {} was damaged or tampered with, \
+                 or does not belong to these shares",
+                self.index,
+                self.path.display()
+            ))
+        })?;
+        self.pos = 0;
+        self.index += 1;
+        Ok(())
+    }
+}
+
+impl Read for Decryptor {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.pos == self.plain.len() {
+            if self.decryptor.is_none() {
+                return Ok(0);
+            }
+            self.next_chunk()?;
+        }
+        let n = buf.len().min(self.plain.len() - self.pos);
+        buf[..n].copy_from_slice(&self.plain[self.pos..self.pos + n]);
+        self.pos += n;
+        Ok(n)
+    }
+}
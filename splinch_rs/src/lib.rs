@@ -1,14 +1,103 @@
 use std::collections::BTreeSet;
 use std::fs::{File, OpenOptions};
-use std::io::{BufReader, BufWriter, Read, Seek, SeekFrom, Write};
+use std::io::{self, BufReader, BufWriter, Cursor, Read, Seek, SeekFrom, Write};
+use std::ops::{Range, RangeInclusive};
 use std::path::{Path, PathBuf};
 
 use anyhow::{Context, Result, bail};
-use rand::{RngCore, rng};
+use sha2::{Digest, Sha256};
+
+mod archive;
+mod audit;
+mod compression;
+mod devices;
+mod header;
+mod health;
+mod hybrid;
+mod mac;
+mod manifest;
+mod metadata;
+mod otp;
+mod padbook;
+mod padding;
+mod random;
+mod refresh;
+mod registry;
+mod resplit;
+mod seed;
+mod shamir;
+mod share;
+
+pub use audit::{AUDIT_ALPHA, AuditReport, AuditTest, Verdict, audit_share};
+pub use compression::Compression;
+pub use devices::{SharedDevice, shared_device};
+pub use header::{SchemeKind, ShareHeader};
+pub use health::{
+    HealthStats, MIN_ENTROPY, PROPORTION_CUTOFF, PROPORTION_WINDOW, REPETITION_CUTOFF,
+};
+pub use manifest::ManifestEntry;
+pub use padbook::{PadUsage, destroy_pads, generate_pad, generate_pad_book, pad_usage};
+pub use padding::{Padding, parse_size};
+pub use random::{DeviceRandom, OsRandom, RandomSource, Randomness, SeededRandom};
+pub use refresh::{RefreshOptions, RefreshedShares, refresh_shares};
+pub use registry::PadReuse;
+pub use resplit::{ResplitOptions, ResplitShares, resplit_share};
+pub use shamir::MAX_SHARES;
+
+use metadata::Metadata;
+use share::{ShareInput, ShareOutput, WrittenShare};
 
 const CHUNK_SIZE: usize = 64 * 1024;
 const VERIFY_FULL_THRESHOLD: u64 = 10 * 1024 * 1024;
 
+/// Optional features applied when writing shares.
+#[derive(Debug, Clone, Default)]
+pub struct SplitOptions {
+    /// Append a one-time MAC so that combining can authenticate the output.
+    pub mac: bool,
+    /// Write a `sha256sum`-compatible manifest of the shares to
+    /// [`manifest_path`] for the input, or into every output directory.
+    pub manifest: bool,
+    /// One shell command per share. When set, share `i` is streamed into the
+    /// stdin of `share_commands[i]`, run with `sh -c`, instead of being
+    /// written to a file; the share paths become the names handed to the
+    /// commands.
+    pub share_commands: Vec<String>,
+    /// Explicit path for each share, instead of `<input>.xorN` next to the
+    /// input. Must list exactly one path per share.
+    pub output_paths: Vec<PathBuf>,
+    /// Directories to write the shares to under their default file names:
+    /// either one directory for every share or one per share.
+    pub output_dirs: Vec<PathBuf>,
+    /// Refuse to split when every share would be written to the same device,
+    /// as reported by [`shared_device`]. A one-time pad counts as share 1.
+    pub strict_devices: bool,
+    /// Give the shares random names that say nothing about the input, and
+    /// name the manifest after the split ID. Combining restores the original
+    /// name from the split metadata.
+    pub random_names: bool,
+    /// Pad the split data to hide the input's exact size. Combining trims
+    /// the padding again.
+    pub padding: Padding,
+    /// Compress the content before splitting. Smaller shares, but how well
+    /// the input compresses shows in their size.
+    pub compression: Compression,
+    /// Encrypt the content with XChaCha20-Poly1305 into a single ciphertext
+    /// file and split only the key. See [`SplitShares::ciphertext_path`].
+    pub hybrid: bool,
+    /// Make the first share of an XOR split a 256-bit seed whose ChaCha20
+    /// keystream is the first pad, instead of a pad as long as the input.
+    /// The split is then only computationally secure.
+    pub seeded_pad: bool,
+    /// Take share 1 of an XOR split from this pre-shared one-time pad file,
+    /// at the first offset its ledger has not recorded as used, instead of
+    /// generating and writing it. See [`SplitShares::one_time_pad_range`].
+    pub one_time_pad: Option<PathBuf>,
+    /// Record fingerprints of the split's XOR pads in this registry, and
+    /// report pads it has already seen in [`SplitShares::pad_reuse`].
+    pub pad_registry: Option<PathBuf>,
+}
+
 /// XOR two equal-length byte slices into the output buffer.
 pub fn xor_buffers(a: &[u8], b: &[u8], output: &mut [u8]) {
     assert_eq!(a.len(), b.len(), "input slices must be equal length");
@@ -18,104 +107,1180 @@ pub fn xor_buffers(a: &[u8], b: &[u8], output: &mut [u8]) {
     }
 }
 
-/// Split a file into two XOR-complementary parts.
+/// XOR `other` into `acc` in place.
+fn xor_into(acc: &mut [u8], other: &[u8]) {
+    assert_eq!(acc.len(), other.len(), "input slices must be equal length");
+    for (a, b) in acc.iter_mut().zip(other) {
+        *a ^= b;
+    }
+}
+
+/// How each chunk of the input is turned into share bytes.
+enum Scheme {
+    /// N-1 random pads plus the XOR of the input with all of them.
+    Xor,
+    /// Shamir k-of-n sharing over GF(256).
+    Threshold(shamir::Splitter),
+}
+
+impl Scheme {
+    fn kind(&self) -> SchemeKind {
+        match self {
+            Scheme::Xor => SchemeKind::Xor,
+            Scheme::Threshold(_) => SchemeKind::Threshold,
+        }
+    }
+
+    /// Number of shares needed to rebuild the input, out of `shares`.
+    fn threshold(&self, shares: usize) -> usize {
+        match self {
+            Scheme::Xor => shares,
+            Scheme::Threshold(splitter) => splitter.threshold(),
+        }
+    }
+
+    /// Scratch bytes needed per input byte for random coefficients.
+    fn scratch_per_byte(&self) -> usize {
+        match self {
+            Scheme::Xor => 0,
+            Scheme::Threshold(splitter) => splitter.coefficients(),
+        }
+    }
+
+    /// Fill `outputs[i][..input.len()]` with share `i` of `input`.
+    ///
+    /// For XOR, `first_pad` supplies the first pad in place of bytes from
+    /// `random`.
+    fn encode(
+        &self,
+        input: &[u8],
+        rand_buf: &mut [u8],
+        outputs: &mut [Vec<u8>],
+        mut first_pad: Option<&mut FirstPad>,
+        random: &mut dyn RandomSource,
+    ) -> Result<()> {
+        let len = input.len();
+        match self {
+            Scheme::Xor => {
+                let (last, pads) = outputs.split_last_mut().expect("at least two shares");
+                let xor_chunk = &mut last[..len];
+                xor_chunk.copy_from_slice(input);
+                for (i, pad) in pads.iter_mut().enumerate() {
+                    let rand_chunk = &mut pad[..len];
+                    if i == 0
+                        && let Some(first_pad) = first_pad.as_deref_mut()
+                    {
+                        first_pad.fill(rand_chunk)?;
+                    } else {
+                        random.fill(rand_chunk)?;
+                    }
+                    xor_into(xor_chunk, rand_chunk);
+                }
+            }
+            Scheme::Threshold(splitter) => {
+                let coeffs = &mut rand_buf[..len * splitter.coefficients()];
+                random.fill(coeffs)?;
+                splitter.split(input, coeffs, outputs);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Where the first pad of an XOR split comes from, when it is not random.
+enum FirstPad {
+    /// The keystream of a seed stored in share 1.
+    Seeded(seed::Keystream),
+    /// A range of a one-time pad file; share 1 is not written.
+    OneTimePad(otp::PadReader),
+}
+
+impl FirstPad {
+    fn fill(&mut self, buf: &mut [u8]) -> Result<()> {
+        match self {
+            FirstPad::Seeded(keystream) => keystream.fill(buf),
+            FirstPad::OneTimePad(pad) => pad.fill(buf),
+        }
+    }
+}
+
+/// How chunks read from a set of shares are turned back into the original.
+enum Combiner {
+    Xor,
+    Threshold(shamir::Interpolator),
+}
+
+impl Combiner {
+    /// Add the contribution of share `index` to `output`.
+    ///
+    /// The first share overwrites `output`; later shares are folded in.
+    fn accumulate(&self, index: usize, share: &[u8], output: &mut [u8]) {
+        match self {
+            Combiner::Xor if index == 0 => output.copy_from_slice(share),
+            Combiner::Xor => xor_into(output, share),
+            Combiner::Threshold(interpolator) => interpolator.accumulate(index, share, output),
+        }
+    }
+}
+
+/// The shares produced by a split.
+#[derive(Debug, Clone)]
+pub struct SplitShares {
+    /// Share file paths, or with share commands the names handed to them.
+    pub share_paths: Vec<PathBuf>,
+    pub split_id: [u8; header::SPLIT_ID_LEN],
+    /// Manifests written for the split, if one was asked for.
+    pub manifest_paths: Vec<PathBuf>,
+    /// The ciphertext written by a hybrid split.
+    pub ciphertext_path: Option<PathBuf>,
+    /// The range of the one-time pad that stands in for share 1, which is
+    /// not among [`SplitShares::share_paths`].
+    pub one_time_pad_range: Option<Range<u64>>,
+    /// What the health tests saw of the random bytes the split drew.
+    pub health: HealthStats,
+    /// Pads of this split that the pad registry had already seen. Empty
+    /// unless [`SplitOptions::pad_registry`] is set.
+    pub pad_reuse: Vec<PadReuse>,
+}
+
+impl SplitShares {
+    /// The split-set ID as lowercase hex, for messages.
+    pub fn split_id_hex(&self) -> String {
+        to_hex(&self.split_id)
+    }
+}
+
+/// Split a file into `shares` XOR-complementary parts.
+///
+/// Given `input_path`, produces `<input_path>.xor1` through `<input_path>.xorN`,
+/// unless `options` gives output paths or directories. The first N-1 files
+/// are independent random pads and the last is the XOR of the input with all
+/// of them. Any N-1 files together reveal no information about the original;
+/// XOR-ing all N output files together reconstructs it.
+///
+/// The input's name, mode, mtime and extended attributes are split along
+/// with its content, and restored by [`combine_files`]. If `input_path` is a
+/// directory, the tree is split as a tar archive that is built while it is
+/// read, and combining unpacks it again.
+///
+/// The pads, keys and IDs of the split are drawn from `random`.
+pub fn split_file(
+    input_path: &Path,
+    shares: usize,
+    options: &SplitOptions,
+    random: &mut dyn RandomSource,
+) -> Result<SplitShares> {
+    let (mut reader, base_path, metadata) = open_input(input_path)?;
+    split_xor(&mut reader, &base_path, shares, options, &metadata, random)
+}
+
+/// Split everything read from `reader` into `shares` XOR-complementary parts.
+///
+/// Works like [`split_file`], with the shares named after `base_path`
+/// (`<base_path>.xor1` and so on). The plaintext is only ever held in memory
+/// one chunk at a time, so `reader` can be a pipe such as stdin. The only
+/// metadata recorded is the file name of `base_path`.
+pub fn split_reader(
+    reader: &mut impl Read,
+    base_path: &Path,
+    shares: usize,
+    options: &SplitOptions,
+    random: &mut dyn RandomSource,
+) -> Result<SplitShares> {
+    split_xor(
+        reader,
+        base_path,
+        shares,
+        options,
+        &reader_metadata(base_path),
+        random,
+    )
+}
+
+fn split_xor(
+    reader: &mut impl Read,
+    base_path: &Path,
+    shares: usize,
+    options: &SplitOptions,
+    metadata: &Metadata,
+    random: &mut dyn RandomSource,
+) -> Result<SplitShares> {
+    if shares < 2 {
+        bail!("share count must be at least 2, got {}", shares);
+    }
+    if shares > u16::MAX as usize {
+        bail!("share count must be at most {}, got {}", u16::MAX, shares);
+    }
+
+    // With a one-time pad, share 1 is the pad and is not written.
+    let first_index = if options.one_time_pad.is_some() { 2 } else { 1 };
+    let share_paths = share_paths(base_path, "xor", first_index..=shares, options, random)?;
+
+    split_into(
+        reader,
+        base_path,
+        &share_paths,
+        &Scheme::Xor,
+        options,
+        metadata,
+        random,
+    )
+}
+
+/// Split a file into `shares` parts, any `threshold` of which rebuild it.
+///
+/// Given `input_path`, produces `<input_path>.sss1` through `<input_path>.sssN`,
+/// unless `options` gives output paths or directories, using Shamir secret
+/// sharing over GF(256). Share `i` holds the sharing polynomials evaluated at
+/// x = i, recorded as the share index in its header. Fewer than `threshold`
+/// files reveal no information about the original. Directories are split
+/// as archives, as with [`split_file`], and the coefficients and IDs are
+/// drawn from `random`.
+pub fn split_file_threshold(
+    input_path: &Path,
+    threshold: usize,
+    shares: usize,
+    options: &SplitOptions,
+    random: &mut dyn RandomSource,
+) -> Result<SplitShares> {
+    let (mut reader, base_path, metadata) = open_input(input_path)?;
+    split_threshold(
+        &mut reader,
+        &base_path,
+        threshold,
+        shares,
+        options,
+        &metadata,
+        random,
+    )
+}
+
+/// Split everything read from `reader` into `shares` parts, any `threshold`
+/// of which rebuild it.
+///
+/// Works like [`split_file_threshold`], with the shares named after
+/// `base_path` (`<base_path>.sss1` and so on).
+pub fn split_reader_threshold(
+    reader: &mut impl Read,
+    base_path: &Path,
+    threshold: usize,
+    shares: usize,
+    options: &SplitOptions,
+    random: &mut dyn RandomSource,
+) -> Result<SplitShares> {
+    split_threshold(
+        reader,
+        base_path,
+        threshold,
+        shares,
+        options,
+        &reader_metadata(base_path),
+        random,
+    )
+}
+
+fn split_threshold(
+    reader: &mut impl Read,
+    base_path: &Path,
+    threshold: usize,
+    shares: usize,
+    options: &SplitOptions,
+    metadata: &Metadata,
+    random: &mut dyn RandomSource,
+) -> Result<SplitShares> {
+    let splitter = shamir::Splitter::new(threshold, shares)?;
+
+    let share_paths = share_paths(base_path, "sss", 1..=shares, options, random)?;
+
+    split_into(
+        reader,
+        base_path,
+        &share_paths,
+        &Scheme::Threshold(splitter),
+        options,
+        metadata,
+        random,
+    )
+}
+
+/// Where the shares with the given indexes go, honouring the output path
+/// and output directory options.
+fn share_paths(
+    base_path: &Path,
+    prefix: &str,
+    indexes: RangeInclusive<usize>,
+    options: &SplitOptions,
+    random: &mut dyn RandomSource,
+) -> Result<Vec<PathBuf>> {
+    let shares = indexes.clone().count();
+    if !options.output_paths.is_empty() {
+        if !options.output_dirs.is_empty() {
+            bail!("give either output paths or output directories for the shares, not both");
+        }
+        if options.output_paths.len() != shares {
+            bail!(
+                "got {} output paths for {} shares; give one path per share",
+                options.output_paths.len(),
+                shares
+            );
+        }
+        for (i, path) in options.output_paths.iter().enumerate() {
+            if options.output_paths[..i].contains(path) {
+                bail!("{} was given for more than one share", path.display());
+            }
+        }
+        if options.random_names {
+            bail!("give either output paths or random names for the shares, not both");
+        }
+        return check_not_input(base_path, options.output_paths.clone());
+    }
+
+    let mut default_paths = Vec::with_capacity(shares);
+    for i in indexes {
+        default_paths.push(if options.random_names {
+            let mut name = [0u8; 16];
+            random.fill(&mut name)?;
+            base_path.with_file_name(to_hex(&name))
+        } else {
+            append_extension(base_path, &format!("{}{}", prefix, i))
+        });
+    }
+    let dirs = &options.output_dirs;
+    if dirs.is_empty() {
+        return check_not_input(base_path, default_paths);
+    }
+    if dirs.len() != 1 && dirs.len() != shares {
+        bail!(
+            "got {} output directories for {} shares; give one for all shares or one per share",
+            dirs.len(),
+            shares
+        );
+    }
+    for dir in dirs {
+        if !dir.is_dir() {
+            bail!("output directory {} does not exist", dir.display());
+        }
+    }
+
+    let paths = default_paths
+        .into_iter()
+        .enumerate()
+        .map(|(i, path)| {
+            let dir = &dirs[i.min(dirs.len() - 1)];
+            dir.join(path.file_name().expect("share paths have a file name"))
+        })
+        .collect();
+    check_not_input(base_path, paths)
+}
+
+/// Fail if any share path is the input itself, so that writing the shares
+/// cannot overwrite what is being split.
+fn check_not_input(input_path: &Path, paths: Vec<PathBuf>) -> Result<Vec<PathBuf>> {
+    let Ok(input) = input_path.canonicalize() else {
+        return Ok(paths);
+    };
+    for path in &paths {
+        if path.canonicalize().is_ok_and(|path| path == input) {
+            bail!(
+                "share path {} is the input {}",
+                path.display(),
+                input_path.display()
+            );
+        }
+    }
+    Ok(paths)
+}
+
+/// Open the input to split, archiving it if it is a directory.
 ///
-/// Given `input_path`, produces `<input_path>.xor1` and `<input_path>.xor2`.
-/// Neither file alone reveals any information about the original.
-/// XOR-ing the two output files together reconstructs the original.
-pub fn split_file(input_path: &Path) -> Result<(PathBuf, PathBuf)> {
-    let xor1_path = append_extension(input_path, "xor1");
-    let xor2_path = append_extension(input_path, "xor2");
+/// Returns the reader, the base path to name the shares after, and the
+/// metadata to split along with the content.
+fn open_input(input_path: &Path) -> Result<(Box<dyn Read>, PathBuf, Metadata)> {
+    if input_path.is_dir() {
+        // Drop any trailing slash, so that `dir/` gives `dir.xor1`.
+        let base_path: PathBuf = input_path.components().collect();
+        if base_path.file_name().is_none() {
+            bail!(
+                "cannot name shares after {}; give output paths for them",
+                input_path.display()
+            );
+        }
+        let metadata = Metadata::from_path(input_path, true)?;
+        let reader = archive::archive_dir(input_path)?;
+        return Ok((Box::new(reader), base_path, metadata));
+    }
 
     let input_file = File::open(input_path)
         .with_context(|| format!("failed to open input file: {}", input_path.display()))?;
-    let mut reader = BufReader::new(input_file);
+    Ok((
+        Box::new(BufReader::new(input_file)),
+        input_path.to_path_buf(),
+        Metadata::from_path(input_path, false)?,
+    ))
+}
+
+/// The metadata recorded for a split read from a stream: just a name.
+fn reader_metadata(base_path: &Path) -> Metadata {
+    Metadata {
+        name: base_path
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned()),
+        ..Metadata::default()
+    }
+}
+
+fn split_into(
+    reader: &mut impl Read,
+    base_path: &Path,
+    share_paths: &[PathBuf],
+    scheme: &Scheme,
+    options: &SplitOptions,
+    metadata: &Metadata,
+    random: &mut dyn RandomSource,
+) -> Result<SplitShares> {
+    // Share 1 of a one-time pad split is the pad, which is not written.
+    let unwritten = usize::from(options.one_time_pad.is_some());
+    let shares = share_paths.len() + unwritten;
+    let threshold = scheme.threshold(shares);
+    if !options.share_commands.is_empty() && options.share_commands.len() != share_paths.len() {
+        bail!(
+            "got {} share commands for {} shares; give one command per share",
+            options.share_commands.len(),
+            share_paths.len()
+        );
+    }
+
+    if options.manifest {
+        for (i, path) in share_paths.iter().enumerate() {
+            let name = path.file_name();
+            if share_paths[..i]
+                .iter()
+                .any(|other| other.file_name() == name)
+            {
+                bail!(
+                    "cannot write a manifest: more than one share is named {}",
+                    file_name_string(path)
+                );
+            }
+        }
+    }
+
+    // A one-time pad is the other half of share 2, so it counts as a share.
+    let stored_paths: Vec<PathBuf> = share_paths
+        .iter()
+        .chain(&options.one_time_pad)
+        .cloned()
+        .collect();
+    if options.strict_devices
+        && options.share_commands.is_empty()
+        && let Some(device) = shared_device(&stored_paths)?
+    {
+        bail!(
+            "refusing to split: every share would be written to {}",
+            device
+        );
+    }
+
+    if options.hybrid && options.padding != Padding::None {
+        bail!("padding cannot be used in hybrid mode, where only the key is split");
+    }
+
+    if options.seeded_pad && scheme.kind() != SchemeKind::Xor {
+        bail!("a seeded pad needs an XOR split; threshold shares have no pad to seed");
+    }
+    if options.one_time_pad.is_some() && scheme.kind() != SchemeKind::Xor {
+        bail!("a one-time pad needs an XOR split; threshold shares have no pad to take from it");
+    }
+    if options.seeded_pad && options.one_time_pad.is_some() {
+        bail!("give either a seeded pad or a one-time pad, not both");
+    }
 
-    let xor1_file = File::create(&xor1_path)
-        .with_context(|| format!("failed to create {}", xor1_path.display()))?;
-    let mut writer1 = BufWriter::new(xor1_file);
+    // From here on every random byte passes the health tests, and a failure
+    // aborts the split.
+    let random = &mut health::HealthTested::new(random);
 
-    let xor2_file = File::create(&xor2_path)
-        .with_context(|| format!("failed to create {}", xor2_path.display()))?;
-    let mut writer2 = BufWriter::new(xor2_file);
+    let mut split_id = [0u8; header::SPLIT_ID_LEN];
+    random.fill(&mut split_id)?;
+
+    let manifest_paths = if options.manifest {
+        manifest_paths(base_path, &split_id, options)
+    } else {
+        Vec::new()
+    };
 
     let mut input_buf = vec![0u8; CHUNK_SIZE];
-    let mut rand_buf = vec![0u8; CHUNK_SIZE];
-    let mut xor_buf = vec![0u8; CHUNK_SIZE];
+    let mut rand_buf = vec![0u8; CHUNK_SIZE * scheme.scratch_per_byte()];
+    let mut outputs = vec![vec![0u8; CHUNK_SIZE]; shares];
+
+    // The metadata block goes through the split as the start of the payload,
+    // and any padding as its end. Compression comes before padding, so that
+    // the padding hides the compressed size.
+    let mut reader = options
+        .compression
+        .encoder(reader)
+        .context("failed to start compressing the input")?;
+
+    // In hybrid mode the content is encrypted here, and only the key goes
+    // through the split.
+    let mut ciphertext = None;
+    if options.hybrid {
+        let path = ciphertext_path(base_path, &split_id, options);
+        let key = hybrid::generate_key(random)?;
+        match hybrid::encrypt_file(&mut reader, &path, &key, options.manifest) {
+            Ok(written) => ciphertext = Some(written),
+            Err(e) => {
+                let _ = std::fs::remove_file(&path);
+                return Err(e.context("the ciphertext was not kept"));
+            }
+        }
+        reader = Box::new(Cursor::new(key));
+    }
+    let discard_ciphertext = || {
+        if let Some(ciphertext) = &ciphertext {
+            let _ = std::fs::remove_file(&ciphertext.path);
+        }
+    };
 
+    let metadata = Metadata {
+        padded: options.padding != Padding::None,
+        compression: options.compression,
+        ciphertext: ciphertext
+            .as_ref()
+            .map(|ciphertext| file_name_string(&ciphertext.path)),
+        ..metadata.clone()
+    };
+    let block = metadata.encode();
+    let prefix_len = block.len() as u64;
+    let content: Box<dyn Read + '_> = match options.padding {
+        Padding::None => Box::new(reader),
+        padding => Box::new(padding::PaddedReader::new(reader, padding, prefix_len)),
+    };
+    let mut reader = Cursor::new(block).chain(content);
+
+    // A seeded first share holds the seed in its header and no payload;
+    // its pad is the keystream, regenerated on combine.
+    let seed = match options.seeded_pad.then(|| seed::generate_seed(random)) {
+        Some(Ok(seed)) => Some(seed),
+        Some(Err(e)) => {
+            discard_ciphertext();
+            return Err(e);
+        }
+        None => None,
+    };
+    let mut first_pad = seed
+        .as_ref()
+        .map(|seed| FirstPad::Seeded(seed::Keystream::new(seed)));
+    let seeded_shares = usize::from(seed.is_some());
+
+    let mut pad_offset = None;
+    if let Some(pad_path) = &options.one_time_pad {
+        let pad = match otp::reserve(pad_path, &split_id) {
+            Ok(pad) => pad,
+            Err(e) => {
+                discard_ciphertext();
+                return Err(e);
+            }
+        };
+        pad_offset = Some(pad.offset());
+        first_pad = Some(FirstPad::OneTimePad(pad));
+    }
+
+    // Every XOR share but the last is a pad. For the registry, keep the ends
+    // of each as they stream past.
+    let pad_count = match (scheme.kind(), &options.pad_registry) {
+        (SchemeKind::Xor, Some(_)) => shares - 1,
+        _ => 0,
+    };
+    let mut pad_blocks = vec![registry::PadBlocks::default(); pad_count];
+
+    let mut share_files = Vec::with_capacity(shares);
+    let streamed = (|| -> Result<_> {
+        // The MAC key is split with the same scheme as the payload, so it is
+        // recoverable from exactly the shares that can rebuild the file. A
+        // one-time pad supplies share 1 of the key, as that share is never
+        // written.
+        let mut mac = None;
+        let mut mac_key_shares = vec![None; shares];
+        if options.mac {
+            let mut key = [0u8; mac::KEY_LEN];
+            random.fill(&mut key)?;
+            let key_pad = first_pad
+                .as_mut()
+                .filter(|pad| matches!(pad, FirstPad::OneTimePad(_)));
+            scheme.encode(&key, &mut rand_buf, &mut outputs, key_pad, random)?;
+            for (key_share, output) in mac_key_shares.iter_mut().zip(&outputs) {
+                *key_share = Some(output[..mac::KEY_LEN].try_into().expect("key length"));
+            }
+            mac = Some(mac::StreamMac::new(&key));
+        }
+
+        for (i, path) in share_paths.iter().enumerate() {
+            let index = i + unwritten;
+            let seed = seed.filter(|_| index == 0);
+            let header = ShareHeader {
+                scheme: scheme.kind(),
+                index: (index + 1) as u16,
+                count: shares as u16,
+                threshold: threshold as u16,
+                split_id,
+                mac_key_share: mac_key_shares[index],
+                block_len: seed.is_none().then_some(CHUNK_SIZE as u32),
+                metadata: true,
+                seed,
+                one_time_pad: pad_offset,
+                sub_share: Vec::new(),
+            };
+            let share = match options.share_commands.get(i) {
+                Some(command) => ShareOutput::spawn(command, path, &header, options.manifest)?,
+                None => ShareOutput::create(path, &header, options.manifest)?,
+            };
+            share_files.push(share);
+        }
+
+        loop {
+            let bytes_read = read_exact_or_eof(&mut reader, &mut input_buf)?;
+            if bytes_read == 0 {
+                break;
+            }
+
+            if let Some(mac) = &mut mac {
+                mac.update(&input_buf[..bytes_read]);
+            }
+
+            scheme.encode(
+                &input_buf[..bytes_read],
+                &mut rand_buf,
+                &mut outputs,
+                first_pad.as_mut(),
+                random,
+            )?;
+            for (blocks, output) in pad_blocks.iter_mut().zip(&outputs) {
+                blocks.update(&output[..bytes_read]);
+            }
+
+            let outputs = &outputs[unwritten..];
+            for (share, output) in share_files.iter_mut().zip(outputs).skip(seeded_shares) {
+                share.write_block(&output[..bytes_read])?;
+            }
+        }
+
+        Ok(mac.map(mac::StreamMac::finalize))
+    })();
+
+    // However the split ended, the ledger keeps exactly the pad bytes used.
+    // If that fails, the rest of the pad stays reserved, which is safe.
+    let released = match (&options.one_time_pad, &first_pad) {
+        (Some(pad_path), Some(FirstPad::OneTimePad(pad))) => otp::release_unused(pad_path, pad)
+            .map(|()| Some(pad.offset()..pad.offset() + pad.used())),
+        _ => Ok(None),
+    };
+
+    let (tag, one_time_pad_range) = match streamed.and_then(|tag| Ok((tag, released?))) {
+        Ok(streamed) => streamed,
+        Err(e) => {
+            let outcomes: Vec<String> = share_files.into_iter().map(ShareOutput::abort).collect();
+            discard_ciphertext();
+            bail!("split aborted: {:#}\n  {}", e, outcomes.join("\n  "));
+        }
+    };
+
+    let mut written = Vec::with_capacity(shares);
+    let mut failures = Vec::new();
+    for share in share_files {
+        match share.finish(tag.as_ref()) {
+            Ok(share) => written.push(share),
+            Err(e) => failures.push(format!("{:#}", e)),
+        }
+    }
+    if !failures.is_empty() {
+        // Without every share the split is useless, so take back the rest.
+        failures.extend(written.into_iter().map(WrittenShare::discard));
+        discard_ciphertext();
+        bail!("split failed:\n  {}", failures.join("\n  "));
+    }
+
+    let pad_reuse = match &options.pad_registry {
+        Some(registry_path) => {
+            let pads: Vec<registry::Pad> = pad_blocks
+                .into_iter()
+                .enumerate()
+                .filter(|(_, blocks)| !blocks.is_empty())
+                .map(|(i, blocks)| registry::Pad {
+                    share: match &options.one_time_pad {
+                        Some(pad_path) if i < unwritten => pad_path.clone(),
+                        _ => share_paths[i - unwritten].clone(),
+                    },
+                    index: (i + 1) as u16,
+                    split_id: to_hex(&split_id),
+                    blocks,
+                })
+                .collect();
+            registry::record_pads(registry_path, &pads, random).with_context(|| {
+                format!(
+                    "the split succeeded, but the pad registry {} was not updated",
+                    registry_path.display()
+                )
+            })?
+        }
+        None => Vec::new(),
+    };
+
+    if options.manifest {
+        let mut entries = written
+            .iter()
+            .map(|share| ManifestEntry {
+                file_name: file_name_string(&share.path),
+                size: Some(share.len),
+                sha256: share.sha256.expect("shares are hashed for a manifest"),
+            })
+            .collect::<Vec<_>>();
+        if let Some(ciphertext) = &ciphertext {
+            entries.push(ManifestEntry {
+                file_name: file_name_string(&ciphertext.path),
+                size: Some(ciphertext.len),
+                sha256: ciphertext
+                    .sha256
+                    .expect("ciphertext is hashed for a manifest"),
+            });
+        }
+        for path in &manifest_paths {
+            manifest::write_manifest(path, &to_hex(&split_id), &entries)?;
+        }
+    }
+
+    Ok(SplitShares {
+        share_paths: share_paths.to_vec(),
+        split_id,
+        manifest_paths,
+        ciphertext_path: ciphertext.map(|ciphertext| ciphertext.path),
+        one_time_pad_range,
+        health: random.stats(),
+        pad_reuse,
+    })
+}
+
+/// Where a hybrid split writes its ciphertext: `<base_path>.enc`, or
+/// `<split ID>.enc` with random share names, in the first output directory
+/// if there is one.
+fn ciphertext_path(
+    base_path: &Path,
+    split_id: &[u8; header::SPLIT_ID_LEN],
+    options: &SplitOptions,
+) -> PathBuf {
+    let path = if options.random_names {
+        base_path.with_file_name(format!("{}.enc", to_hex(split_id)))
+    } else {
+        append_extension(base_path, "enc")
+    };
+    match options.output_dirs.first() {
+        Some(dir) => dir.join(path.file_name().expect("ciphertext path has a file name")),
+        None => path,
+    }
+}
+
+/// Where [`split_file`] writes the manifest for `input_path`: `<input_path>.sha256`.
+///
+/// For [`split_reader`], pass the base path the shares were named after.
+pub fn manifest_path(input_path: &Path) -> PathBuf {
+    append_extension(input_path, "sha256")
+}
+
+/// Where a split writes its manifests: a copy in every output directory so
+/// that it travels with the shares, or otherwise [`manifest_path`]. With
+/// random share names the manifest is named `<split ID>.sha256` instead.
+fn manifest_paths(
+    base_path: &Path,
+    split_id: &[u8; header::SPLIT_ID_LEN],
+    options: &SplitOptions,
+) -> Vec<PathBuf> {
+    let path = if options.random_names {
+        base_path.with_file_name(format!("{}.sha256", to_hex(split_id)))
+    } else {
+        manifest_path(base_path)
+    };
+    let mut dirs = options.output_dirs.clone();
+    dirs.dedup();
+    if dirs.is_empty() {
+        return vec![path];
+    }
+    let file_name = path.file_name().expect("manifest path has a file name");
+    dirs.iter().map(|dir| dir.join(file_name)).collect()
+}
+
+/// The state of one share listed in a manifest.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ManifestStatus {
+    Ok,
+    /// The share is not present next to the manifest.
+    Missing,
+    /// The share has a different size than the manifest records.
+    WrongSize {
+        expected: u64,
+        actual: u64,
+    },
+    /// The share has the recorded size but a different SHA-256.
+    WrongHash,
+}
+
+/// Check the shares listed in a manifest against their recorded size and
+/// SHA-256.
+///
+/// Share names are resolved relative to the manifest's directory. Returns
+/// every entry with its status; a missing share is reported rather than
+/// treated as an error, since a recipient may hold only some of the shares.
+pub fn check_manifest(manifest: &Path) -> Result<Vec<(ManifestEntry, ManifestStatus)>> {
+    let dir = manifest.parent().unwrap_or(Path::new(""));
+    let entries = manifest::read_manifest(manifest)?;
+    if entries.is_empty() {
+        bail!("{} lists no shares", manifest.display());
+    }
+
+    let mut results = Vec::with_capacity(entries.len());
+    for entry in entries {
+        let path = dir.join(&entry.file_name);
+        let status = check_manifest_entry(&path, &entry)?;
+        results.push((entry, status));
+    }
+    Ok(results)
+}
+
+fn check_manifest_entry(path: &Path, entry: &ManifestEntry) -> Result<ManifestStatus> {
+    let file = match File::open(path) {
+        Ok(file) => file,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(ManifestStatus::Missing),
+        Err(e) => return Err(e).with_context(|| format!("failed to open {}", path.display())),
+    };
+
+    let actual = file
+        .metadata()
+        .with_context(|| format!("failed to read metadata for {}", path.display()))?
+        .len();
+    if let Some(expected) = entry.size
+        && expected != actual
+    {
+        return Ok(ManifestStatus::WrongSize { expected, actual });
+    }
+
+    let mut reader = BufReader::new(file);
+    let mut hasher = Sha256::new();
+    let mut buf = vec![0u8; CHUNK_SIZE];
     loop {
-        let bytes_read = read_exact_or_eof(&mut reader, &mut input_buf)?;
-        if bytes_read == 0 {
+        let n = read_exact_or_eof(&mut reader, &mut buf)?;
+        if n == 0 {
             break;
         }
+        hasher.update(&buf[..n]);
+    }
 
-        let input_chunk = &input_buf[..bytes_read];
-        let rand_chunk = &mut rand_buf[..bytes_read];
-        let xor_chunk = &mut xor_buf[..bytes_read];
+    if <[u8; 32]>::from(hasher.finalize()) == entry.sha256 {
+        Ok(ManifestStatus::Ok)
+    } else {
+        Ok(ManifestStatus::WrongHash)
+    }
+}
 
-        rng().fill_bytes(rand_chunk);
-        xor_buffers(input_chunk, rand_chunk, xor_chunk);
+/// Verify that the split files reproduce the original.
+///
+/// `shares` must be the complete set written by [`split_file`] or
+/// [`split_file_threshold`], plus the pad file for a split that took share 1
+/// from a one-time pad. XOR shares are combined all at once; threshold
+/// shares are checked in consecutive groups of `threshold` so that every
+/// share takes part in at least one reconstruction. Large files are only
+/// sampled, at offsets drawn from `random`.
+pub fn verify_files(
+    original: &Path,
+    shares: &[PathBuf],
+    random: &mut dyn RandomSource,
+) -> Result<bool> {
+    if shares.is_empty() {
+        bail!("no share files given to verify");
+    }
+
+    let mut inputs = open_shares(shares)?;
+    check_same_split(&inputs)?;
+    fill_pad_len(&mut inputs)?;
+
+    let header = match &inputs[0].header {
+        Some(header) if header.scheme == SchemeKind::Threshold => header.clone(),
+        _ => return verify_with(original, &mut inputs, &Combiner::Xor, random),
+    };
 
-        writer1
-            .write_all(rand_chunk)
-            .context("failed to write to xor1 file")?;
-        writer2
-            .write_all(xor_chunk)
-            .context("failed to write to xor2 file")?;
+    let threshold = header.threshold as usize;
+    if inputs.len() < threshold {
+        bail!(
+            "need at least {} shares to verify but got {}",
+            threshold,
+            inputs.len()
+        );
     }
 
-    writer1.flush().context("failed to flush xor1 file")?;
-    writer2.flush().context("failed to flush xor2 file")?;
+    let mut start = 0;
+    loop {
+        let start_at = start.min(inputs.len() - threshold);
+        let group = &mut inputs[start_at..start_at + threshold];
+        let combiner = Combiner::Threshold(shamir::Interpolator::new(&share_xs(group)?)?);
 
-    Ok((xor1_path, xor2_path))
+        if !verify_with(original, group, &combiner, random)? {
+            return Ok(false);
+        }
+
+        start += threshold;
+        if start >= inputs.len() {
+            return Ok(true);
+        }
+    }
 }
 
-/// Verify that XOR-ing the two split files reproduces the original.
-pub fn verify_files(original: &Path, xor1: &Path, xor2: &Path) -> Result<bool> {
+fn verify_with(
+    original: &Path,
+    shares: &mut [ShareInput],
+    combiner: &Combiner,
+    random: &mut dyn RandomSource,
+) -> Result<bool> {
     let file_size = std::fs::metadata(original)
         .with_context(|| format!("failed to read metadata for {}", original.display()))?
         .len();
 
+    let (offset, len, metadata) = content_range(shares, combiner)?;
+    if metadata.compression != Compression::None || metadata.ciphertext.is_some() {
+        return verify_streamed(original, shares, combiner);
+    }
+    if len != file_size {
+        return Ok(false);
+    }
     if file_size <= VERIFY_FULL_THRESHOLD {
-        verify_full(original, xor1, xor2)
+        verify_full(original, shares, combiner, offset)
     } else {
-        verify_sampled(original, xor1, xor2, file_size)
+        verify_sampled(original, shares, combiner, file_size, offset, random)
     }
 }
 
-fn verify_full(original: &Path, xor1: &Path, xor2: &Path) -> Result<bool> {
+/// Where the original's content lies in the payload, as an offset and a
+/// length: after the metadata block and before the padding, if the split
+/// has them. Also returns the metadata.
+fn content_range(shares: &mut [ShareInput], combiner: &Combiner) -> Result<(u64, u64, Metadata)> {
+    for share in shares.iter_mut() {
+        share.seek_payload(0)?;
+    }
+    let payload_len = shares[0].payload_len;
+    let mut recombiner = Recombiner::new(shares, combiner);
+    let metadata = recombiner.read_metadata()?;
+    let offset = recombiner.position();
+    let len = recombiner.content_left.unwrap_or(payload_len - offset);
+    Ok((offset, len, metadata))
+}
+
+/// Verify a compressed or hybrid split by restoring all of it, since such
+/// content cannot be sampled at an offset.
+fn verify_streamed(
+    original: &Path,
+    shares: &mut [ShareInput],
+    combiner: &Combiner,
+) -> Result<bool> {
     let mut orig_reader = BufReader::new(
         File::open(original).with_context(|| format!("failed to open {}", original.display()))?,
     );
-    let mut xor1_reader = BufReader::new(
-        File::open(xor1).with_context(|| format!("failed to open {}", xor1.display()))?,
-    );
-    let mut xor2_reader = BufReader::new(
-        File::open(xor2).with_context(|| format!("failed to open {}", xor2.display()))?,
+    for share in shares.iter_mut() {
+        share.seek_payload(0)?;
+    }
+    let mut recombiner = Recombiner::new(shares, combiner);
+    let metadata = recombiner.read_metadata()?;
+    let mut content = content_reader(&mut recombiner, &metadata)?;
+
+    let mut orig_buf = vec![0u8; CHUNK_SIZE];
+    let mut content_buf = vec![0u8; CHUNK_SIZE];
+    loop {
+        let orig_n = read_exact_or_eof(&mut orig_reader, &mut orig_buf)?;
+        let content_n = read_exact_or_eof(&mut content, &mut content_buf)?;
+        if orig_n != content_n || orig_buf[..orig_n] != content_buf[..content_n] {
+            return Ok(false);
+        }
+        if orig_n == 0 {
+            return Ok(true);
+        }
+    }
+}
+
+/// Whether the payload starts with a metadata block.
+fn has_metadata(shares: &[ShareInput]) -> bool {
+    shares[0]
+        .header
+        .as_ref()
+        .is_some_and(|header| header.metadata)
+}
+
+/// Read the header of a share file.
+///
+/// Returns `None` for a legacy raw share written without a header.
+pub fn read_share_header(path: &Path) -> Result<Option<ShareHeader>> {
+    Ok(ShareInput::open(path)?.header)
+}
+
+/// Read the header of the split that [`combine_files`] would combine from
+/// `input_paths`, wherever its shares fall among them.
+///
+/// Returns `None` for a legacy raw pair written without headers.
+pub fn resolve_split_header(input_paths: &[PathBuf]) -> Result<Option<ShareHeader>> {
+    let (shares, _) = resolve_shares(input_paths)?;
+    Ok(shares
+        .into_iter()
+        .find(|share| !share.is_one_time_pad())
+        .and_then(|share| share.header))
+}
+
+/// Check that every share was written by the same split as the first.
+///
+/// Headered shares must carry the same split-set ID and layout; legacy raw
+/// shares carry no ID and can only be checked for matching sizes.
+fn check_same_split(shares: &[ShareInput]) -> Result<()> {
+    let (first, rest) = shares.split_first().expect("at least one share");
+
+    for share in rest {
+        match (&first.header, &share.header) {
+            (None, None) => {}
+            (None, Some(_)) | (Some(_), None) => bail!(
+                "cannot combine a legacy raw share with a headered share: {} and {}",
+                first.path.display(),
+                share.path.display()
+            ),
+            (Some(a), Some(b)) if a.split_id != b.split_id => bail!(
+                "{} and {} come from different splits (split IDs {} and {}); \
+                 only shares written by the same split can be combined",
+                first.path.display(),
+                share.path.display(),
+                a.split_id_hex(),
+                b.split_id_hex()
+            ),
+            (Some(a), Some(b))
+                if a.scheme == b.scheme
+                    && a.count == b.count
+                    && a.threshold == b.threshold
+                    && a.mac_key_share.is_some() == b.mac_key_share.is_some() => {}
+            (Some(a), Some(b)) => bail!(
+                "shares describe different splits: {} is {}, {} is {}",
+                first.path.display(),
+                describe_layout(a),
+                share.path.display(),
+                describe_layout(b)
+            ),
+        }
+
+        if !first.is_pad() && !share.is_pad() && share.payload_len != first.payload_len {
+            bail!(
+                "file sizes differ: {} has {} payload bytes, {} has {}",
+                first.path.display(),
+                first.payload_len,
+                share.path.display(),
+                share.payload_len
+            );
+        }
+    }
+
+    Ok(())
+}
+
+/// Give the pads that the shares do not store the payload length of the
+/// stored shares.
+fn fill_pad_len(shares: &mut [ShareInput]) -> Result<()> {
+    let Some(len) = shares
+        .iter()
+        .find(|share| !share.is_pad())
+        .map(|share| share.payload_len)
+    else {
+        return Ok(());
+    };
+    for share in shares.iter_mut().filter(|share| share.is_pad()) {
+        share.set_pad_len(len)?;
+    }
+    Ok(())
+}
+
+fn describe_layout(header: &ShareHeader) -> String {
+    let layout = match header.scheme {
+        SchemeKind::Xor => format!("one of {} XOR shares", header.count),
+        SchemeKind::Threshold => format!(
+            "one of {} threshold shares needing {}",
+            header.count, header.threshold
+        ),
+    };
+    if header.mac_key_share.is_some() {
+        format!("{} with a MAC", layout)
+    } else {
+        layout
+    }
+}
+
+/// The Shamir x-coordinates of a set of headered threshold shares.
+fn share_xs(shares: &[ShareInput]) -> Result<Vec<u8>> {
+    shares
+        .iter()
+        .map(|share| {
+            let index = share.header.as_ref().expect("threshold share header").index;
+            u8::try_from(index).with_context(|| {
+                format!(
+                    "{} has share index {}, beyond the {} x-coordinates of GF(256)",
+                    share.path.display(),
+                    index,
+                    MAX_SHARES
+                )
+            })
+        })
+        .collect()
+}
+
+/// Read the next chunk from every share and combine them into `recombined`.
+///
+/// Returns `None` if the shares returned different numbers of bytes.
+fn read_recombined(
+    shares: &mut [ShareInput],
+    combiner: &Combiner,
+    share_buf: &mut [u8],
+    recombined: &mut [u8],
+) -> Result<Option<usize>> {
+    let mut len = None;
+
+    for (index, share) in shares.iter_mut().enumerate() {
+        let n = share.read_payload(share_buf)?;
+        if *len.get_or_insert(n) != n {
+            return Ok(None);
+        }
+        combiner.accumulate(index, &share_buf[..n], &mut recombined[..n]);
+    }
+
+    Ok(len)
+}
+
+fn verify_full(
+    original: &Path,
+    shares: &mut [ShareInput],
+    combiner: &Combiner,
+    offset: u64,
+) -> Result<bool> {
+    let mut orig_reader = BufReader::new(
+        File::open(original).with_context(|| format!("failed to open {}", original.display()))?,
     );
+    for share in shares.iter_mut() {
+        share.seek_payload(offset)?;
+    }
 
     let mut orig_buf = vec![0u8; CHUNK_SIZE];
-    let mut xor1_buf = vec![0u8; CHUNK_SIZE];
-    let mut xor2_buf = vec![0u8; CHUNK_SIZE];
+    let mut share_buf = vec![0u8; CHUNK_SIZE];
     let mut recombined = vec![0u8; CHUNK_SIZE];
 
     loop {
         let orig_n = read_exact_or_eof(&mut orig_reader, &mut orig_buf)?;
-        let xor1_n = read_exact_or_eof(&mut xor1_reader, &mut xor1_buf)?;
-        let xor2_n = read_exact_or_eof(&mut xor2_reader, &mut xor2_buf)?;
-
-        if orig_n != xor1_n || orig_n != xor2_n {
-            return Ok(false);
-        }
         if orig_n == 0 {
             break;
         }
+        let share_n = match read_recombined(shares, combiner, &mut share_buf, &mut recombined)? {
+            Some(n) => n,
+            None => return Ok(false),
+        };
 
-        xor_buffers(
-            &xor1_buf[..orig_n],
-            &xor2_buf[..orig_n],
-            &mut recombined[..orig_n],
-        );
+        // Past the content the shares may go on into padding.
+        if share_n < orig_n {
+            return Ok(false);
+        }
 
         if recombined[..orig_n] != orig_buf[..orig_n] {
             return Ok(false);
@@ -125,39 +1290,26 @@ fn verify_full(original: &Path, xor1: &Path, xor2: &Path) -> Result<bool> {
     Ok(true)
 }
 
-fn verify_sampled(original: &Path, xor1: &Path, xor2: &Path, file_size: u64) -> Result<bool> {
-    let xor1_size = std::fs::metadata(xor1)
-        .with_context(|| format!("failed to read metadata for {}", xor1.display()))?
-        .len();
-    let xor2_size = std::fs::metadata(xor2)
-        .with_context(|| format!("failed to read metadata for {}", xor2.display()))?
-        .len();
-
-    if file_size != xor1_size || file_size != xor2_size {
-        return Ok(false);
-    }
-
+fn verify_sampled(
+    original: &Path,
+    shares: &mut [ShareInput],
+    combiner: &Combiner,
+    file_size: u64,
+    offset: u64,
+    random: &mut dyn RandomSource,
+) -> Result<bool> {
     let chunk = CHUNK_SIZE as u64;
-    let last_offset = if file_size >= chunk {
-        file_size - chunk
-    } else {
-        0
-    };
+    let last_offset = file_size.saturating_sub(chunk);
 
     let mut offsets = BTreeSet::new();
     offsets.insert(0u64);
     offsets.insert(last_offset);
 
     // Generate 8 random interior offsets
-    let interior_range = if file_size > chunk {
-        file_size - chunk
-    } else {
-        0
-    };
+    let interior_range = file_size.saturating_sub(chunk);
     if interior_range > 0 {
-        let mut r = rng();
         while offsets.len() < 10 {
-            let offset = r.next_u64() % interior_range;
+            let offset = random.next_u64()? % interior_range;
             offsets.insert(offset);
         }
     }
@@ -165,39 +1317,29 @@ fn verify_sampled(original: &Path, xor1: &Path, xor2: &Path, file_size: u64) ->
     let mut orig_file = BufReader::new(
         File::open(original).with_context(|| format!("failed to open {}", original.display()))?,
     );
-    let mut xor1_file = BufReader::new(
-        File::open(xor1).with_context(|| format!("failed to open {}", xor1.display()))?,
-    );
-    let mut xor2_file = BufReader::new(
-        File::open(xor2).with_context(|| format!("failed to open {}", xor2.display()))?,
-    );
 
     let mut orig_buf = vec![0u8; CHUNK_SIZE];
-    let mut xor1_buf = vec![0u8; CHUNK_SIZE];
-    let mut xor2_buf = vec![0u8; CHUNK_SIZE];
+    let mut share_buf = vec![0u8; CHUNK_SIZE];
     let mut recombined = vec![0u8; CHUNK_SIZE];
 
-    for &offset in &offsets {
-        orig_file.seek(SeekFrom::Start(offset))?;
-        xor1_file.seek(SeekFrom::Start(offset))?;
-        xor2_file.seek(SeekFrom::Start(offset))?;
+    for &sample in &offsets {
+        orig_file.seek(SeekFrom::Start(sample))?;
+        for share in shares.iter_mut() {
+            share.seek_payload(offset + sample)?;
+        }
 
         let orig_n = read_exact_or_eof(&mut orig_file, &mut orig_buf)?;
-        let xor1_n = read_exact_or_eof(&mut xor1_file, &mut xor1_buf)?;
-        let xor2_n = read_exact_or_eof(&mut xor2_file, &mut xor2_buf)?;
-
-        if orig_n != xor1_n || orig_n != xor2_n {
-            return Ok(false);
-        }
         if orig_n == 0 {
             continue;
         }
+        let share_n = match read_recombined(shares, combiner, &mut share_buf, &mut recombined)? {
+            Some(n) => n,
+            None => return Ok(false),
+        };
 
-        xor_buffers(
-            &xor1_buf[..orig_n],
-            &xor2_buf[..orig_n],
-            &mut recombined[..orig_n],
-        );
+        if share_n < orig_n {
+            return Ok(false);
+        }
 
         if recombined[..orig_n] != orig_buf[..orig_n] {
             return Ok(false);
@@ -218,6 +1360,18 @@ fn read_exact_or_eof(reader: &mut impl Read, buf: &mut [u8]) -> Result<usize> {
     Ok(total)
 }
 
+/// Lowercase hex encoding, for split IDs and hashes in messages and manifests.
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// The final component of `path` as a string, for listing in a manifest.
+fn file_name_string(path: &Path) -> String {
+    path.file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default()
+}
+
 fn append_extension(path: &Path, ext: &str) -> PathBuf {
     let mut new_path = path.as_os_str().to_owned();
     new_path.push(".");
@@ -225,91 +1379,642 @@ fn append_extension(path: &Path, ext: &str) -> PathBuf {
     PathBuf::from(new_path)
 }
 
-/// Combine two XOR-complementary files back into the original.
+/// Combine shares back into the original.
 ///
-/// Given either the `.xor1` or `.xor2` file, auto-discovers the partner
-/// and XORs them together to reconstruct the original file.
-/// Returns the path of the output file.
-pub fn combine_files(input_path: &Path) -> Result<PathBuf> {
-    let (xor1_path, xor2_path) = resolve_xor_pair(input_path)?;
-
-    let xor1_size = std::fs::metadata(&xor1_path)
-        .with_context(|| format!("failed to read metadata for {}", xor1_path.display()))?
-        .len();
-    let xor2_size = std::fs::metadata(&xor2_path)
-        .with_context(|| format!("failed to read metadata for {}", xor2_path.display()))?
-        .len();
+/// The scheme, share count and threshold are read from the share headers.
+/// Given a single share, auto-discovers its partners next to it by extension
+/// (`.xorN` or `.sssN`), or for randomly named shares by split ID; given
+/// several, uses exactly those. XOR shares are all XOR-ed together; for
+/// threshold shares the first `threshold` distinct shares found are
+/// interpolated. A legacy headerless `.xor1`/`.xor2` pair from splinch 0.2
+/// is combined as raw bytes.
+///
+/// Block checksums are checked as the shares are read, and if the split has
+/// a one-time MAC the output is authenticated against it. If either check
+/// fails the output is removed again and the error names the damaged share,
+/// and for checksums the damaged blocks. The output is named after the
+/// original, in the directory of the first share, and gets back its mode,
+/// mtime and extended attributes; a directory is unpacked into a new
+/// directory. Returns the path of the output file or directory.
+pub fn combine_files(input_paths: &[PathBuf]) -> Result<PathBuf> {
+    let (mut shares, combiner) = resolve_shares(input_paths)?;
+
+    let share_path = shares[0].path.clone();
+    let prefix = match &shares[0].header {
+        Some(header) => header.scheme.extension_prefix(),
+        None => "xor",
+    };
+
+    let mut recombiner = Recombiner::new(&mut shares, &combiner);
+    let metadata = recombiner.read_metadata()?;
+
+    let base_path = match metadata.safe_name() {
+        Some(name) => share_path.with_file_name(name),
+        None if share_index(&share_path, prefix).is_some() => share_path.with_extension(""),
+        None => bail!(
+            "the shares do not record a file name and {} has no .{}N extension; \
+             give an output path",
+            share_path.display(),
+            prefix
+        ),
+    };
+    let output_path = resolve_output_path(&base_path);
+
+    restore_output(recombiner, &metadata, &output_path)?;
+    Ok(output_path)
+}
+
+/// Combine shares like [`combine_files`], writing the output to exactly
+/// `output_path`.
+///
+/// Refuses to overwrite an existing file. The output is removed again if a
+/// checksum or the MAC does not match.
+pub fn combine_files_to(input_paths: &[PathBuf], output_path: &Path) -> Result<()> {
+    let (mut shares, combiner) = resolve_shares(input_paths)?;
+    let mut recombiner = Recombiner::new(&mut shares, &combiner);
+    let metadata = recombiner.read_metadata()?;
+    restore_output(recombiner, &metadata, output_path)
+}
+
+/// Write the rest of the recombined payload to a new file or directory at
+/// `output_path` and apply `metadata` to it, removing the output again if
+/// any check fails.
+fn restore_output(recombiner: Recombiner, metadata: &Metadata, output_path: &Path) -> Result<()> {
+    // Metadata is only restored once the output has passed its checks, so
+    // with a MAC it has been authenticated by then.
+    let authenticated = recombiner.mac.is_some();
+    let written = if metadata.archive {
+        std::fs::create_dir(output_path)
+            .with_context(|| format!("failed to create {}", output_path.display()))?;
+        unpack_output(recombiner, metadata, output_path)
+    } else {
+        let out_file = OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(output_path)
+            .with_context(|| format!("failed to create {}", output_path.display()))?;
+        copy_output(recombiner, metadata, &mut BufWriter::new(out_file))
+    };
+
+    if let Err(e) = written.and_then(|()| metadata.restore(output_path, authenticated)) {
+        let removed = if metadata.archive {
+            std::fs::remove_dir_all(output_path)
+        } else {
+            std::fs::remove_file(output_path)
+        };
+        removed.with_context(|| format!("failed to remove {}", output_path.display()))?;
+        return Err(e.context("the output was not kept"));
+    }
+    Ok(())
+}
+
+/// Unpack the recombined directory archive into the existing `output_path`.
+fn unpack_output(recombiner: Recombiner, metadata: &Metadata, output_path: &Path) -> Result<()> {
+    let (mut writer, unpacker) = archive::Unpacker::start(output_path)?;
+    let copied = copy_output(recombiner, metadata, &mut writer);
+    drop(writer);
+    let unpacked = unpacker.finish();
+    copied.and(unpacked)
+}
+
+/// Combine shares like [`combine_files`], streaming the output to `writer`.
+///
+/// The plaintext is only ever held in memory one chunk at a time, so `writer`
+/// can be a pipe such as stdout. Output stops before the first chunk that
+/// touches a corrupt block. The MAC can only be checked once everything has
+/// been written, so if this returns an authentication error the caller must
+/// discard what `writer` received. The recorded metadata is not applied.
+pub fn combine_to_writer(input_paths: &[PathBuf], writer: &mut impl Write) -> Result<()> {
+    let (mut shares, combiner) = resolve_shares(input_paths)?;
+    let mut recombiner = Recombiner::new(&mut shares, &combiner);
+    let metadata = recombiner.read_metadata()?;
+    copy_output(recombiner, &metadata, writer)
+        .context("the output written so far must be discarded")
+}
+
+/// Copy the rest of the recombined content to `writer`, decompressing it if
+/// `metadata` says so, then check the block checksums and the MAC.
+///
+/// A checksum or MAC failure is reported ahead of a write or decompression
+/// error, since a damaged share is the likelier cause of both.
+fn copy_output(
+    mut recombiner: Recombiner,
+    metadata: &Metadata,
+    writer: &mut impl Write,
+) -> Result<()> {
+    let copied = content_reader(&mut recombiner, metadata).and_then(|mut content| {
+        io::copy(&mut content, writer)
+            .and_then(|_| writer.flush())
+            .context("failed to restore the output")
+    });
+    recombiner.finish()?;
+    copied
+}
+
+/// The original's content: the rest of the recombined payload or, for a
+/// hybrid split, the ciphertext next to the first share decrypted with the
+/// recombined key, decompressed if `metadata` says so.
+fn content_reader<'r>(
+    recombiner: &'r mut Recombiner,
+    metadata: &Metadata,
+) -> Result<Box<dyn Read + 'r>> {
+    let content: Box<dyn Read + 'r> = match metadata.safe_ciphertext()? {
+        None => Box::new(recombiner),
+        Some(name) => {
+            let mut key = [0u8; hybrid::KEY_MATERIAL_LEN];
+            let read = recombiner.read_exact(&mut key);
+            // The key must pass its checksums and MAC before it is used.
+            recombiner.finish()?;
+            read.context("the shares hold a truncated key")?;
+            let path = recombiner.shares[0].path.with_file_name(name);
+            Box::new(hybrid::Decryptor::open(&path, &key)?)
+        }
+    };
+    Ok(metadata.compression.decoder(content)?)
+}
+
+/// Reads the recombined payload of a set of shares, updating the MAC as it
+/// goes.
+///
+/// A read fails as soon as a chunk touches a corrupt block, so nothing from
+/// a damaged block is ever returned, and ends where the content ends, before
+/// any padding. [`Recombiner::finish`] then reads what is left to find every
+/// damaged block, and checks the MAC.
+struct Recombiner<'a> {
+    shares: &'a mut [ShareInput],
+    combiner: &'a Combiner,
+    mac: Option<mac::StreamMac>,
+    share_buf: Vec<u8>,
+    buf: Vec<u8>,
+    pos: usize,
+    len: usize,
+    /// Payload bytes read into the buffer so far.
+    offset: u64,
+    /// Content bytes left to return, once known for a padded split.
+    content_left: Option<u64>,
+}
+
+impl<'a> Recombiner<'a> {
+    fn new(shares: &'a mut [ShareInput], combiner: &'a Combiner) -> Self {
+        let mac = recover_mac_key(shares, combiner).map(|key| mac::StreamMac::new(&key));
+        Self {
+            shares,
+            combiner,
+            mac,
+            share_buf: vec![0u8; CHUNK_SIZE],
+            buf: vec![0u8; CHUNK_SIZE],
+            pos: 0,
+            len: 0,
+            offset: 0,
+            content_left: None,
+        }
+    }
+
+    /// The payload offset of the next byte to be returned.
+    fn position(&self) -> u64 {
+        self.offset - (self.len - self.pos) as u64
+    }
+
+    /// Read the next chunk into the buffer, returning its length.
+    fn fill(&mut self) -> Result<usize> {
+        let n = match read_recombined(
+            self.shares,
+            self.combiner,
+            &mut self.share_buf,
+            &mut self.buf,
+        )? {
+            Some(n) => n,
+            None => bail!("unexpected read size mismatch during combine"),
+        };
+        self.pos = 0;
+        self.len = n;
+        self.offset += n as u64;
+        Ok(n)
+    }
+
+    fn is_corrupt(&self) -> bool {
+        self.shares.iter().any(ShareInput::has_corrupt_blocks)
+    }
+
+    /// Read the metadata block at the start of the payload, or return empty
+    /// metadata if the split has none. For a padded split, also read the
+    /// content length from the end of the payload.
+    ///
+    /// If either cannot be read, a checksum or MAC failure behind that is
+    /// reported in its place.
+    fn read_metadata(&mut self) -> Result<Metadata> {
+        if !has_metadata(self.shares) {
+            return Ok(Metadata::default());
+        }
+        let metadata = Metadata::read_from(self).map_err(|e| self.finish().err().unwrap_or(e))?;
+        if metadata.padded {
+            let content_len = self
+                .read_content_len()
+                .map_err(|e| self.finish().err().unwrap_or(e))?;
+            self.content_left = Some(content_len);
+        }
+        Ok(metadata)
+    }
+
+    /// Read the content length stored at the end of a padded payload, then
+    /// carry on from where reading left off.
+    fn read_content_len(&mut self) -> Result<u64> {
+        let payload_len = self.shares[0].payload_len;
+        let Some(trailer_at) = payload_len.checked_sub(padding::TRAILER_LEN) else {
+            bail!("padded split is too short to hold its content length");
+        };
+
+        for share in self.shares.iter_mut() {
+            share.seek_payload(trailer_at)?;
+        }
+        let mut share_buf = [0u8; padding::TRAILER_LEN as usize];
+        let mut trailer = [0u8; padding::TRAILER_LEN as usize];
+        let read = read_recombined(self.shares, self.combiner, &mut share_buf, &mut trailer)?;
+        for share in self.shares.iter_mut() {
+            share.seek_payload(self.offset)?;
+        }
 
-    if xor1_size != xor2_size {
+        padding::content_len(&trailer[..read.unwrap_or(0)], trailer_at - self.position())
+    }
+
+    /// Read the rest of the payload, then fail if any block had a bad
+    /// checksum or the MAC does not match.
+    fn finish(&mut self) -> Result<()> {
+        while self.fill()? > 0 {
+            if !self.is_corrupt()
+                && let Some(mac) = &mut self.mac
+            {
+                mac.update(&self.buf[..self.len]);
+            }
+        }
+
+        check_blocks(self.shares)?;
+        match self.mac.take() {
+            Some(mac) => check_tag(self.shares, &mac.finalize()),
+            None => Ok(()),
+        }
+    }
+}
+
+impl Read for Recombiner<'_> {
+    fn read(&mut self, out: &mut [u8]) -> io::Result<usize> {
+        if self.content_left == Some(0) {
+            return Ok(0);
+        }
+        if self.pos == self.len {
+            self.fill()
+                .map_err(|e| io::Error::other(format!("{:#}", e)))?;
+            if self.is_corrupt() {
+                self.len = 0;
+                return Err(io::Error::other("a share has a corrupt block"));
+            }
+            if let Some(mac) = &mut self.mac {
+                mac.update(&self.buf[..self.len]);
+            }
+        }
+
+        let mut n = out.len().min(self.len - self.pos);
+        if let Some(left) = &mut self.content_left {
+            n = (n as u64).min(*left) as usize;
+            *left -= n as u64;
+        }
+        out[..n].copy_from_slice(&self.buf[self.pos..self.pos + n]);
+        self.pos += n;
+        Ok(n)
+    }
+}
+
+/// Fail if any block read from the shares had a bad checksum.
+fn check_blocks(shares: &[ShareInput]) -> Result<()> {
+    let damaged: Vec<String> = shares
+        .iter()
+        .filter(|share| share.has_corrupt_blocks())
+        .map(ShareInput::describe_corrupt_blocks)
+        .collect();
+
+    if damaged.is_empty() {
+        return Ok(());
+    }
+    bail!("corrupt blocks found:\n  {}", damaged.join("\n  "))
+}
+
+/// The result of checking one share's block checksums.
+#[derive(Debug, Clone)]
+pub struct ShareCheck {
+    pub header: Option<ShareHeader>,
+    /// Payload bytes per block, or `None` if the share has no checksums.
+    pub block_len: Option<u64>,
+    /// Number of checksummed blocks in the share.
+    pub blocks: u64,
+    /// Indexes of blocks whose checksum did not match.
+    pub corrupt_blocks: Vec<u64>,
+    /// Payload length in bytes, for turning block indexes into byte ranges.
+    pub payload_len: u64,
+}
+
+/// Check every block checksum in a share file without combining it.
+///
+/// Shares written without checksums (including legacy raw shares) are
+/// reported with `block_len` set to `None` and nothing to check.
+pub fn check_share(path: &Path) -> Result<ShareCheck> {
+    let mut share = ShareInput::open(path)?;
+
+    let mut blocks = 0;
+    if let Some(block_len) = share.block_len() {
+        blocks = share.payload_len.div_ceil(block_len);
+        let mut buf = vec![0u8; block_len as usize];
+        while share.read_payload(&mut buf)? > 0 {}
+    }
+
+    Ok(ShareCheck {
+        block_len: share.block_len(),
+        blocks,
+        corrupt_blocks: share.corrupt_blocks,
+        payload_len: share.payload_len,
+        header: share.header,
+    })
+}
+
+/// Look up the XOR pads among `shares` in the pad registry at `registry`,
+/// without recording them.
+///
+/// Returns every pad that the registry recorded for a different split, such
+/// as a `.xor1` copied from one split into another. Threshold shares, the
+/// last share of an XOR split and legacy raw shares hold no pad and are
+/// skipped, as is a seeded share given without a full share to size it.
+pub fn check_pad_registry(registry: &Path, shares: &[PathBuf]) -> Result<Vec<PadReuse>> {
+    let mut inputs = open_shares(shares)?;
+    fill_pad_len(&mut inputs)?;
+
+    let mut pads = Vec::new();
+    for share in &mut inputs {
+        let Some(header) = share.header.clone() else {
+            continue;
+        };
+        if header.scheme != SchemeKind::Xor || header.index >= header.count {
+            continue;
+        }
+        let len = share.payload_len;
+        let block_len = len.min(registry::FINGERPRINT_BLOCK as u64);
+        if block_len == 0 {
+            continue;
+        }
+
+        let mut first = vec![0u8; block_len as usize];
+        let n = share.read_payload(&mut first)?;
+        first.truncate(n);
+        let mut last = vec![0u8; block_len as usize];
+        share.seek_payload(len - block_len)?;
+        let n = share.read_payload(&mut last)?;
+        last.truncate(n);
+
+        pads.push(registry::Pad {
+            share: share.path.clone(),
+            index: header.index,
+            split_id: header.split_id_hex(),
+            blocks: registry::PadBlocks::new(first, last),
+        });
+    }
+    registry::check_pads(registry, &pads)
+}
+
+/// Rebuild the one-time MAC key from the key shares in the headers.
+fn recover_mac_key(shares: &[ShareInput], combiner: &Combiner) -> Option<[u8; mac::KEY_LEN]> {
+    let mut key = [0u8; mac::KEY_LEN];
+    for (index, share) in shares.iter().enumerate() {
+        let key_share = share.header.as_ref()?.mac_key_share.as_ref()?;
+        combiner.accumulate(index, key_share, &mut key);
+    }
+    Some(key)
+}
+
+/// Compare the recomputed MAC tag with the tag stored in every share.
+fn check_tag(shares: &[ShareInput], tag: &[u8; mac::TAG_LEN]) -> Result<()> {
+    // A one-time pad carries no tag of its own.
+    let shares: Vec<&ShareInput> = shares
+        .iter()
+        .filter(|share| !share.is_one_time_pad())
+        .collect();
+    let mismatched: Vec<String> = shares
+        .iter()
+        .filter(|share| share.tag.as_ref() != Some(tag))
+        .map(|share| share.path.display().to_string())
+        .collect();
+
+    if mismatched.is_empty() {
+        return Ok(());
+    }
+    if mismatched.len() < shares.len() {
         bail!(
-            "file sizes differ: {} is {} bytes, {} is {} bytes",
-            xor1_path.display(),
-            xor1_size,
-            xor2_path.display(),
-            xor2_size
+            "authentication failed: the MAC tag stored in {} does not match; \
+             that share was damaged or tampered with",
+            mismatched.join(", ")
         );
     }
+    bail!(
+        "authentication failed: the combined output does not match the MAC; \
+         one of the shares was damaged or tampered with"
+    )
+}
 
-    let base_path = strip_xor_extension(&xor1_path)?;
-    let output_path = resolve_output_path(&base_path);
-
-    let mut xor1_reader = BufReader::new(
-        File::open(&xor1_path)
-            .with_context(|| format!("failed to open {}", xor1_path.display()))?,
-    );
-    let mut xor2_reader = BufReader::new(
-        File::open(&xor2_path)
-            .with_context(|| format!("failed to open {}", xor2_path.display()))?,
-    );
+/// Parse the share number from an extension such as `.xor3` or `.sss3`.
+fn share_index(path: &Path, prefix: &str) -> Option<usize> {
+    let ext = path.extension().and_then(|e| e.to_str())?;
+    let index: usize = ext.strip_prefix(prefix)?.parse().ok()?;
+    (index >= 1).then_some(index)
+}
 
-    let out_file = File::create(&output_path)
-        .with_context(|| format!("failed to create {}", output_path.display()))?;
-    let mut writer = BufWriter::new(out_file);
+/// Open the given share files.
+///
+/// A file without a header, given along with the shares of a split that took
+/// share 1 from a one-time pad, is opened as that pad. Complete sets of
+/// sub-shares are joined into the shares they were split from.
+fn open_shares(paths: &[PathBuf]) -> Result<Vec<ShareInput>> {
+    let mut shares = resplit::join_sub_shares(
+        paths
+            .iter()
+            .map(|path| ShareInput::open(path))
+            .collect::<Result<Vec<_>>>()?,
+    )?;
+    let Some(split) = shares
+        .iter()
+        .filter_map(|share| share.header.as_ref())
+        .find(|header| header.one_time_pad.is_some())
+        .cloned()
+    else {
+        return Ok(shares);
+    };
+    for share in shares.iter_mut().filter(|share| share.header.is_none()) {
+        *share = ShareInput::open_one_time_pad(&share.path, &split)?;
+    }
+    Ok(shares)
+}
 
-    let mut xor1_buf = vec![0u8; CHUNK_SIZE];
-    let mut xor2_buf = vec![0u8; CHUNK_SIZE];
-    let mut out_buf = vec![0u8; CHUNK_SIZE];
+/// Open the shares to combine and pick how to combine them.
+fn resolve_shares(input_paths: &[PathBuf]) -> Result<(Vec<ShareInput>, Combiner)> {
+    if input_paths.is_empty() {
+        bail!("no share files given to combine");
+    }
+    let mut given = open_shares(input_paths)?;
+
+    // Go by a real share, as a one-time pad has no header of its own.
+    let first_at = given
+        .iter()
+        .position(|share| !share.is_one_time_pad())
+        .unwrap_or(0);
+    let first = given.remove(first_at);
+    // A share joined from sub-shares goes by the name it had before.
+    let first_path = if first.is_joined() {
+        resplit::whole_share_path(&first.path)
+    } else {
+        first.path.clone()
+    };
 
-    loop {
-        let n1 = read_exact_or_eof(&mut xor1_reader, &mut xor1_buf)?;
-        let n2 = read_exact_or_eof(&mut xor2_reader, &mut xor2_buf)?;
+    let header = match &first.header {
+        Some(header) => header.clone(),
+        None => return resolve_legacy_pair(first, input_paths),
+    };
 
-        if n1 != n2 {
-            bail!("unexpected read size mismatch during combine");
+    let candidates: Vec<PathBuf> = if given.iter().all(ShareInput::is_one_time_pad) {
+        let prefix = header.scheme.extension_prefix();
+        if share_index(&first_path, prefix).is_none() {
+            find_partners_by_id(&first_path, &header)?
+        } else {
+            // Share 1 of a one-time pad split is the pad, not a file.
+            let first_index = if header.one_time_pad.is_some() { 2 } else { 1 };
+            (first_index..=header.count)
+                .map(|i| first_path.with_extension(format!("{}{}", prefix, i)))
+                .filter(|path| *path != first_path)
+                .filter(|path| header.scheme == SchemeKind::Xor || resplit::share_exists(path))
+                .collect()
         }
-        if n1 == 0 {
+    } else {
+        Vec::new()
+    };
+    let candidates = given.into_iter().map(Ok).chain(
+        candidates
+            .into_iter()
+            .map(|path| resplit::open_share_tree(&path)),
+    );
+
+    let needed = header.threshold as usize;
+    let mut shares = vec![first];
+    for share in candidates {
+        if shares.len() == needed {
             break;
         }
+        let share = share?;
+        let index = share.header.as_ref().map(|h| h.index);
+        if shares
+            .iter()
+            .any(|s| s.header.as_ref().map(|h| h.index) == index)
+        {
+            continue;
+        }
+        shares.push(share);
+        check_same_split(&shares)?;
+    }
 
-        xor_buffers(&xor1_buf[..n1], &xor2_buf[..n1], &mut out_buf[..n1]);
-
-        writer
-            .write_all(&out_buf[..n1])
-            .context("failed to write to output file")?;
+    if header.one_time_pad.is_some() && !shares.iter().any(ShareInput::is_one_time_pad) {
+        bail!(
+            "split {} took share 1 from a one-time pad; give the pad file with -i as well",
+            header.split_id_hex()
+        );
+    }
+    if shares.len() < needed {
+        bail!(
+            "need {} shares to combine but only found {}",
+            needed,
+            shares.len()
+        );
     }
+    fill_pad_len(&mut shares)?;
 
-    writer.flush().context("failed to flush output file")?;
+    let combiner = match header.scheme {
+        SchemeKind::Xor => Combiner::Xor,
+        SchemeKind::Threshold => {
+            Combiner::Threshold(shamir::Interpolator::new(&share_xs(&shares)?)?)
+        }
+    };
 
-    Ok(output_path)
+    Ok((shares, combiner))
 }
 
-fn resolve_xor_pair(input_path: &Path) -> Result<(PathBuf, PathBuf)> {
-    let ext = input_path
-        .extension()
-        .and_then(|e| e.to_str())
-        .unwrap_or("");
+/// Find the other shares of a split in the directory of `first_path` by
+/// their split ID, for shares whose names do not give them away.
+///
+/// Sub-shares are not partners; they are only found by name, or given.
+fn find_partners_by_id(first_path: &Path, header: &ShareHeader) -> Result<Vec<PathBuf>> {
+    let dir = match first_path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+    let entries =
+        std::fs::read_dir(dir).with_context(|| format!("failed to list {}", dir.display()))?;
 
-    let (xor1_path, xor2_path) = match ext {
-        "xor1" => {
-            let xor2 = input_path.with_extension("xor2");
-            (input_path.to_path_buf(), xor2)
+    let mut partners = Vec::new();
+    for entry in entries {
+        let entry = entry.with_context(|| format!("failed to list {}", dir.display()))?;
+        if !entry.file_type().is_ok_and(|kind| kind.is_file()) {
+            continue;
         }
-        "xor2" => {
-            let xor1 = input_path.with_extension("xor1");
-            (xor1, input_path.to_path_buf())
+        let path = entry.path();
+        if path.file_name() == first_path.file_name() {
+            continue;
+        }
+        // Files that are not shares, or cannot be read, are not partners.
+        if let Ok(Some(other)) = read_share_header(&path)
+            && other.split_id == header.split_id
+            && other.sub_share.is_empty()
+        {
+            partners.push(path);
         }
+    }
+    partners.sort();
+
+    // Share 1 of a one-time pad split is the pad, which has no header.
+    let stored = header.count as usize - usize::from(header.one_time_pad.is_some());
+    if header.scheme == SchemeKind::Xor && partners.len() + 1 < stored {
+        bail!(
+            "found {} of the {} shares of split {} next to {}; pass every share with -i",
+            partners.len() + 1,
+            stored,
+            header.split_id_hex(),
+            first_path.display()
+        );
+    }
+    Ok(partners)
+}
+
+/// Resolve a headerless `.xor1`/`.xor2` pair written by splinch 0.2.
+fn resolve_legacy_pair(
+    first: ShareInput,
+    input_paths: &[PathBuf],
+) -> Result<(Vec<ShareInput>, Combiner)> {
+    let partner_path = match input_paths {
+        [_] => {
+            let pair = resolve_xor_pair(&first.path)?;
+            if pair.0 == first.path { pair.1 } else { pair.0 }
+        }
+        [_, partner] => partner.clone(),
         _ => bail!(
-            "input file must have .xor1 or .xor2 extension, got: {}",
+            "legacy raw shares come in pairs, got {} files",
+            input_paths.len()
+        ),
+    };
+
+    let shares = vec![first, ShareInput::open(&partner_path)?];
+    check_same_split(&shares)?;
+
+    Ok((shares, Combiner::Xor))
+}
+
+fn resolve_xor_pair(input_path: &Path) -> Result<(PathBuf, PathBuf)> {
+    let (xor1_path, xor2_path) = match share_index(input_path, "xor") {
+        Some(1) => (input_path.to_path_buf(), input_path.with_extension("xor2")),
+        Some(2) => (input_path.with_extension("xor1"), input_path.to_path_buf()),
+        _ => bail!(
+            "legacy raw share must have .xor1 or .xor2 extension, got: {}",
             input_path.display()
         ),
     };
@@ -324,15 +2029,6 @@ fn resolve_xor_pair(input_path: &Path) -> Result<(PathBuf, PathBuf)> {
     Ok((xor1_path, xor2_path))
 }
 
-fn strip_xor_extension(path: &Path) -> Result<PathBuf> {
-    let ext = path.extension().and_then(|e| e.to_str()).unwrap_or("");
-
-    match ext {
-        "xor1" | "xor2" => Ok(path.with_extension("")),
-        _ => bail!("expected .xor1 or .xor2 extension, got: {}", path.display()),
-    }
-}
-
 fn resolve_output_path(base_path: &Path) -> PathBuf {
     if !base_path.exists() {
         return base_path.to_path_buf();
@@ -356,12 +2052,25 @@ fn resolve_output_path(base_path: &Path) -> PathBuf {
     }
 }
 
+/// Write `len` bytes from `random` to `writer`.
+fn write_random(writer: &mut impl Write, len: u64, random: &mut dyn RandomSource) -> Result<()> {
+    let mut rand_buf = vec![0u8; CHUNK_SIZE];
+    let mut remaining = len;
+    while remaining > 0 {
+        let to_write = remaining.min(CHUNK_SIZE as u64) as usize;
+        random.fill(&mut rand_buf[..to_write])?;
+        writer.write_all(&rand_buf[..to_write])?;
+        remaining -= to_write as u64;
+    }
+    Ok(())
+}
+
 /// Securely delete a file by overwriting it with random bytes, then removing it.
 ///
-/// Each pass overwrites the entire file with cryptographically random data
-/// and flushes to physical storage with `sync_all()`. After all passes,
-/// the file is removed from the filesystem.
-pub fn secure_delete(path: &Path, passes: u32) -> Result<()> {
+/// Each pass overwrites the entire file with bytes from `random` and
+/// flushes to physical storage with `sync_all()`. After all passes, the file
+/// is removed from the filesystem.
+pub fn secure_delete(path: &Path, passes: u32, random: &mut dyn RandomSource) -> Result<()> {
     let file_size = std::fs::metadata(path)
         .with_context(|| format!("failed to read metadata for {}", path.display()))?
         .len();
@@ -371,21 +2080,12 @@ pub fn secure_delete(path: &Path, passes: u32) -> Result<()> {
         .open(path)
         .with_context(|| format!("failed to open {} for writing", path.display()))?;
 
-    let mut rand_buf = vec![0u8; CHUNK_SIZE];
-
     for pass in 1..=passes {
         file.seek(SeekFrom::Start(0))
             .with_context(|| format!("failed to seek in {} (pass {})", path.display(), pass))?;
 
-        let mut remaining = file_size;
-        while remaining > 0 {
-            let to_write = remaining.min(CHUNK_SIZE as u64) as usize;
-            rng().fill_bytes(&mut rand_buf[..to_write]);
-            file.write_all(&rand_buf[..to_write]).with_context(|| {
-                format!("failed to overwrite {} (pass {})", path.display(), pass)
-            })?;
-            remaining -= to_write as u64;
-        }
+        write_random(&mut file, file_size, random)
+            .with_context(|| format!("failed to overwrite {} (pass {})", path.display(), pass))?;
 
         file.sync_all()
             .with_context(|| format!("failed to sync {} (pass {})", path.display(), pass))?;
@@ -396,3 +2096,173 @@ pub fn secure_delete(path: &Path, passes: u32) -> Result<()> {
 
     Ok(())
 }
+
+/// A fresh, empty directory for the files of the test `name`.
+#[cfg(test)]
+fn test_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("splinch-{}-{}", name, std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Split `content`, written to `dir/name`, into `shares` XOR shares.
+    fn split_xor_file(
+        dir: &Path,
+        name: &str,
+        content: &[u8],
+        shares: usize,
+        options: &SplitOptions,
+        seed: u64,
+    ) -> Vec<PathBuf> {
+        let input = dir.join(name);
+        std::fs::write(&input, content).unwrap();
+        split_file(
+            input.as_path(),
+            shares,
+            options,
+            &mut SeededRandom::from_u64(seed),
+        )
+        .unwrap()
+        .share_paths
+    }
+
+    /// XOR the payloads of `shares` together and run the MAC over the
+    /// result, with the key recombined from the shares' headers.
+    fn recombined_tag(shares: &mut [ShareInput], flip: Option<usize>) -> [u8; mac::TAG_LEN] {
+        let key = recover_mac_key(shares, &Combiner::Xor).expect("split has a MAC");
+        let mut payload = Vec::new();
+        for share in shares.iter_mut() {
+            let mut bytes = Vec::new();
+            let mut buf = vec![0u8; CHUNK_SIZE];
+            loop {
+                let n = share.read_payload(&mut buf).unwrap();
+                if n == 0 {
+                    break;
+                }
+                bytes.extend_from_slice(&buf[..n]);
+            }
+            if payload.is_empty() {
+                payload = bytes;
+            } else {
+                xor_into(&mut payload, &bytes);
+            }
+        }
+        if let Some(at) = flip {
+            payload[at] ^= 0x01;
+        }
+        let mut mac = mac::StreamMac::new(&key);
+        mac.update(&payload);
+        mac.finalize()
+    }
+
+    #[test]
+    fn recombined_mac_key_verifies_the_tag() {
+        let dir = test_dir("mac-round-trip");
+        let options = SplitOptions {
+            mac: true,
+            ..SplitOptions::default()
+        };
+        let content: Vec<u8> = (0..200_000u32).map(|i| (i % 251) as u8).collect();
+        let paths = split_xor_file(&dir, "secret", &content, 3, &options, 1);
+
+        let mut shares = open_shares(&paths).unwrap();
+        let tag = recombined_tag(&mut shares, None);
+        assert!(check_tag(&shares, &tag).is_ok());
+
+        let mut shares = open_shares(&paths).unwrap();
+        let tag = recombined_tag(&mut shares, Some(150_000));
+        let err = check_tag(&shares, &tag).unwrap_err();
+        assert!(err.to_string().contains("authentication failed"), "{}", err);
+
+        let output = dir.join("restored");
+        combine_files_to(&paths, &output).unwrap();
+        assert_eq!(std::fs::read(&output).unwrap(), content);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn a_share_from_another_split_fails_the_mac() {
+        let dir = test_dir("mac-swapped");
+        let options = SplitOptions {
+            mac: true,
+            ..SplitOptions::default()
+        };
+        let content = b"the same file, split twice".repeat(100);
+        let (first_dir, second_dir) = (dir.join("first"), dir.join("second"));
+        std::fs::create_dir(&first_dir).unwrap();
+        std::fs::create_dir(&second_dir).unwrap();
+        let first = split_xor_file(&first_dir, "secret", &content, 2, &options, 1);
+        let second = split_xor_file(&second_dir, "secret", &content, 2, &options, 2);
+
+        // Combining refuses the mix outright, by split ID.
+        let mixed = vec![first[0].clone(), second[1].clone()];
+        assert!(combine_files_to(&mixed, &dir.join("mixed")).is_err());
+
+        // Past that check, the swapped share still fails the MAC.
+        let mut shares = open_shares(&mixed).unwrap();
+        let tag = recombined_tag(&mut shares, None);
+        let err = check_tag(&shares, &tag).unwrap_err();
+        assert!(err.to_string().contains("authentication failed"), "{}", err);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn shares_never_overwrite_the_input_or_an_existing_file() {
+        let dir = test_dir("no-overwrite");
+        let input = dir.join("secret");
+        std::fs::write(&input, b"keep me").unwrap();
+
+        let onto_input = SplitOptions {
+            output_paths: vec![dir.join("part"), dir.join(".").join("secret")],
+            ..SplitOptions::default()
+        };
+        let err = split_file(&input, 2, &onto_input, &mut SeededRandom::from_u64(1)).unwrap_err();
+        assert!(err.to_string().contains("is the input"), "{:#}", err);
+        assert_eq!(std::fs::read(&input).unwrap(), b"keep me");
+
+        std::fs::write(dir.join("secret.xor2"), b"an older share").unwrap();
+        let options = SplitOptions::default();
+        assert!(split_file(&input, 2, &options, &mut SeededRandom::from_u64(1)).is_err());
+        assert_eq!(
+            std::fs::read(dir.join("secret.xor2")).unwrap(),
+            b"an older share"
+        );
+        assert!(!dir.join("secret.xor1").exists());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn a_failed_share_takes_back_the_finished_ones() {
+        let dir = test_dir("finish-failure");
+        let options = SplitOptions {
+            share_commands: vec![
+                "cat > /dev/null; exit 3".to_string(),
+                format!("cat > {}/delivered", dir.display()),
+            ],
+            ..SplitOptions::default()
+        };
+        let input = dir.join("secret");
+        std::fs::write(&input, b"all or nothing").unwrap();
+        let err = split_file(&input, 2, &options, &mut SeededRandom::from_u64(1)).unwrap_err();
+        let message = format!("{:#}", err);
+        assert!(message.contains("exit status: 3"), "{}", message);
+        assert!(message.contains("already delivered"), "{}", message);
+
+        // Share files that were finished are deleted.
+        let paths = split_xor_file(&dir, "other", b"content", 2, &SplitOptions::default(), 2);
+        let shares = open_shares(&paths).unwrap();
+        let path = dir.join("finished");
+        let written = ShareOutput::create(&path, shares[0].header.as_ref().unwrap(), false)
+            .unwrap()
+            .finish(None)
+            .unwrap();
+        assert!(written.discard().ends_with("complete, deleted"));
+        assert!(!path.exists());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -1,29 +1,55 @@
-use std::path::PathBuf;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
 use std::process;
 
 use anyhow::{Result, bail};
-use clap::Parser;
-use splinch_rs::{combine_files, secure_delete, split_file, verify_files};
+use clap::{Parser, Subcommand};
+use splinch_rs::{
+    AUDIT_ALPHA, Compression, ManifestStatus, PROPORTION_CUTOFF, PROPORTION_WINDOW, PadReuse,
+    Padding, REPETITION_CUTOFF, RandomSource, Randomness, RefreshOptions, ResplitOptions,
+    SplitOptions, SplitShares, Verdict, audit_share, check_manifest, check_pad_registry,
+    check_share, combine_files, combine_files_to, combine_to_writer, destroy_pads, generate_pad,
+    generate_pad_book, pad_usage, parse_size, refresh_shares, resolve_split_header, resplit_share,
+    secure_delete, shared_device, split_file, split_file_threshold, split_reader,
+    split_reader_threshold, verify_files,
+};
 
 #[derive(Parser)]
 #[command(
     name = "splinch",
     version,
-    about = "Split a file into two XOR-complementary parts for secure transport, or combine them back"
+    about = "Split a file into two XOR-complementary parts for secure transport, or combine them back",
+    args_conflicts_with_subcommands = true,
+    subcommand_negates_reqs = true
 )]
 struct Cli {
-    /// Path to the input file to split or a .xor1/.xor2 file to combine
-    #[arg(short = 'i', long = "input")]
-    input: PathBuf,
+    #[command(subcommand)]
+    command: Option<Commands>,
+
+    /// Path to the input file to split ("-" for stdin) or a .xorN/.sssN file to combine (repeat to list shares)
+    #[arg(
+        short = 'i',
+        long = "input",
+        required_unless_present = "check_manifest"
+    )]
+    input: Vec<PathBuf>,
 
     /// Verify the split files against the original after splitting
     #[arg(short = 'v', long = "verify")]
     verify: bool,
 
-    /// Combine two XOR files back into the original
+    /// Combine XOR files back into the original
     #[arg(short = 'c', long = "combine")]
     combine: bool,
 
+    /// Number of shares to split into (default: 2)
+    #[arg(short = 'n', long = "shares", default_value_t = 2)]
+    shares: usize,
+
+    /// Use Shamir threshold sharing: any K of the shares rebuild the file
+    #[arg(short = 'k', long = "threshold", value_name = "K")]
+    threshold: Option<usize>,
+
     /// Securely delete the original file after splitting (overwrite with random data)
     #[arg(short = 's', long = "secure-delete")]
     secure_delete: bool,
@@ -31,26 +57,293 @@ struct Cli {
     /// Number of overwrite passes for secure delete (default: 1)
     #[arg(short = 'p', long = "passes", default_value_t = 1)]
     passes: u32,
+
+    /// Add a one-time MAC so that combining detects damaged or tampered shares
+    #[arg(short = 'm', long = "mac")]
+    mac: bool,
+
+    /// Check the block checksums of share files and report corrupt blocks
+    #[arg(long = "check")]
+    check: bool,
+
+    /// Write <input>.sha256, a sha256sum-compatible manifest of the shares
+    #[arg(long = "manifest")]
+    manifest: bool,
+
+    /// Check received shares against a manifest (combine afterwards with -c)
+    #[arg(long = "check-manifest", value_name = "FILE")]
+    check_manifest: Option<PathBuf>,
+
+    /// Stream each share into a shell command instead of a file (repeat once per share)
+    #[arg(long = "share-cmd", value_name = "CMD")]
+    share_cmd: Vec<String>,
+
+    /// Split: path for each share, repeated once per share. Combine: path for the output ("-" for stdout)
+    #[arg(short = 'o', long = "output", value_name = "PATH")]
+    output: Vec<PathBuf>,
+
+    /// Directory for the shares: give once for all shares or once per share
+    #[arg(short = 'd', long = "output-dir", value_name = "DIR")]
+    output_dir: Vec<PathBuf>,
+
+    /// Split a directory tree (names, permissions, mtimes, symlinks) as a streamed archive
+    #[arg(short = 'r', long = "recursive")]
+    recursive: bool,
+
+    /// Refuse to split when every share would land on the same device
+    #[arg(long = "strict-devices")]
+    strict_devices: bool,
+
+    /// Give the shares random names; combining restores the original name
+    #[arg(long = "random-names")]
+    random_names: bool,
+
+    /// Encrypt the input with XChaCha20-Poly1305 into one <input>.enc and split only the key
+    #[arg(long = "hybrid")]
+    hybrid: bool,
+
+    /// Make the first share a 256-bit seed for a ChaCha20 pad (computational security only)
+    #[arg(long = "seeded-pad")]
+    seeded_pad: bool,
+
+    /// Take the first share from a pre-shared one-time pad file, tracked in <PAD>.ledger, and do not write it
+    #[arg(long = "one-time-pad", value_name = "PAD")]
+    one_time_pad: Option<PathBuf>,
+
+    /// Where random bytes come from: os, a device or file such as /dev/hwrng, or seed:HEX (tests only)
+    #[arg(long = "random-source", value_name = "SOURCE")]
+    random_source: Option<Randomness>,
+
+    /// Allow a seed:HEX random source, whose bytes anyone who knows the seed can reproduce
+    #[arg(long = "insecure-deterministic")]
+    insecure_deterministic: bool,
+
+    /// Record pad fingerprints in this registry, and warn about pads it has seen in another split
+    #[arg(long = "pad-registry", value_name = "FILE")]
+    pad_registry: Option<PathBuf>,
+
+    /// Report the SP 800-90B health test statistics of the random bytes after splitting
+    #[arg(long = "health-report")]
+    health_report: bool,
+
+    /// Compress the input before splitting: none, zstd or deflate (smaller shares, but the size leaks more)
+    #[arg(long = "compress", value_name = "ALGO")]
+    compress: Option<Compression>,
+
+    /// Pad the shares to hide the input's size: pow2, block:N or a target size such as 64M
+    #[arg(long = "pad", value_name = "MODE")]
+    pad: Option<Padding>,
+
+    /// Write the combined file to stdout instead of next to the shares
+    #[arg(long = "stdout")]
+    stdout: bool,
+}
+
+#[derive(Subcommand)]
+enum Commands {
+    /// Generate and manage pre-shared one-time pads for --one-time-pad
+    #[command(subcommand)]
+    Pad(PadCommand),
+
+    /// Run statistical randomness tests over share payloads and report p-values
+    Audit {
+        /// Share files, or pads, to test
+        #[arg(required = true)]
+        paths: Vec<PathBuf>,
+    },
+
+    /// Replace the shares of an XOR split with fresh ones for the same file, without combining them
+    Refresh {
+        /// Share files of the split; given one, the others are found as when combining
+        #[arg(required = true)]
+        shares: Vec<PathBuf>,
+
+        /// Path for each refreshed share, in share order (repeat; default: replace the shares)
+        #[arg(short = 'o', long = "output", value_name = "PATH")]
+        output: Vec<PathBuf>,
+
+        /// Securely delete the old shares once the refreshed ones are written
+        #[arg(short = 's', long = "secure-delete")]
+        secure_delete: bool,
+
+        /// Number of overwrite passes for secure delete (default: 1)
+        #[arg(short = 'p', long = "passes", default_value_t = 1)]
+        passes: u32,
+
+        /// Where the masks come from: os, a device or file, or seed:HEX (tests only)
+        #[arg(long = "random-source", value_name = "SOURCE")]
+        random_source: Option<Randomness>,
+
+        /// Allow a seed:HEX random source, whose bytes anyone who knows the seed can reproduce
+        #[arg(long = "insecure-deterministic")]
+        insecure_deterministic: bool,
+    },
+
+    /// Split one share again into sub-shares that must all be present to rebuild it
+    Resplit {
+        /// Share file, or sub-share, to split again
+        share: PathBuf,
+
+        /// Number of sub-shares (default: 2)
+        #[arg(short = 'n', long = "shares", default_value_t = 2)]
+        shares: usize,
+
+        /// Directory for the sub-shares: give once for all sub-shares or once per sub-share
+        #[arg(short = 'd', long = "output-dir", value_name = "DIR")]
+        output_dir: Vec<PathBuf>,
+
+        /// Securely delete the share once the sub-shares are written
+        #[arg(short = 's', long = "secure-delete")]
+        secure_delete: bool,
+
+        /// Number of overwrite passes for secure delete (default: 1)
+        #[arg(short = 'p', long = "passes", default_value_t = 1)]
+        passes: u32,
+
+        /// Where the sub-share pads come from: os, a device or file, or seed:HEX (tests only)
+        #[arg(long = "random-source", value_name = "SOURCE")]
+        random_source: Option<Randomness>,
+
+        /// Allow a seed:HEX random source, whose bytes anyone who knows the seed can reproduce
+        #[arg(long = "insecure-deterministic")]
+        insecure_deterministic: bool,
+    },
+}
+
+#[derive(Subcommand)]
+enum PadCommand {
+    /// Generate a pad file, or with --pages a directory of numbered pages, of random bytes
+    Generate {
+        /// Pad file, or pad book directory with --pages, to create
+        path: PathBuf,
+
+        /// Total pad size, such as 64M or 1G
+        #[arg(long = "size", value_name = "SIZE", value_parser = parse_size)]
+        size: u64,
+
+        /// Split the pad into this many numbered pages
+        #[arg(long = "pages", value_name = "N")]
+        pages: Option<usize>,
+
+        /// Where the pad bytes come from: os, a device or file such as /dev/hwrng, or seed:HEX (tests only)
+        #[arg(long = "random-source", value_name = "SOURCE")]
+        random_source: Option<Randomness>,
+
+        /// Allow a seed:HEX random source, whose bytes anyone who knows the seed can reproduce
+        #[arg(long = "insecure-deterministic")]
+        insecure_deterministic: bool,
+    },
+
+    /// List the used and remaining bytes of a pad or of each page of a pad book
+    List {
+        /// Pad file or pad book directory
+        path: PathBuf,
+    },
+
+    /// Securely delete the used pages of a pad book, or the given pad files
+    Destroy {
+        /// Pad book directory or pad files
+        #[arg(required = true)]
+        paths: Vec<PathBuf>,
+
+        /// Number of overwrite passes (default: 1)
+        #[arg(short = 'p', long = "passes", default_value_t = 1)]
+        passes: u32,
+
+        /// Where the overwrite bytes come from: os, a device or file, or seed:HEX (tests only)
+        #[arg(long = "random-source", value_name = "SOURCE")]
+        random_source: Option<Randomness>,
+
+        /// Allow a seed:HEX random source, whose bytes anyone who knows the seed can reproduce
+        #[arg(long = "insecure-deterministic")]
+        insecure_deterministic: bool,
+    },
+}
+
+impl Cli {
+    /// Whether the combined file goes to stdout.
+    fn writes_stdout(&self) -> bool {
+        self.stdout || matches!(self.output.as_slice(), [path] if is_stdin(path))
+    }
+}
+
+/// Print a progress message, to stderr when stdout carries the combined file.
+macro_rules! status {
+    ($cli:expr, $($arg:tt)*) => {
+        if $cli.writes_stdout() {
+            eprintln!($($arg)*);
+        } else {
+            println!($($arg)*);
+        }
+    };
+}
+
+/// Base name for the shares of a split read from stdin.
+const STDIN_BASE: &str = "stdin";
+
+/// Whether `path` is "-", meaning stdin or stdout.
+fn is_stdin(path: &Path) -> bool {
+    path.as_os_str() == "-"
+}
+
+fn single_input(cli: &Cli) -> Result<&PathBuf> {
+    match cli.input.as_slice() {
+        [input] => Ok(input),
+        _ => bail!("exactly one --input is required here"),
+    }
 }
 
 fn run_split(cli: &Cli) -> Result<()> {
-    let metadata = std::fs::metadata(&cli.input);
+    if cli.stdout {
+        bail!("--stdout can only be used with --combine");
+    }
+    let input = single_input(cli)?;
+    check_seeded(cli.random_source.as_ref(), cli.insecure_deterministic)?;
+    if cli.verify && !cli.share_cmd.is_empty() {
+        bail!("--verify cannot be used with --share-cmd; the shares are not kept locally");
+    }
+    if !cli.share_cmd.is_empty() && (!cli.output.is_empty() || !cli.output_dir.is_empty()) {
+        bail!("--output and --output-dir cannot be used with --share-cmd");
+    }
+    if cli.output.iter().any(|path| is_stdin(path)) {
+        bail!("shares cannot be written to stdout; use --share-cmd to pipe them");
+    }
+    if is_stdin(input) {
+        if cli.recursive {
+            bail!("--recursive cannot be used when splitting stdin");
+        }
+        return run_split_stdin(cli);
+    }
+
+    let metadata = std::fs::metadata(input);
     match &metadata {
-        Ok(m) if !m.is_file() => bail!("{} is not a regular file", cli.input.display()),
-        Err(e) => bail!("cannot access {}: {}", cli.input.display(), e),
+        Ok(m) if m.is_dir() => return run_split_dir(cli, input),
+        Ok(m) if !m.is_file() => bail!("{} is not a regular file", input.display()),
+        Err(e) => bail!("cannot access {}: {}", input.display(), e),
         _ => {}
     }
+    if cli.recursive {
+        bail!("--recursive needs a directory to split");
+    }
 
     let file_size = metadata.unwrap().len();
-    println!("Splitting {} ({} bytes)...", cli.input.display(), file_size);
+    println!("Splitting {} ({} bytes)...", input.display(), file_size);
 
-    let (xor1, xor2) = split_file(&cli.input)?;
-    println!("Created: {}", xor1.display());
-    println!("Created: {}", xor2.display());
+    let options = split_options(cli);
+    let mut random = open_random(cli.random_source.as_ref())?;
+    let split = match cli.threshold {
+        Some(threshold) => {
+            split_file_threshold(input, threshold, cli.shares, &options, random.as_mut())?
+        }
+        None => split_file(input, cli.shares, &options, random.as_mut())?,
+    };
+    report_split(cli, &split)?;
 
     if cli.verify {
         print!("Verifying... ");
-        let ok = verify_files(&cli.input, &xor1, &xor2)?;
+        let mut shares = split.share_paths.clone();
+        shares.extend(cli.one_time_pad.clone());
+        let ok = verify_files(input, &shares, random.as_mut())?;
         if ok {
             println!("OK");
         } else {
@@ -62,16 +355,195 @@ fn run_split(cli: &Cli) -> Result<()> {
     if cli.secure_delete {
         println!(
             "Securely deleting {} ({} pass(es))...",
-            cli.input.display(),
+            input.display(),
             cli.passes
         );
-        secure_delete(&cli.input, cli.passes)?;
+        secure_delete(input, cli.passes, random.as_mut())?;
         println!("Deleted.");
     }
 
     Ok(())
 }
 
+/// Split a directory tree, streamed through the split as an archive.
+fn run_split_dir(cli: &Cli, input: &Path) -> Result<()> {
+    if !cli.recursive {
+        bail!(
+            "{} is a directory; use --recursive to split it",
+            input.display()
+        );
+    }
+    if cli.verify {
+        bail!("--verify cannot be used when splitting a directory");
+    }
+    if cli.secure_delete {
+        bail!("--secure-delete cannot be used when splitting a directory");
+    }
+
+    println!("Splitting directory {}...", input.display());
+
+    let options = split_options(cli);
+    let mut random = open_random(cli.random_source.as_ref())?;
+    let split = match cli.threshold {
+        Some(threshold) => {
+            split_file_threshold(input, threshold, cli.shares, &options, random.as_mut())?
+        }
+        None => split_file(input, cli.shares, &options, random.as_mut())?,
+    };
+    report_split(cli, &split)
+}
+
+/// Split stdin into shares named `stdin.xorN` (or `.sssN`) in the current
+/// directory. The plaintext is never written to disk.
+fn run_split_stdin(cli: &Cli) -> Result<()> {
+    if cli.verify {
+        bail!("--verify cannot be used when splitting stdin");
+    }
+    if cli.secure_delete {
+        bail!("--secure-delete cannot be used when splitting stdin");
+    }
+
+    println!("Splitting stdin...");
+
+    let base = Path::new(STDIN_BASE);
+    let options = split_options(cli);
+    let mut random = open_random(cli.random_source.as_ref())?;
+    let mut stdin = io::stdin().lock();
+    let split = match cli.threshold {
+        Some(threshold) => split_reader_threshold(
+            &mut stdin,
+            base,
+            threshold,
+            cli.shares,
+            &options,
+            random.as_mut(),
+        )?,
+        None => split_reader(&mut stdin, base, cli.shares, &options, random.as_mut())?,
+    };
+    report_split(cli, &split)
+}
+
+fn split_options(cli: &Cli) -> SplitOptions {
+    SplitOptions {
+        mac: cli.mac,
+        manifest: cli.manifest,
+        share_commands: cli.share_cmd.clone(),
+        output_paths: cli.output.clone(),
+        output_dirs: cli.output_dir.clone(),
+        strict_devices: cli.strict_devices,
+        random_names: cli.random_names,
+        padding: cli.pad.unwrap_or_default(),
+        compression: cli.compress.unwrap_or_default(),
+        hybrid: cli.hybrid,
+        seeded_pad: cli.seeded_pad,
+        one_time_pad: cli.one_time_pad.clone(),
+        pad_registry: cli.pad_registry.clone(),
+    }
+}
+
+/// Open the random source to use, the OS by default. A command opens it
+/// once and draws every random byte it needs from it.
+fn open_random(source: Option<&Randomness>) -> Result<Box<dyn RandomSource>> {
+    source.cloned().unwrap_or_default().open()
+}
+
+/// Refuse a seeded `source`, which makes every random byte reproducible,
+/// unless `insecure_deterministic` allows it, and warn when it does.
+fn check_seeded(source: Option<&Randomness>, insecure_deterministic: bool) -> Result<()> {
+    if matches!(source, Some(Randomness::Seeded(_))) {
+        if !insecure_deterministic {
+            bail!(
+                "a seed: random source is reproducible, so anyone who knows the seed can \
+                 rebuild every pad; it is for tests only and needs --insecure-deterministic"
+            );
+        }
+        eprintln!("Warning: a seeded random source is reproducible; use it for tests only.");
+    }
+    Ok(())
+}
+
+fn report_split(cli: &Cli, split: &SplitShares) -> Result<()> {
+    if cli.share_cmd.is_empty() {
+        for path in &split.share_paths {
+            println!("Created: {}", path.display());
+        }
+    } else {
+        for (path, command) in split.share_paths.iter().zip(&cli.share_cmd) {
+            println!("Sent: {} to `{}`", path.display(), command);
+        }
+    }
+    if let Some(path) = &split.ciphertext_path {
+        println!("Ciphertext: {}", path.display());
+    }
+    if let (Some(pad), Some(range)) = (&cli.one_time_pad, &split.one_time_pad_range) {
+        println!(
+            "One-time pad: {} bytes {}..{} (share 1, not written)",
+            pad.display(),
+            range.start,
+            range.end
+        );
+    }
+    println!("Split ID: {}", split.split_id_hex());
+    for path in &split.manifest_paths {
+        println!("Manifest: {}", path.display());
+    }
+    if cli.health_report {
+        let health = &split.health;
+        println!(
+            "Health tests (NIST SP 800-90B): passed on {} random bytes",
+            health.samples
+        );
+        println!(
+            "  Repetition count: longest run {} (cutoff {})",
+            health.longest_run, REPETITION_CUTOFF
+        );
+        println!(
+            "  Adaptive proportion: at most {} in {} windows of {} bytes (cutoff {})",
+            health.max_proportion, health.windows, PROPORTION_WINDOW, PROPORTION_CUTOFF
+        );
+    }
+    warn_pad_reuse(&split.pad_reuse);
+    if cli.seeded_pad {
+        eprintln!(
+            "Warning: {} holds only a 256-bit seed for a ChaCha20 pad. This split is \
+             computationally secure, not information-theoretically secure: the other \
+             shares are only as hidden as ChaCha20 is strong.",
+            split.share_paths[0].display()
+        );
+    }
+    let stored_paths: Vec<PathBuf> = split
+        .share_paths
+        .iter()
+        .chain(&cli.one_time_pad)
+        .cloned()
+        .collect();
+    if cli.share_cmd.is_empty()
+        && let Some(device) = shared_device(&stored_paths)?
+    {
+        eprintln!(
+            "Warning: every share is on the same device ({}). Move them to separate \
+             devices before sending them, or use --output-dir.",
+            device
+        );
+    }
+    Ok(())
+}
+
+/// Warn about pads that the pad registry has seen in another split.
+fn warn_pad_reuse(reuse: &[PadReuse]) {
+    for pad in reuse {
+        eprintln!(
+            "Warning: the pad in {} (share {}) was already used by split {} (share {}). \
+             XOR-ing the shares of the two splits reveals the XOR of both originals; \
+             treat both as exposed.",
+            pad.share.display(),
+            pad.index,
+            pad.earlier_split_id,
+            pad.earlier_index
+        );
+    }
+}
+
 fn run_combine(cli: &Cli) -> Result<()> {
     if cli.verify {
         bail!("--verify cannot be used with --combine");
@@ -82,26 +554,453 @@ fn run_combine(cli: &Cli) -> Result<()> {
     if cli.passes != 1 {
         bail!("--passes cannot be used with --combine");
     }
+    if cli.mac {
+        bail!(
+            "--mac cannot be used with --combine; a MAC is checked whenever the shares carry one"
+        );
+    }
+    if cli.manifest {
+        bail!("--manifest cannot be used with --combine");
+    }
+    if !cli.share_cmd.is_empty() {
+        bail!("--share-cmd cannot be used with --combine");
+    }
+    if cli.strict_devices {
+        bail!("--strict-devices cannot be used with --combine");
+    }
+    if cli.random_names {
+        bail!("--random-names cannot be used with --combine");
+    }
+    if cli.hybrid {
+        bail!(
+            "--hybrid cannot be used with --combine; hybrid shares are detected from the split metadata"
+        );
+    }
+    if cli.seeded_pad {
+        bail!(
+            "--seeded-pad cannot be used with --combine; a seeded share is detected from its header"
+        );
+    }
+    if cli.one_time_pad.is_some() {
+        bail!(
+            "--one-time-pad cannot be used with --combine; give the pad file with -i along with the shares"
+        );
+    }
+    if cli.random_source.is_some() || cli.insecure_deterministic {
+        bail!(
+            "--random-source and --insecure-deterministic cannot be used with --combine; \
+             combining draws no random bytes"
+        );
+    }
+    if cli.health_report {
+        bail!("--health-report cannot be used with --combine");
+    }
+    if cli.compress.is_some() {
+        bail!(
+            "--compress cannot be used with --combine; compressed shares are detected from the split metadata"
+        );
+    }
+    if cli.pad.is_some() {
+        bail!("--pad cannot be used with --combine; padding is trimmed automatically");
+    }
+    if cli.recursive {
+        bail!(
+            "--recursive cannot be used with --combine; directories are detected from the split metadata"
+        );
+    }
+    if !cli.output_dir.is_empty() {
+        bail!("--output-dir cannot be used with --combine; use --output for the combined file");
+    }
+    if cli.output.len() > 1 {
+        bail!("--combine takes a single --output");
+    }
+    if cli.stdout && !cli.output.is_empty() {
+        bail!("--stdout cannot be used with --output");
+    }
+    if cli.shares != 2 || cli.threshold.is_some() {
+        bail!(
+            "--shares and --threshold cannot be used with --combine; they are read from the share headers"
+        );
+    }
 
-    let metadata = std::fs::metadata(&cli.input);
-    match &metadata {
-        Ok(m) if !m.is_file() => bail!("{} is not a regular file", cli.input.display()),
-        Err(e) => bail!("cannot access {}: {}", cli.input.display(), e),
-        _ => {}
+    for input in &cli.input {
+        let metadata = std::fs::metadata(input);
+        match &metadata {
+            Ok(m) if !m.is_file() => bail!("{} is not a regular file", input.display()),
+            Err(e) => bail!("cannot access {}: {}", input.display(), e),
+            _ => {}
+        }
+    }
+
+    if let Some(registry) = &cli.pad_registry {
+        warn_pad_reuse(&check_pad_registry(registry, &cli.input)?);
+    }
+
+    status!(cli, "Combining from {}...", cli.input[0].display());
+
+    if cli.writes_stdout() {
+        let mut stdout = io::stdout().lock();
+        combine_to_writer(&cli.input, &mut stdout)?;
+        stdout.flush()?;
+        eprintln!("Restored to stdout");
+    } else if let [output_path] = cli.output.as_slice() {
+        combine_files_to(&cli.input, output_path)?;
+        println!("Restored: {}", output_path.display());
+    } else {
+        let output_path = combine_files(&cli.input)?;
+        println!("Restored: {}", output_path.display());
+    }
+    if resolve_split_header(&cli.input)?.is_some_and(|h| h.mac_key_share.is_some()) {
+        status!(cli, "Authenticated: one-time MAC matches");
+    }
+
+    Ok(())
+}
+
+fn run_check(cli: &Cli) -> Result<()> {
+    if cli.stdout || !cli.output.is_empty() || !cli.output_dir.is_empty() {
+        bail!("--stdout, --output and --output-dir cannot be used with --check");
+    }
+    if cli.combine {
+        bail!("--check cannot be used with --combine");
+    }
+    if cli.verify {
+        bail!("--verify cannot be used with --check");
+    }
+    if cli.secure_delete {
+        bail!("--secure-delete cannot be used with --check");
+    }
+
+    let mut damaged = false;
+    for input in &cli.input {
+        let check = check_share(input)?;
+        match check.block_len {
+            None => println!("{}: no block checksums to check", input.display()),
+            Some(_) if check.corrupt_blocks.is_empty() => {
+                println!("{}: OK ({} blocks)", input.display(), check.blocks)
+            }
+            Some(block_len) => {
+                damaged = true;
+                println!(
+                    "{}: {} of {} blocks corrupt",
+                    input.display(),
+                    check.corrupt_blocks.len(),
+                    check.blocks
+                );
+                for &index in &check.corrupt_blocks {
+                    let start = index * block_len;
+                    let end = (start + block_len).min(check.payload_len);
+                    println!("  block {} (share bytes {}..{})", index, start, end);
+                }
+            }
+        }
     }
 
-    println!("Combining from {}...", cli.input.display());
+    if damaged {
+        process::exit(1);
+    }
+
+    Ok(())
+}
+
+fn run_check_manifest(cli: &Cli, manifest: &Path) -> Result<()> {
+    if cli.check {
+        bail!("--check cannot be used with --check-manifest");
+    }
+    if !cli.combine && !cli.input.is_empty() {
+        bail!("--check-manifest checks the shares it lists; --input is only used with -c");
+    }
+    if cli.combine && cli.input.is_empty() {
+        bail!("-c with --check-manifest needs --input naming the shares to combine");
+    }
+
+    let mut bad = false;
+    let mut present = 0;
+    for (entry, status) in check_manifest(manifest)? {
+        match status {
+            ManifestStatus::Ok => {
+                present += 1;
+                status!(cli, "{}: OK", entry.file_name);
+            }
+            ManifestStatus::Missing => status!(cli, "{}: missing", entry.file_name),
+            ManifestStatus::WrongSize { expected, actual } => {
+                bad = true;
+                status!(
+                    cli,
+                    "{}: FAILED (size {} bytes, expected {})",
+                    entry.file_name,
+                    actual,
+                    expected
+                );
+            }
+            ManifestStatus::WrongHash => {
+                bad = true;
+                status!(cli, "{}: FAILED (SHA-256 mismatch)", entry.file_name);
+            }
+        }
+    }
+
+    if bad {
+        process::exit(1);
+    }
+    if present == 0 {
+        bail!(
+            "none of the shares listed in {} were found",
+            manifest.display()
+        );
+    }
 
-    let output_path = combine_files(&cli.input)?;
-    println!("Restored: {}", output_path.display());
+    if cli.combine {
+        run_combine(cli)?;
+    }
+    Ok(())
+}
 
+fn run_pad(command: &PadCommand) -> Result<()> {
+    match command {
+        PadCommand::Generate {
+            path,
+            size,
+            pages,
+            random_source,
+            insecure_deterministic,
+        } => {
+            check_seeded(random_source.as_ref(), *insecure_deterministic)?;
+            let mut random = open_random(random_source.as_ref())?;
+            match pages {
+                Some(pages) => {
+                    let paths = generate_pad_book(path, *size, *pages, random.as_mut())?;
+                    println!(
+                        "Created pad book {} with {} pages ({} bytes)",
+                        path.display(),
+                        paths.len(),
+                        size
+                    );
+                }
+                None => {
+                    generate_pad(path, *size, random.as_mut())?;
+                    println!("Created pad {} ({} bytes)", path.display(), size);
+                }
+            }
+            println!("Copy it to the other party in person before using it with --one-time-pad.");
+        }
+        PadCommand::List { path } => {
+            let pages = pad_usage(path)?;
+            if pages.is_empty() {
+                bail!("{} has no pages", path.display());
+            }
+            for page in &pages {
+                println!(
+                    "{}: {} bytes, {} used, {} left",
+                    page.path.display(),
+                    page.len,
+                    page.used,
+                    page.remaining()
+                );
+            }
+            let remaining: u64 = pages.iter().map(|page| page.remaining()).sum();
+            let unused = pages.iter().filter(|page| page.used == 0).count();
+            println!(
+                "Remaining: {} bytes in {} of {} pages, {} of them unused",
+                remaining,
+                pages.iter().filter(|page| page.remaining() > 0).count(),
+                pages.len(),
+                unused
+            );
+        }
+        PadCommand::Destroy {
+            paths,
+            passes,
+            random_source,
+            insecure_deterministic,
+        } => {
+            check_seeded(random_source.as_ref(), *insecure_deterministic)?;
+            let mut random = open_random(random_source.as_ref())?;
+            for path in paths {
+                let destroyed = destroy_pads(path, *passes, random.as_mut())?;
+                if destroyed.is_empty() {
+                    println!("{}: no used pages to destroy", path.display());
+                }
+                for page in destroyed {
+                    println!("Destroyed: {}", page.display());
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+fn run_audit(paths: &[PathBuf]) -> Result<()> {
+    let mut failed = 0;
+    for path in paths {
+        let report = audit_share(path)?;
+        println!("{}: {} payload bytes", path.display(), report.bytes);
+        for test in &report.tests {
+            let verdict = match test.verdict {
+                Verdict::Pass => "pass",
+                Verdict::Fail => "FAIL",
+                Verdict::TooShort => "too short",
+            };
+            let p_value = test
+                .p_value
+                .map(|p| format!("p = {:.4}", p))
+                .unwrap_or_default();
+            let line = format!(
+                "  {:<20} {:<10} {:<12} {}",
+                test.name, verdict, p_value, test.statistic
+            );
+            println!("{}", line.trim_end());
+        }
+        if !report.passed() {
+            failed += 1;
+        }
+    }
+
+    if failed > 0 {
+        println!(
+            "{} of {} files failed a test at significance level {}. A random file fails one \
+             test in about 25 audits by chance; audit other shares before drawing conclusions.",
+            failed,
+            paths.len(),
+            AUDIT_ALPHA
+        );
+        process::exit(1);
+    }
+    Ok(())
+}
+
+fn run_refresh(
+    shares: &[PathBuf],
+    output: &[PathBuf],
+    secure_delete: bool,
+    passes: u32,
+    random_source: Option<&Randomness>,
+    insecure_deterministic: bool,
+) -> Result<()> {
+    check_seeded(random_source, insecure_deterministic)?;
+    let options = RefreshOptions {
+        output_paths: output.to_vec(),
+        secure_delete_passes: secure_delete.then_some(passes),
+    };
+    let refreshed = refresh_shares(shares, &options, open_random(random_source)?.as_mut())?;
+
+    let deleted = if secure_delete {
+        " (old share securely deleted)"
+    } else {
+        ""
+    };
+    for (old, new) in refreshed.old_paths.iter().zip(&refreshed.share_paths) {
+        if old == new {
+            println!("Refreshed: {}{}", new.display(), deleted);
+        } else {
+            println!(
+                "Refreshed: {} -> {}{}",
+                old.display(),
+                new.display(),
+                deleted
+            );
+        }
+    }
+    println!(
+        "Split ID: {} (was {})",
+        refreshed.split_id_hex(),
+        refreshed.old_split_id_hex()
+    );
+    if output.is_empty() && !secure_delete {
+        eprintln!(
+            "Warning: the old shares were replaced but not overwritten, so their contents \
+             may survive on disk. Use --secure-delete to overwrite them."
+        );
+    }
+    eprintln!(
+        "Note: manifests written for the old shares no longer match. The old shares still \
+         combine with each other, so destroy every copy of them."
+    );
+    Ok(())
+}
+
+fn run_resplit(
+    share: &Path,
+    shares: usize,
+    output_dir: &[PathBuf],
+    secure_delete: bool,
+    passes: u32,
+    random_source: Option<&Randomness>,
+    insecure_deterministic: bool,
+) -> Result<()> {
+    check_seeded(random_source, insecure_deterministic)?;
+    let options = ResplitOptions {
+        sub_shares: shares,
+        output_dirs: output_dir.to_vec(),
+        secure_delete_passes: secure_delete.then_some(passes),
+    };
+    let resplit = resplit_share(share, &options, open_random(random_source)?.as_mut())?;
+
+    for path in &resplit.sub_share_paths {
+        println!("Created: {}", path.display());
+    }
+    if secure_delete {
+        println!("Securely deleted: {}", share.display());
+    }
+    println!("Re-split ID: {}", resplit.split_id_hex());
+    if let Some(device) = shared_device(&resplit.sub_share_paths)? {
+        eprintln!(
+            "Warning: every sub-share is on the same device ({}). Move them to separate \
+             devices before handing them out, or use --output-dir.",
+            device
+        );
+    }
     Ok(())
 }
 
 fn run() -> Result<()> {
     let cli = Cli::parse();
 
-    if cli.combine {
+    if let Some(Commands::Pad(command)) = &cli.command {
+        run_pad(command)
+    } else if let Some(Commands::Audit { paths }) = &cli.command {
+        run_audit(paths)
+    } else if let Some(Commands::Refresh {
+        shares,
+        output,
+        secure_delete,
+        passes,
+        random_source,
+        insecure_deterministic,
+    }) = &cli.command
+    {
+        run_refresh(
+            shares,
+            output,
+            *secure_delete,
+            *passes,
+            random_source.as_ref(),
+            *insecure_deterministic,
+        )
+    } else if let Some(Commands::Resplit {
+        share,
+        shares,
+        output_dir,
+        secure_delete,
+        passes,
+        random_source,
+        insecure_deterministic,
+    }) = &cli.command
+    {
+        run_resplit(
+            share,
+            *shares,
+            output_dir,
+            *secure_delete,
+            *passes,
+            random_source.as_ref(),
+            *insecure_deterministic,
+        )
+    } else if let Some(manifest) = &cli.check_manifest {
+        run_check_manifest(&cli, manifest)
+    } else if cli.check {
+        run_check(&cli)
+    } else if cli.combine {
         run_combine(&cli)
     } else {
         run_split(&cli)
@@ -0,0 +1,131 @@
+//! Transport manifests listing each share's size and SHA-256.
+//!
+//! The manifest is a `sha256sum` checksum file, so recipients without
+//! splinch can check their share with `sha256sum -c`. Sizes and the split ID
+//! are carried on `#` comment lines, which `sha256sum` ignores:
+//!
+//! ```text
+//! # splinch manifest for split 5fb5967f3b4d6754508d6ee0d7b9a8ac
+//! # size 1048640 secret.pdf.xor1
+//! 3a7bd3e2360a3d29eea436fcfb7e44c735d117c42d1c1835420b6b9942dd4f1b  secret.pdf.xor1
+//! ```
+//!
+//! File names are relative to the directory holding the manifest.
+
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::path::Path;
+
+use anyhow::{Context, Result, bail};
+
+/// One share listed in a manifest.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ManifestEntry {
+    /// Share file name, relative to the manifest's directory.
+    pub file_name: String,
+    /// Share size in bytes, if the manifest records it.
+    pub size: Option<u64>,
+    pub sha256: [u8; 32],
+}
+
+/// Write a manifest listing `entries`.
+pub fn write_manifest(path: &Path, split_id: &str, entries: &[ManifestEntry]) -> Result<()> {
+    for entry in entries {
+        if entry.file_name.contains(['\n', '\r', '\\']) {
+            bail!(
+                "cannot list {:?} in a manifest: sha256sum cannot represent its name",
+                entry.file_name
+            );
+        }
+    }
+
+    let file =
+        File::create(path).with_context(|| format!("failed to create {}", path.display()))?;
+    let mut writer = BufWriter::new(file);
+
+    let mut contents = format!("# splinch manifest for split {}\n", split_id);
+    for entry in entries {
+        if let Some(size) = entry.size {
+            contents.push_str(&format!("# size {} {}\n", size, entry.file_name));
+        }
+        contents.push_str(&format!(
+            "{}  {}\n",
+            crate::to_hex(&entry.sha256),
+            entry.file_name
+        ));
+    }
+
+    writer
+        .write_all(contents.as_bytes())
+        .and_then(|()| writer.flush())
+        .with_context(|| format!("failed to write {}", path.display()))
+}
+
+/// Read the entries of a manifest written by splinch or `sha256sum`.
+pub fn read_manifest(path: &Path) -> Result<Vec<ManifestEntry>> {
+    let file = File::open(path).with_context(|| format!("failed to open {}", path.display()))?;
+
+    let mut sizes = HashMap::new();
+    let mut entries = Vec::new();
+
+    for (number, line) in BufReader::new(file).lines().enumerate() {
+        let line = line.with_context(|| format!("failed to read {}", path.display()))?;
+        let line = line.trim_end_matches('\r');
+
+        if let Some(comment) = line.strip_prefix('#') {
+            if let Some((size, name)) = comment
+                .trim_start()
+                .strip_prefix("size ")
+                .and_then(|rest| rest.split_once(' '))
+            {
+                let size: u64 = size
+                    .parse()
+                    .with_context(|| format!("{}:{}: invalid size", path.display(), number + 1))?;
+                sizes.insert(name.to_string(), size);
+            }
+            continue;
+        }
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let parsed = line.split_once(' ').and_then(|(hash, rest)| {
+            // sha256sum marks binary mode with '*' and text mode with ' '.
+            let name = rest.strip_prefix(['*', ' '])?;
+            Some((from_hex(hash)?, name))
+        });
+        let (sha256, file_name) = match parsed {
+            Some(parsed) => parsed,
+            None => bail!(
+                "{}:{}: not a sha256sum line: {}",
+                path.display(),
+                number + 1,
+                line
+            ),
+        };
+
+        entries.push(ManifestEntry {
+            file_name: file_name.to_string(),
+            size: None,
+            sha256,
+        });
+    }
+
+    for entry in &mut entries {
+        entry.size = sizes.get(&entry.file_name).copied();
+    }
+
+    Ok(entries)
+}
+
+pub fn from_hex(hex: &str) -> Option<[u8; 32]> {
+    if hex.len() != 64 || !hex.is_ascii() {
+        return None;
+    }
+    let mut out = [0u8; 32];
+    for (i, byte) in out.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(&hex[2 * i..2 * i + 2], 16).ok()?;
+    }
+    Some(out)
+}
@@ -0,0 +1,416 @@
+//! The metadata block at the start of the split data.
+//!
+//! Shares whose header has the metadata flag carry, ahead of the content and
+//! split along with it, a block recording the original's name, mode, mtime
+//! and extended attributes. Because it is split like the content, a share on
+//! its own reveals none of it, and the share files can have meaningless
+//! names. All integers are little-endian:
+//!
+//! | size | field                                             |
+//! |------|---------------------------------------------------|
+//! | 4    | length of the rest of the block                   |
+//! | 1    | block version (1)                                 |
+//! | ...  | fields: 1-byte tag, 4-byte length, value          |
+//!
+//! | tag | value                                                |
+//! |-----|------------------------------------------------------|
+//! | 1   | file name, UTF-8, a single path component            |
+//! | 2   | mode, u32                                            |
+//! | 3   | mtime, i64 seconds and u32 nanoseconds since 1970    |
+//! | 4   | extended attribute: u16 name length, raw name, value |
+//! | 5   | kind, u8: 0 = file, 1 = tar archive of a directory   |
+//! | 6   | padded, empty: the payload ends in padding           |
+//! | 7   | compression, u8: 1 = zstd, 2 = deflate               |
+//! | 8   | ciphertext file name, UTF-8: the content is a key    |
+//!
+//! Readers reject tags they do not know, since a field they skip could
+//! change how the content must be restored.
+
+use std::ffi::{OsStr, OsString};
+use std::fs::{File, FileTimes};
+use std::io::Read;
+use std::path::{Component, Path};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use anyhow::{Context, Result, bail};
+
+use crate::compression::Compression;
+
+const VERSION: u8 = 1;
+
+const TAG_NAME: u8 = 1;
+const TAG_MODE: u8 = 2;
+const TAG_MTIME: u8 = 3;
+const TAG_XATTR: u8 = 4;
+const TAG_KIND: u8 = 5;
+const TAG_PADDED: u8 = 6;
+const TAG_COMPRESSION: u8 = 7;
+const TAG_CIPHERTEXT: u8 = 8;
+
+/// Largest metadata block accepted when reading, as a guard against
+/// allocating whatever length a damaged block claims.
+const MAX_LEN: u32 = 16 * 1024 * 1024;
+
+/// What was split, and how to restore it.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Metadata {
+    /// The original's file name, without any directory.
+    pub name: Option<String>,
+    pub mode: Option<u32>,
+    pub mtime: Option<SystemTime>,
+    pub xattrs: Vec<(OsString, Vec<u8>)>,
+    /// Whether the content is a tar archive of a directory.
+    pub archive: bool,
+    /// Whether the payload is padded, as described in the `padding` module.
+    pub padded: bool,
+    /// How the content was compressed before splitting.
+    pub compression: Compression,
+    /// For a hybrid split, the name of the ciphertext file; the content is
+    /// then the key that decrypts it, as described in the `hybrid` module.
+    pub ciphertext: Option<String>,
+}
+
+impl Metadata {
+    /// Capture the metadata of the file or directory at `path`.
+    pub fn from_path(path: &Path, archive: bool) -> Result<Self> {
+        let metadata = std::fs::metadata(path)
+            .with_context(|| format!("failed to read metadata for {}", path.display()))?;
+
+        let name = path
+            .components()
+            .next_back()
+            .and_then(|component| match component {
+                Component::Normal(name) => Some(name.to_string_lossy().into_owned()),
+                _ => None,
+            });
+
+        let mut xattrs = Vec::new();
+        if let Ok(names) = xattr::list_deref(path) {
+            for attr in names {
+                if let Some(value) = xattr::get_deref(path, &attr).with_context(|| {
+                    format!("failed to read attribute {:?} of {}", attr, path.display())
+                })? {
+                    xattrs.push((attr, value));
+                }
+            }
+        }
+
+        Ok(Self {
+            name,
+            mode: mode_of(&metadata),
+            mtime: metadata.modified().ok(),
+            xattrs,
+            archive,
+            padded: false,
+            compression: Compression::None,
+            ciphertext: None,
+        })
+    }
+
+    /// Encode the block, including its length prefix.
+    pub fn encode(&self) -> Vec<u8> {
+        let mut body = vec![VERSION];
+        if let Some(name) = &self.name {
+            push_field(&mut body, TAG_NAME, name.as_bytes());
+        }
+        if let Some(mode) = self.mode {
+            push_field(&mut body, TAG_MODE, &mode.to_le_bytes());
+        }
+        if let Some(mtime) = self.mtime {
+            let (secs, nanos) = match mtime.duration_since(UNIX_EPOCH) {
+                Ok(after) => (after.as_secs() as i64, after.subsec_nanos()),
+                Err(e) => {
+                    let before = e.duration();
+                    match before.subsec_nanos() {
+                        0 => (-(before.as_secs() as i64), 0),
+                        nanos => (-(before.as_secs() as i64) - 1, 1_000_000_000 - nanos),
+                    }
+                }
+            };
+            let mut value = secs.to_le_bytes().to_vec();
+            value.extend_from_slice(&nanos.to_le_bytes());
+            push_field(&mut body, TAG_MTIME, &value);
+        }
+        for (name, value) in &self.xattrs {
+            let name = os_bytes(name);
+            let mut field = (name.len() as u16).to_le_bytes().to_vec();
+            field.extend_from_slice(&name);
+            field.extend_from_slice(value);
+            push_field(&mut body, TAG_XATTR, &field);
+        }
+        if self.archive {
+            push_field(&mut body, TAG_KIND, &[1]);
+        }
+        if self.padded {
+            push_field(&mut body, TAG_PADDED, &[]);
+        }
+        if self.compression != Compression::None {
+            push_field(&mut body, TAG_COMPRESSION, &[self.compression.id()]);
+        }
+        if let Some(ciphertext) = &self.ciphertext {
+            push_field(&mut body, TAG_CIPHERTEXT, ciphertext.as_bytes());
+        }
+
+        let mut block = (body.len() as u32).to_le_bytes().to_vec();
+        block.extend_from_slice(&body);
+        block
+    }
+
+    /// Read a block written by [`Metadata::encode`].
+    pub fn read_from(reader: &mut impl Read) -> Result<Self> {
+        let mut len = [0u8; 4];
+        reader
+            .read_exact(&mut len)
+            .context("metadata block is truncated")?;
+        let len = u32::from_le_bytes(len);
+        if len == 0 || len > MAX_LEN {
+            bail!("metadata block length {} is invalid", len);
+        }
+
+        let mut body = vec![0u8; len as usize];
+        reader
+            .read_exact(&mut body)
+            .context("metadata block is truncated")?;
+        if body[0] != VERSION {
+            bail!(
+                "unsupported metadata version {} (this build reads {})",
+                body[0],
+                VERSION
+            );
+        }
+
+        let mut metadata = Self::default();
+        let mut fields = &body[1..];
+        while !fields.is_empty() {
+            if fields.len() < 5 {
+                bail!("metadata block is truncated");
+            }
+            let tag = fields[0];
+            let len = u32::from_le_bytes(fields[1..5].try_into().expect("length field")) as usize;
+            if fields.len() - 5 < len {
+                bail!("metadata block is truncated");
+            }
+            let value = &fields[5..5 + len];
+            fields = &fields[5 + len..];
+
+            match tag {
+                TAG_NAME => {
+                    let name = String::from_utf8(value.to_vec())
+                        .context("metadata file name is not UTF-8")?;
+                    metadata.name = Some(name);
+                }
+                TAG_MODE => metadata.mode = Some(u32::from_le_bytes(fixed(value)?)),
+                TAG_MTIME => {
+                    let value: [u8; 12] = fixed(value)?;
+                    let secs = i64::from_le_bytes(value[..8].try_into().expect("seconds"));
+                    let nanos = u32::from_le_bytes(value[8..].try_into().expect("nanoseconds"));
+                    metadata.mtime = mtime_from(secs, nanos);
+                }
+                TAG_XATTR => {
+                    if value.len() < 2 {
+                        bail!("metadata attribute is truncated");
+                    }
+                    let name_len = u16::from_le_bytes([value[0], value[1]]) as usize;
+                    if value.len() - 2 < name_len {
+                        bail!("metadata attribute is truncated");
+                    }
+                    let name = os_string(&value[2..2 + name_len]);
+                    metadata.xattrs.push((name, value[2 + name_len..].to_vec()));
+                }
+                TAG_KIND => match fixed::<1>(value)? {
+                    [0] => metadata.archive = false,
+                    [1] => metadata.archive = true,
+                    [kind] => bail!("unknown content kind {} in metadata", kind),
+                },
+                TAG_PADDED => {
+                    fixed::<0>(value)?;
+                    metadata.padded = true;
+                }
+                TAG_COMPRESSION => {
+                    let [id] = fixed::<1>(value)?;
+                    metadata.compression = Compression::from_id(id)?;
+                }
+                TAG_CIPHERTEXT => {
+                    let name = String::from_utf8(value.to_vec())
+                        .context("metadata ciphertext name is not UTF-8")?;
+                    metadata.ciphertext = Some(name);
+                }
+                _ => bail!(
+                    "metadata field {} is not understood by this build of splinch",
+                    tag
+                ),
+            }
+        }
+
+        Ok(metadata)
+    }
+
+    /// The recorded name, if it is safe to create in a chosen directory: a
+    /// single normal path component.
+    pub fn safe_name(&self) -> Option<&str> {
+        self.name
+            .as_deref()
+            .filter(|name| is_single_component(name))
+    }
+
+    /// The recorded ciphertext file name, if it is a single normal path
+    /// component that can be looked up next to the shares.
+    pub fn safe_ciphertext(&self) -> Result<Option<&str>> {
+        match self.ciphertext.as_deref() {
+            Some(name) if !is_single_component(name) => {
+                bail!("metadata names an unsafe ciphertext path {:?}", name)
+            }
+            name => Ok(name),
+        }
+    }
+
+    /// Apply the recorded attributes, mode and mtime to the restored `path`.
+    ///
+    /// Extended attributes are restored where the filesystem and privileges
+    /// allow, as `cp -a` does; the mode and mtime must succeed. The setuid,
+    /// setgid and sticky bits are only restored when `authenticated`, that is
+    /// when the split's MAC vouched for the metadata.
+    pub fn restore(&self, path: &Path, authenticated: bool) -> Result<()> {
+        for (name, value) in &self.xattrs {
+            let _ = xattr::set(path, name, value);
+        }
+
+        // The mtime goes before the mode, which may make the file unreadable.
+        if let Some(mtime) = self.mtime {
+            File::open(path)
+                .and_then(|file| file.set_times(FileTimes::new().set_modified(mtime)))
+                .with_context(|| format!("failed to set mtime of {}", path.display()))?;
+        }
+
+        if let Some(mode) = self.mode {
+            let mode = if authenticated { mode } else { mode & 0o777 };
+            set_mode(path, mode)
+                .with_context(|| format!("failed to set permissions of {}", path.display()))?;
+        }
+
+        Ok(())
+    }
+}
+
+fn is_single_component(name: &str) -> bool {
+    let mut components = Path::new(name).components();
+    matches!(
+        (components.next(), components.next()),
+        (Some(Component::Normal(_)), None)
+    )
+}
+
+fn push_field(body: &mut Vec<u8>, tag: u8, value: &[u8]) {
+    body.push(tag);
+    body.extend_from_slice(&(value.len() as u32).to_le_bytes());
+    body.extend_from_slice(value);
+}
+
+fn fixed<const N: usize>(value: &[u8]) -> Result<[u8; N]> {
+    value
+        .try_into()
+        .map_err(|_| anyhow::anyhow!("metadata field has length {}, expected {}", value.len(), N))
+}
+
+fn mtime_from(secs: i64, nanos: u32) -> Option<SystemTime> {
+    if nanos >= 1_000_000_000 {
+        return None;
+    }
+    if secs >= 0 {
+        UNIX_EPOCH.checked_add(Duration::new(secs as u64, nanos))
+    } else {
+        UNIX_EPOCH
+            .checked_sub(Duration::from_secs(secs.unsigned_abs()))?
+            .checked_add(Duration::from_nanos(nanos as u64))
+    }
+}
+
+/// The raw bytes of an attribute name, which need not be UTF-8.
+#[cfg(unix)]
+fn os_bytes(name: &OsStr) -> Vec<u8> {
+    use std::os::unix::ffi::OsStrExt;
+
+    name.as_bytes().to_vec()
+}
+
+#[cfg(not(unix))]
+fn os_bytes(name: &OsStr) -> Vec<u8> {
+    name.to_string_lossy().into_owned().into_bytes()
+}
+
+#[cfg(unix)]
+fn os_string(bytes: &[u8]) -> OsString {
+    use std::os::unix::ffi::OsStringExt;
+
+    OsString::from_vec(bytes.to_vec())
+}
+
+#[cfg(not(unix))]
+fn os_string(bytes: &[u8]) -> OsString {
+    String::from_utf8_lossy(bytes).into_owned().into()
+}
+
+#[cfg(unix)]
+fn mode_of(metadata: &std::fs::Metadata) -> Option<u32> {
+    use std::os::unix::fs::PermissionsExt;
+
+    Some(metadata.permissions().mode() & 0o7777)
+}
+
+#[cfg(not(unix))]
+fn mode_of(_metadata: &std::fs::Metadata) -> Option<u32> {
+    None
+}
+
+#[cfg(unix)]
+fn set_mode(path: &Path, mode: u32) -> std::io::Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+
+    std::fs::set_permissions(path, std::fs::Permissions::from_mode(mode & 0o7777))
+}
+
+#[cfg(not(unix))]
+fn set_mode(_path: &Path, _mode: u32) -> std::io::Result<()> {
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(unix)]
+    #[test]
+    fn attribute_names_keep_their_raw_bytes() {
+        use std::os::unix::ffi::OsStrExt;
+
+        let name = OsStr::from_bytes(b"user.caf\xe9").to_os_string();
+        let metadata = Metadata {
+            name: Some("secret".to_string()),
+            xattrs: vec![(name, b"value".to_vec())],
+            ..Metadata::default()
+        };
+        let decoded = Metadata::read_from(&mut metadata.encode().as_slice()).unwrap();
+        assert_eq!(decoded, metadata);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn special_mode_bits_need_authentication() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = crate::test_dir("metadata-mode");
+        let path = dir.join("restored");
+        std::fs::write(&path, b"content").unwrap();
+        let metadata = Metadata {
+            mode: Some(0o4755),
+            ..Metadata::default()
+        };
+        let mode = |path: &Path| std::fs::metadata(path).unwrap().permissions().mode() & 0o7777;
+
+        metadata.restore(&path, false).unwrap();
+        assert_eq!(mode(&path), 0o755);
+        metadata.restore(&path, true).unwrap();
+        assert_eq!(mode(&path), 0o4755);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,298 @@
+//! Pre-shared one-time pad files and the ledger of what has been used.
+//!
+//! Two parties exchange a large pad file in person, and later splits draw
+//! the first XOR pad from it instead of from the random number generator.
+//! That share is never written: the receiver already holds it in the pad
+//! file, so only the other shares travel. Every written share records where
+//! the split's range starts in the pad, and the range is laid out as:
+//!
+//! | size | field                                          |
+//! |------|------------------------------------------------|
+//! | 32   | share 1 of the MAC key, if the split has a MAC |
+//! | ...  | the pad, as long as the other shares' payload  |
+//!
+//! A pad byte must never be used twice, so the sender keeps a ledger next
+//! to the pad, `<pad>.ledger`, with one line per split:
+//!
+//! ```text
+//! <offset> <length> <split ID>
+//! ```
+//!
+//! Each split starts after the end of every range in the ledger. Before it
+//! reads any pad byte, it reserves the rest of the pad, and once it stops it
+//! cuts the reservation down to what it used, so a split that dies midway
+//! leaves its range reserved rather than free. Both steps hold an exclusive
+//! lock on the pad file while they read and rewrite the ledger, so that two
+//! splits running at once never reserve the same bytes.
+
+use std::fs::{File, OpenOptions};
+use std::io::{BufReader, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result, bail};
+
+use crate::header::SPLIT_ID_LEN;
+
+/// Where the ledger of `pad_path` is kept: `<pad_path>.ledger`.
+pub fn ledger_path(pad_path: &Path) -> PathBuf {
+    crate::append_extension(pad_path, "ledger")
+}
+
+/// One range of a pad recorded in its ledger.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LedgerEntry {
+    pub offset: u64,
+    pub len: u64,
+    /// The split ID as lowercase hex.
+    pub split_id: String,
+}
+
+impl LedgerEntry {
+    pub fn end(&self) -> u64 {
+        self.offset + self.len
+    }
+}
+
+/// Read the ledger of `pad_path`; a pad without a ledger is unused.
+pub fn read_ledger(pad_path: &Path) -> Result<Vec<LedgerEntry>> {
+    let path = ledger_path(pad_path);
+    let text = match std::fs::read_to_string(&path) {
+        Ok(text) => text,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e).with_context(|| format!("failed to read {}", path.display())),
+    };
+
+    let mut entries = Vec::new();
+    for (i, line) in text.lines().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        match parse_entry(line) {
+            Some(entry) => entries.push(entry),
+            None => bail!("{} line {} is malformed: {:?}", path.display(), i + 1, line),
+        }
+    }
+    Ok(entries)
+}
+
+fn parse_entry(line: &str) -> Option<LedgerEntry> {
+    let mut fields = line.split_whitespace();
+    let offset: u64 = fields.next()?.parse().ok()?;
+    let len: u64 = fields.next()?.parse().ok()?;
+    let split_id = fields.next()?.to_string();
+    if fields.next().is_some() || offset.checked_add(len).is_none() {
+        return None;
+    }
+    Some(LedgerEntry {
+        offset,
+        len,
+        split_id,
+    })
+}
+
+/// The first pad byte after every range recorded in the ledger.
+pub fn next_offset(entries: &[LedgerEntry]) -> u64 {
+    entries.iter().map(LedgerEntry::end).max().unwrap_or(0)
+}
+
+/// Pad bytes read from a pad file for one split.
+pub struct PadReader {
+    path: PathBuf,
+    reader: BufReader<File>,
+    offset: u64,
+    end: u64,
+    used: u64,
+}
+
+impl PadReader {
+    /// Where the split's range starts in the pad.
+    pub fn offset(&self) -> u64 {
+        self.offset
+    }
+
+    /// Pad bytes handed out so far.
+    pub fn used(&self) -> u64 {
+        self.used
+    }
+
+    /// Fill `buf` with the next pad bytes, failing if the pad runs out.
+    pub fn fill(&mut self, buf: &mut [u8]) -> Result<()> {
+        let left = self.end - self.offset - self.used;
+        if buf.len() as u64 > left {
+            bail!(
+                "the one-time pad {} has only {} unused bytes left, not enough for this input",
+                self.path.display(),
+                left
+            );
+        }
+        self.reader
+            .read_exact(buf)
+            .with_context(|| format!("failed to read {}", self.path.display()))?;
+        self.used += buf.len() as u64;
+        Ok(())
+    }
+}
+
+/// Reserve the rest of the pad at `pad_path` for a split and open it at the
+/// first unused byte.
+pub fn reserve(pad_path: &Path, split_id: &[u8; SPLIT_ID_LEN]) -> Result<PadReader> {
+    let file = File::open(pad_path)
+        .with_context(|| format!("failed to open the one-time pad {}", pad_path.display()))?;
+    let end = file
+        .metadata()
+        .with_context(|| format!("failed to read metadata for {}", pad_path.display()))?
+        .len();
+
+    lock(&file, pad_path)?;
+    let offset = next_offset(&read_ledger(pad_path)?);
+    if offset >= end {
+        bail!(
+            "the one-time pad {} is used up: its ledger accounts for all {} bytes",
+            pad_path.display(),
+            end
+        );
+    }
+
+    append_entry(
+        pad_path,
+        &LedgerEntry {
+            offset,
+            len: end - offset,
+            split_id: crate::to_hex(split_id),
+        },
+    )?;
+    file.unlock()
+        .with_context(|| format!("failed to unlock {}", pad_path.display()))?;
+
+    let mut reader = BufReader::new(file);
+    reader
+        .seek(SeekFrom::Start(offset))
+        .with_context(|| format!("failed to seek in {}", pad_path.display()))?;
+    Ok(PadReader {
+        path: pad_path.to_path_buf(),
+        reader,
+        offset,
+        end,
+        used: 0,
+    })
+}
+
+/// Take an exclusive lock on the pad file, waiting for any other split that
+/// holds it. The ledger is replaced on release, so the pad is locked instead.
+fn lock(file: &File, pad_path: &Path) -> Result<()> {
+    file.lock()
+        .with_context(|| format!("failed to lock the one-time pad {}", pad_path.display()))
+}
+
+fn append_entry(pad_path: &Path, entry: &LedgerEntry) -> Result<()> {
+    let path = ledger_path(pad_path);
+    let mut file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&path)
+        .with_context(|| format!("failed to open {}", path.display()))?;
+    writeln!(file, "{} {} {}", entry.offset, entry.len, entry.split_id)
+        .and_then(|()| file.sync_all())
+        .with_context(|| format!("failed to write to {}", path.display()))
+}
+
+/// Cut the reservation made for a split down to the bytes it used.
+pub fn release_unused(pad_path: &Path, pad: &PadReader) -> Result<()> {
+    let path = ledger_path(pad_path);
+    let file = File::open(pad_path)
+        .with_context(|| format!("failed to open the one-time pad {}", pad_path.display()))?;
+    lock(&file, pad_path)?;
+    let mut entries = read_ledger(pad_path)?;
+    let Some(entry) = entries
+        .iter_mut()
+        .rev()
+        .find(|entry| entry.offset == pad.offset)
+    else {
+        bail!(
+            "{} lost the reservation at offset {}",
+            path.display(),
+            pad.offset
+        );
+    };
+    entry.len = pad.used;
+
+    let text: String = entries
+        .iter()
+        .filter(|entry| entry.len > 0)
+        .map(|entry| format!("{} {} {}\n", entry.offset, entry.len, entry.split_id))
+        .collect();
+    let tmp_path = crate::append_extension(&path, "tmp");
+    let mut file = File::create(&tmp_path)
+        .with_context(|| format!("failed to create {}", tmp_path.display()))?;
+    file.write_all(text.as_bytes())
+        .and_then(|()| file.sync_all())
+        .with_context(|| format!("failed to write to {}", tmp_path.display()))?;
+    std::fs::rename(&tmp_path, &path)
+        .with_context(|| format!("failed to replace {}", path.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_pad(name: &str, len: usize) -> PathBuf {
+        let path =
+            std::env::temp_dir().join(format!("splinch-{}-{}.pad", name, std::process::id()));
+        let _ = std::fs::remove_file(ledger_path(&path));
+        std::fs::write(&path, vec![0x5a; len]).unwrap();
+        path
+    }
+
+    fn remove_pad(path: &Path) {
+        let _ = std::fs::remove_file(ledger_path(path));
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn reservations_follow_each_other() {
+        let path = temp_pad("otp-sequential", 1000);
+
+        let mut first = reserve(&path, &[1; SPLIT_ID_LEN]).unwrap();
+        first.fill(&mut [0u8; 100]).unwrap();
+        release_unused(&path, &first).unwrap();
+        let mut second = reserve(&path, &[2; SPLIT_ID_LEN]).unwrap();
+        second.fill(&mut [0u8; 50]).unwrap();
+        release_unused(&path, &second).unwrap();
+        let entries = read_ledger(&path).unwrap();
+        remove_pad(&path);
+
+        assert_eq!(first.offset(), 0);
+        assert_eq!(second.offset(), 100);
+        assert_eq!(entries.len(), 2);
+        assert_eq!(next_offset(&entries), 150);
+    }
+
+    #[test]
+    fn reservations_wait_for_the_pad_lock() {
+        let path = temp_pad("otp-locked", 1000);
+
+        // Hold the lock as another split would between reading the ledger
+        // and recording its range there.
+        let other = File::open(&path).unwrap();
+        other.lock().unwrap();
+        let waiting = std::thread::spawn({
+            let path = path.clone();
+            move || reserve(&path, &[2; SPLIT_ID_LEN]).unwrap().offset()
+        });
+        std::thread::sleep(std::time::Duration::from_millis(200));
+        append_entry(
+            &path,
+            &LedgerEntry {
+                offset: 0,
+                len: 100,
+                split_id: crate::to_hex(&[1; SPLIT_ID_LEN]),
+            },
+        )
+        .unwrap();
+        other.unlock().unwrap();
+        let offset = waiting.join().unwrap();
+        remove_pad(&path);
+
+        assert_eq!(offset, 100);
+    }
+}
@@ -0,0 +1,198 @@
+//! Generating and managing one-time pads for [`SplitOptions::one_time_pad`].
+//!
+//! A pad is a file of random bytes from a [`RandomSource`]. A pad book
+//! is a directory of numbered pages, `001.pad`, `002.pad` and so on, each a
+//! pad of its own with its own ledger, so that both parties can tear out and
+//! destroy a page once it has been used. Usage is read from the ledgers
+//! described in the `otp` module.
+//!
+//! [`SplitOptions::one_time_pad`]: crate::SplitOptions::one_time_pad
+
+use std::fs::{File, OpenOptions};
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result, bail};
+
+use crate::health::HealthTested;
+use crate::{RandomSource, otp};
+
+/// How much of a pad has been used, according to its ledger.
+#[derive(Debug, Clone)]
+pub struct PadUsage {
+    pub path: PathBuf,
+    pub len: u64,
+    /// Bytes up to the end of the last range in the ledger, which can no
+    /// longer be used.
+    pub used: u64,
+}
+
+impl PadUsage {
+    pub fn remaining(&self) -> u64 {
+        self.len.saturating_sub(self.used)
+    }
+}
+
+/// Write a new pad of `len` bytes from `random` to `path`, which must not
+/// exist. The bytes go through the health tests in the `health` module, and
+/// a pad that fails them is deleted.
+pub fn generate_pad(path: &Path, len: u64, random: &mut dyn RandomSource) -> Result<()> {
+    let file = OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(path)
+        .with_context(|| format!("failed to create {}", path.display()))?;
+    let mut writer = BufWriter::new(file);
+
+    let written = (|| -> Result<()> {
+        crate::write_random(&mut writer, len, &mut HealthTested::new(random))?;
+        writer.flush()?;
+        writer.get_ref().sync_all()?;
+        Ok(())
+    })();
+    if let Err(e) = written {
+        drop(writer);
+        let _ = std::fs::remove_file(path);
+        return Err(e.context(format!("failed to write {}", path.display())));
+    }
+    Ok(())
+}
+
+/// Write a new pad book of `pages` pages holding `len` bytes from `random`
+/// in all to the directory `dir`, which must not exist. Returns the page
+/// paths. If any page fails, the pages already written and the directory
+/// are removed again.
+pub fn generate_pad_book(
+    dir: &Path,
+    len: u64,
+    pages: usize,
+    random: &mut dyn RandomSource,
+) -> Result<Vec<PathBuf>> {
+    if pages == 0 {
+        bail!("a pad book needs at least one page");
+    }
+    if len < pages as u64 {
+        bail!("cannot fill {} pages with {} bytes", pages, len);
+    }
+    std::fs::create_dir(dir).with_context(|| format!("failed to create {}", dir.display()))?;
+
+    let page_len = len.div_ceil(pages as u64);
+    let width = pages.to_string().len().max(3);
+    let mut paths = Vec::with_capacity(pages);
+    let mut remaining = len;
+    for page in 1..=pages {
+        let path = dir.join(format!("{:0width$}.pad", page, width = width));
+        let len = remaining.min(page_len);
+        if let Err(e) = generate_pad(&path, len, random) {
+            for path in &paths {
+                let _ = std::fs::remove_file(path);
+            }
+            let _ = std::fs::remove_dir(dir);
+            return Err(e.context(format!(
+                "pad book aborted; removed {} and the {} pages already written",
+                dir.display(),
+                paths.len()
+            )));
+        }
+        remaining -= len;
+        paths.push(path);
+    }
+    Ok(paths)
+}
+
+/// The pages of a pad book, in order, or the pad itself for a pad file.
+pub fn pad_pages(path: &Path) -> Result<Vec<PathBuf>> {
+    if !path.is_dir() {
+        return Ok(vec![path.to_path_buf()]);
+    }
+    let mut pages = Vec::new();
+    for entry in
+        std::fs::read_dir(path).with_context(|| format!("failed to list {}", path.display()))?
+    {
+        let entry = entry.with_context(|| format!("failed to list {}", path.display()))?;
+        let page = entry.path();
+        if page.extension().is_some_and(|ext| ext == "pad") && page.is_file() {
+            pages.push(page);
+        }
+    }
+    pages.sort();
+    Ok(pages)
+}
+
+/// How much of each page of a pad book, or of a single pad, is used.
+pub fn pad_usage(path: &Path) -> Result<Vec<PadUsage>> {
+    pad_pages(path)?
+        .into_iter()
+        .map(|page| {
+            let len = File::open(&page)
+                .and_then(|file| file.metadata())
+                .with_context(|| format!("failed to read metadata for {}", page.display()))?
+                .len();
+            let used = otp::next_offset(&otp::read_ledger(&page)?);
+            Ok(PadUsage {
+                path: page,
+                len,
+                used,
+            })
+        })
+        .collect()
+}
+
+/// Securely delete pads with [`secure_delete`], along with their ledgers.
+///
+/// A pad book directory loses every page that its ledger shows was used; a
+/// pad or page given by path is destroyed whether or not it was used, since
+/// the receiving side keeps no ledger. Returns the pads destroyed.
+///
+/// [`secure_delete`]: crate::secure_delete
+pub fn destroy_pads(
+    path: &Path,
+    passes: u32,
+    random: &mut dyn RandomSource,
+) -> Result<Vec<PathBuf>> {
+    let pages = if path.is_dir() {
+        pad_usage(path)?
+            .into_iter()
+            .filter(|usage| usage.used > 0)
+            .map(|usage| usage.path)
+            .collect()
+    } else {
+        vec![path.to_path_buf()]
+    };
+
+    for page in &pages {
+        crate::secure_delete(page, passes, random)?;
+        let ledger = otp::ledger_path(page);
+        if ledger.exists() {
+            std::fs::remove_file(&ledger)
+                .with_context(|| format!("failed to remove {}", ledger.display()))?;
+        }
+    }
+    Ok(pages)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::SeededRandom;
+
+    #[test]
+    fn seeded_pad_book_pages_differ() {
+        let dir = std::env::temp_dir().join(format!("splinch-pad-book-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+
+        let mut random = SeededRandom::from_u64(1);
+        let pages = generate_pad_book(&dir, 3 * 4096, 3, &mut random).unwrap();
+        let contents: Vec<Vec<u8>> = pages
+            .iter()
+            .map(|page| std::fs::read(page).unwrap())
+            .collect();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(contents.len(), 3);
+        assert!(contents.iter().all(|page| page.len() == 4096));
+        assert_ne!(contents[0], contents[1]);
+        assert_ne!(contents[1], contents[2]);
+        assert_ne!(contents[0], contents[2]);
+    }
+}
@@ -0,0 +1,234 @@
+//! Length-hiding padding of the split data.
+//!
+//! Without padding every share is as long as the original, so anyone who
+//! sees one learns the exact file size. A padded split rounds its payload up
+//! to a size bucket and ends it with the true content length, so only the
+//! bucket shows. The padding is zeros, which the split turns into bytes as
+//! random-looking as the rest of each share:
+//!
+//! | size | field                                      |
+//! |------|--------------------------------------------|
+//! | ...  | metadata block and content                 |
+//! | ...  | zero bytes up to the padded length         |
+//! | 8    | content length, little-endian              |
+//!
+//! The metadata block records that the split is padded, so that combining
+//! knows to look for the length at the end.
+
+use std::io::{self, Cursor, Read, Repeat, Take};
+use std::str::FromStr;
+
+use anyhow::{Context, Result, bail};
+
+/// Length of the content length stored at the end of a padded payload.
+pub const TRAILER_LEN: u64 = 8;
+
+/// How far to pad the split data.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Padding {
+    #[default]
+    None,
+    /// Round up to the next power of two.
+    PowerOfTwo,
+    /// Round up to a multiple of the given number of bytes.
+    Block(u64),
+    /// Pad to exactly the given number of bytes, failing if the input does
+    /// not fit.
+    Size(u64),
+}
+
+impl Padding {
+    /// The padded length of a payload of `len` bytes, including the trailer.
+    pub fn padded_len(self, len: u64) -> Result<u64> {
+        let len = len + TRAILER_LEN;
+        match self {
+            Padding::None => Ok(len),
+            Padding::PowerOfTwo => len
+                .checked_next_power_of_two()
+                .context("input is too large to pad to a power of two"),
+            Padding::Block(block) => len
+                .div_ceil(block)
+                .checked_mul(block)
+                .context("input is too large to pad to a whole block"),
+            Padding::Size(size) if len > size => bail!(
+                "input needs {} bytes of split data, more than the padding target of {}",
+                len,
+                size
+            ),
+            Padding::Size(size) => Ok(size),
+        }
+    }
+}
+
+impl FromStr for Padding {
+    type Err = anyhow::Error;
+
+    /// Parse `pow2`, `block:N` or a target size; sizes take an optional
+    /// `K`, `M` or `G` suffix (powers of 1024).
+    fn from_str(s: &str) -> Result<Self> {
+        if s == "pow2" {
+            return Ok(Padding::PowerOfTwo);
+        }
+        if let Some(block) = s.strip_prefix("block:") {
+            let block = parse_size(block)?;
+            if block == 0 {
+                bail!("padding block size must be at least 1");
+            }
+            return Ok(Padding::Block(block));
+        }
+        parse_size(s)
+            .map(Padding::Size)
+            .with_context(|| format!("expected pow2, block:N or a size, got {:?}", s))
+    }
+}
+
+/// Parse a size in bytes, with an optional `K`, `M` or `G` suffix (powers
+/// of 1024).
+pub fn parse_size(s: &str) -> Result<u64> {
+    let (digits, unit) = match s.char_indices().last() {
+        Some((i, 'K' | 'k')) => (&s[..i], 1 << 10),
+        Some((i, 'M' | 'm')) => (&s[..i], 1 << 20),
+        Some((i, 'G' | 'g')) => (&s[..i], 1 << 30),
+        _ => (s, 1),
+    };
+    let n: u64 = digits
+        .parse()
+        .with_context(|| format!("invalid size {:?}", s))?;
+    n.checked_mul(unit)
+        .with_context(|| format!("size {:?} is too large", s))
+}
+
+/// The content length recorded in `trailer`, the last bytes of a padded
+/// payload, checked against the `room` for content ahead of the trailer.
+pub fn content_len(trailer: &[u8], room: u64) -> Result<u64> {
+    let Ok(trailer) = trailer.try_into() else {
+        bail!("padded split is too short to hold its content length");
+    };
+    let content_len = u64::from_le_bytes(trailer);
+    if content_len > room {
+        bail!(
+            "padded split records {} content bytes but has room for only {}",
+            content_len,
+            room
+        );
+    }
+    Ok(content_len)
+}
+
+/// Reads the content from `inner`, followed by the padding and trailer once
+/// the content ends.
+pub struct PaddedReader<R> {
+    inner: R,
+    padding: Padding,
+    /// Payload bytes ahead of the content, which count towards the padded
+    /// length.
+    prefix_len: u64,
+    content_len: u64,
+    tail: Option<io::Chain<Take<Repeat>, Cursor<[u8; TRAILER_LEN as usize]>>>,
+}
+
+impl<R: Read> PaddedReader<R> {
+    pub fn new(inner: R, padding: Padding, prefix_len: u64) -> Self {
+        Self {
+            inner,
+            padding,
+            prefix_len,
+            content_len: 0,
+            tail: None,
+        }
+    }
+}
+
+impl<R: Read> Read for PaddedReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if let Some(tail) = &mut self.tail {
+            return tail.read(buf);
+        }
+
+        let n = self.inner.read(buf)?;
+        if n > 0 || buf.is_empty() {
+            self.content_len += n as u64;
+            return Ok(n);
+        }
+
+        let len = self.prefix_len + self.content_len;
+        let padded_len = self
+            .padding
+            .padded_len(len)
+            .map_err(|e| io::Error::other(format!("{:#}", e)))?;
+        let zeros = io::repeat(0).take(padded_len - len - TRAILER_LEN);
+        let trailer = Cursor::new(self.content_len.to_le_bytes());
+        self.tail.insert(zeros.chain(trailer)).read(buf)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Pad `content` behind `prefix_len` payload bytes, as a split would.
+    fn pad(content: &[u8], padding: Padding, prefix_len: u64) -> Vec<u8> {
+        let mut padded = Vec::new();
+        PaddedReader::new(content, padding, prefix_len)
+            .read_to_end(&mut padded)
+            .unwrap();
+        padded
+    }
+
+    #[test]
+    fn the_trailer_round_trips() {
+        let paddings = [
+            Padding::None,
+            Padding::PowerOfTwo,
+            Padding::Block(1000),
+            Padding::Size(10_000),
+        ];
+        let prefix_len = 20;
+        for padding in paddings {
+            for len in [0, 1, 100, 4096, 5000] {
+                let content: Vec<u8> = (0..len).map(|i| (i % 251 + 1) as u8).collect();
+                let padded = pad(&content, padding, prefix_len);
+                let payload_len = prefix_len + padded.len() as u64;
+                assert_eq!(
+                    payload_len,
+                    padding.padded_len(prefix_len + len as u64).unwrap()
+                );
+
+                let trailer_at = padded.len() - TRAILER_LEN as usize;
+                assert_eq!(&padded[..len], content.as_slice());
+                assert!(padded[len..trailer_at].iter().all(|&b| b == 0));
+                let room = trailer_at as u64;
+                assert_eq!(
+                    content_len(&padded[trailer_at..], room).unwrap(),
+                    len as u64
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn truncated_or_corrupt_trailers_are_rejected() {
+        let padded = pad(b"content", Padding::Block(64), 0);
+        let trailer_at = padded.len() - TRAILER_LEN as usize;
+        let room = trailer_at as u64;
+
+        for short in 0..TRAILER_LEN as usize {
+            let err = content_len(&padded[trailer_at..trailer_at + short], room).unwrap_err();
+            assert!(err.to_string().contains("too short"), "{}", err);
+        }
+
+        let mut corrupt = padded[trailer_at..].to_vec();
+        corrupt[7] ^= 0x80;
+        let err = content_len(&corrupt, room).unwrap_err();
+        assert!(err.to_string().contains("has room for only"), "{}", err);
+        assert!(content_len(&(room + 1).to_le_bytes(), room).is_err());
+        assert_eq!(content_len(&room.to_le_bytes(), room).unwrap(), room);
+    }
+
+    #[test]
+    fn content_beyond_a_fixed_size_is_rejected() {
+        let mut reader = PaddedReader::new(&[0u8; 100][..], Padding::Size(100), 0);
+        assert!(reader.read_to_end(&mut Vec::new()).is_err());
+        assert_eq!(pad(&[0u8; 92], Padding::Size(100), 0).len(), 100);
+    }
+}
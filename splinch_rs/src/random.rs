@@ -0,0 +1,159 @@
+//! Where the random bytes behind pads, keys, seeds and split IDs come from.
+//!
+//! Everything that needs randomness draws it from a [`RandomSource`] given by
+//! the caller, which may be any implementation of the trait. The command line
+//! picks one of the built-in sources with a [`Randomness`] setting:
+//!
+//! | setting     | source                                             |
+//! |-------------|----------------------------------------------------|
+//! | `os`        | the OS random number generator (the default)       |
+//! | `seed:HEX`  | a ChaCha20 DRBG from a 32-byte seed, for tests     |
+//! | a path      | bytes read from a device or file, e.g. `/dev/hwrng` |
+//!
+//! A source is opened once and then drawn from by everything that needs
+//! random bytes, so that no two pads, pages or overwrite passes repeat. A
+//! seeded source still gives the same bytes every time it is opened, so it
+//! makes splits reproducible and must never be used for real data. A file is
+//! read from the start each time it is opened, so it must not be reused
+//! either.
+
+use std::fs::File;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use anyhow::{Context, Result, bail};
+use rand::rngs::OsRng;
+use rand::{RngCore, SeedableRng, TryRngCore};
+use rand_chacha::ChaCha20Rng;
+
+/// Length of the seed of a [`SeededRandom`].
+pub const SEED_LEN: usize = 32;
+
+/// A source of random bytes.
+pub trait RandomSource {
+    /// Fill `buf` with random bytes.
+    fn fill(&mut self, buf: &mut [u8]) -> Result<()>;
+
+    /// A random `u64`.
+    fn next_u64(&mut self) -> Result<u64> {
+        let mut buf = [0u8; 8];
+        self.fill(&mut buf)?;
+        Ok(u64::from_le_bytes(buf))
+    }
+}
+
+/// The OS random number generator.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct OsRandom;
+
+impl RandomSource for OsRandom {
+    fn fill(&mut self, buf: &mut [u8]) -> Result<()> {
+        OsRng
+            .try_fill_bytes(buf)
+            .context("the OS random number generator failed")
+    }
+}
+
+/// A ChaCha20 DRBG: the same seed always gives the same bytes.
+pub struct SeededRandom {
+    rng: ChaCha20Rng,
+}
+
+impl SeededRandom {
+    pub fn new(seed: [u8; SEED_LEN]) -> Self {
+        Self {
+            rng: ChaCha20Rng::from_seed(seed),
+        }
+    }
+
+    pub fn from_u64(seed: u64) -> Self {
+        Self {
+            rng: ChaCha20Rng::seed_from_u64(seed),
+        }
+    }
+}
+
+impl RandomSource for SeededRandom {
+    fn fill(&mut self, buf: &mut [u8]) -> Result<()> {
+        self.rng.fill_bytes(buf);
+        Ok(())
+    }
+}
+
+/// Bytes read from a device such as `/dev/hwrng`, or from a file.
+pub struct DeviceRandom {
+    path: PathBuf,
+    file: File,
+}
+
+impl DeviceRandom {
+    pub fn open(path: &Path) -> Result<Self> {
+        let file = File::open(path)
+            .with_context(|| format!("failed to open the random source {}", path.display()))?;
+        Ok(Self {
+            path: path.to_path_buf(),
+            file,
+        })
+    }
+}
+
+impl RandomSource for DeviceRandom {
+    fn fill(&mut self, buf: &mut [u8]) -> Result<()> {
+        let n = crate::read_exact_or_eof(&mut self.file, buf)
+            .with_context(|| format!("failed to read the random source {}", self.path.display()))?;
+        if n < buf.len() {
+            bail!("the random source {} ran out of bytes", self.path.display());
+        }
+        Ok(())
+    }
+}
+
+/// Which built-in [`RandomSource`] to use, as given on the command line.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum Randomness {
+    #[default]
+    Os,
+    /// A [`SeededRandom`]; reproducible, so for tests only.
+    Seeded([u8; SEED_LEN]),
+    /// A [`DeviceRandom`] reading the given device or file.
+    Device(PathBuf),
+}
+
+impl Randomness {
+    /// Open the source, positioned at its first byte.
+    pub fn open(&self) -> Result<Box<dyn RandomSource>> {
+        Ok(match self {
+            Randomness::Os => Box::new(OsRandom),
+            Randomness::Seeded(seed) => Box::new(SeededRandom::new(*seed)),
+            Randomness::Device(path) => Box::new(DeviceRandom::open(path)?),
+        })
+    }
+}
+
+impl FromStr for Randomness {
+    type Err = anyhow::Error;
+
+    /// Parse `os`, `seed:` and 64 hex digits, or the path of a device or
+    /// file.
+    fn from_str(s: &str) -> Result<Self> {
+        if s == "os" {
+            return Ok(Randomness::Os);
+        }
+        if let Some(hex) = s.strip_prefix("seed:") {
+            let mut seed = [0u8; SEED_LEN];
+            if hex.len() != 2 * SEED_LEN || !hex.is_ascii() {
+                bail!("a seed must be {} hex digits", 2 * SEED_LEN);
+            }
+            for (byte, pair) in seed.iter_mut().zip(hex.as_bytes().chunks(2)) {
+                let pair = std::str::from_utf8(pair).expect("ASCII");
+                *byte = u8::from_str_radix(pair, 16)
+                    .with_context(|| format!("invalid hex digits {:?} in seed", pair))?;
+            }
+            return Ok(Randomness::Seeded(seed));
+        }
+        if s.is_empty() {
+            bail!("expected os, seed:HEX or the path of a device or file");
+        }
+        Ok(Randomness::Device(PathBuf::from(s)))
+    }
+}
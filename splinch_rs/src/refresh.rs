@@ -0,0 +1,397 @@
+//! Refreshing the shares of an XOR split without rebuilding the original.
+//!
+//! Shares kept for years may leak one at a time. Refreshing replaces a whole
+//! set with a new one that rebuilds the same original but shares no bytes
+//! with the old set, so that an old share is useless alongside the new ones.
+//! Every share but the last is XOR-ed with a fresh random mask, and the last
+//! with the XOR of all the masks, so the masks cancel when combining:
+//!
+//! ```text
+//! new_i = old_i ^ mask_i                             for i < N
+//! new_N = old_N ^ mask_1 ^ ... ^ mask_(N-1)
+//! ```
+//!
+//! The shares are streamed block by block and never XOR-ed together, so the
+//! original exists neither in memory nor on disk at any point. The MAC key
+//! shares in the headers are refreshed the same way; the key, and so the
+//! tag, stay the same. The new set gets a new split ID, so old and new
+//! shares cannot be combined by mistake.
+
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result, bail};
+
+use crate::header::{SPLIT_ID_LEN, ShareHeader};
+use crate::health::HealthTested;
+use crate::share::{ShareInput, ShareOutput};
+use crate::{CHUNK_SIZE, HealthStats, RandomSource, SchemeKind, mac};
+
+/// Options for [`refresh_shares`].
+#[derive(Debug, Clone, Default)]
+pub struct RefreshOptions {
+    /// Path for each refreshed share, in share index order. When empty, the
+    /// refreshed shares replace the old ones under their names.
+    pub output_paths: Vec<PathBuf>,
+    /// Securely delete the old shares with this many overwrite passes once
+    /// the refreshed ones are written. Without it, replaced shares are only
+    /// unlinked.
+    pub secure_delete_passes: Option<u32>,
+}
+
+/// The shares produced by a refresh.
+#[derive(Debug, Clone)]
+pub struct RefreshedShares {
+    /// The old shares, in share index order.
+    pub old_paths: Vec<PathBuf>,
+    /// The refreshed shares, in the same order.
+    pub share_paths: Vec<PathBuf>,
+    pub old_split_id: [u8; SPLIT_ID_LEN],
+    pub split_id: [u8; SPLIT_ID_LEN],
+    /// What the health tests saw of the masks.
+    pub health: HealthStats,
+}
+
+impl RefreshedShares {
+    /// The new split-set ID as lowercase hex, for messages.
+    pub fn split_id_hex(&self) -> String {
+        crate::to_hex(&self.split_id)
+    }
+
+    /// The old split-set ID as lowercase hex, for messages.
+    pub fn old_split_id_hex(&self) -> String {
+        crate::to_hex(&self.old_split_id)
+    }
+}
+
+/// Replace the shares of an XOR split with a fresh set for the same
+/// original, without combining them, drawing the masks and the new split
+/// ID from `random`.
+///
+/// Every share of the split is needed; given one, the others are found as
+/// by [`combine_files`]. Seeded and one-time pad splits cannot be
+/// refreshed, since their first share is not stored in full, and neither
+/// can threshold splits or shares split again into sub-shares.
+///
+/// [`combine_files`]: crate::combine_files
+pub fn refresh_shares(
+    shares: &[PathBuf],
+    options: &RefreshOptions,
+    random: &mut dyn RandomSource,
+) -> Result<RefreshedShares> {
+    let (mut inputs, _) = crate::resolve_shares(shares)?;
+    inputs.sort_by_key(|share| share.header.as_ref().map(|header| header.index));
+
+    let mut headers = Vec::with_capacity(inputs.len());
+    for share in &inputs {
+        let Some(header) = &share.header else {
+            bail!(
+                "{} is a legacy raw share without a header and cannot be refreshed; split the original again",
+                share.path.display()
+            );
+        };
+        if header.scheme != SchemeKind::Xor {
+            bail!(
+                "{} is a threshold share; only XOR shares can be refreshed",
+                share.path.display()
+            );
+        }
+        if share.is_joined() {
+            bail!(
+                "share {} of split {} was split again into sub-shares, \
+                 which cannot be refreshed",
+                header.index,
+                header.split_id_hex()
+            );
+        }
+        if header.seed.is_some() || header.one_time_pad.is_some() {
+            bail!(
+                "split {} keeps its first pad as a seed or in a one-time pad file, \
+                 so its shares cannot be refreshed",
+                header.split_id_hex()
+            );
+        }
+        headers.push(header.clone());
+    }
+    let old_split_id = headers[0].split_id;
+
+    let tag = inputs[0].tag;
+    if inputs.iter().any(|share| share.tag != tag) {
+        bail!(
+            "the shares of split {} carry different MAC tags; one was damaged or tampered with",
+            headers[0].split_id_hex()
+        );
+    }
+
+    let old_paths: Vec<PathBuf> = inputs.iter().map(|share| share.path.clone()).collect();
+    let in_place = options.output_paths.is_empty();
+    let share_paths = if in_place {
+        old_paths.clone()
+    } else {
+        if options.output_paths.len() != inputs.len() {
+            bail!(
+                "got {} output paths for {} shares; give one per share, in share order",
+                options.output_paths.len(),
+                inputs.len()
+            );
+        }
+        if let Some(path) = options
+            .output_paths
+            .iter()
+            .find(|path| old_paths.contains(path))
+        {
+            bail!(
+                "{} is one of the old shares; leave out --output to replace the old shares",
+                path.display()
+            );
+        }
+        options.output_paths.clone()
+    };
+    // In place, the refreshed shares are written next to the old ones and
+    // only take their names once every one of them is complete. The old
+    // shares are moved aside meanwhile, and only deleted once every name
+    // holds a refreshed share.
+    let (write_paths, aside_paths): (Vec<PathBuf>, Vec<PathBuf>) = if in_place {
+        old_paths
+            .iter()
+            .map(|path| {
+                (
+                    crate::append_extension(path, "refresh"),
+                    crate::append_extension(path, "old"),
+                )
+            })
+            .unzip()
+    } else {
+        (share_paths.clone(), old_paths.clone())
+    };
+    if in_place && let Some(path) = aside_paths.iter().find(|path| path.exists()) {
+        bail!(
+            "{} is in the way of moving an old share aside; remove it first",
+            path.display()
+        );
+    }
+
+    let random = &mut HealthTested::new(random);
+
+    let mut split_id = [0u8; SPLIT_ID_LEN];
+    random.fill(&mut split_id)?;
+
+    let mut key_shares: Vec<Option<[u8; mac::KEY_LEN]>> =
+        headers.iter().map(|header| header.mac_key_share).collect();
+    if let Some((last, rest)) = key_shares.split_last_mut()
+        && let Some(last) = last
+    {
+        let mut mask = [0u8; mac::KEY_LEN];
+        for key_share in rest.iter_mut().flatten() {
+            random.fill(&mut mask)?;
+            crate::xor_into(key_share, &mask);
+            crate::xor_into(last, &mask);
+        }
+    }
+
+    let mut outputs = Vec::with_capacity(inputs.len());
+    let streamed = (|| -> Result<()> {
+        for ((header, key_share), path) in headers.iter().zip(&key_shares).zip(&write_paths) {
+            let header = ShareHeader {
+                split_id,
+                mac_key_share: *key_share,
+                block_len: header.block_len.map(|_| CHUNK_SIZE as u32),
+                ..header.clone()
+            };
+            outputs.push(ShareOutput::create(path, &header, false)?);
+        }
+        stream_refreshed(&mut inputs, &mut outputs, random)
+    })();
+    if let Err(e) = streamed {
+        let outcomes: Vec<String> = outputs.into_iter().map(ShareOutput::abort).collect();
+        bail!("refresh aborted: {:#}\n  {}", e, outcomes.join("\n  "));
+    }
+
+    let mut failures = Vec::new();
+    for share in outputs {
+        if let Err(e) = share.finish(tag.as_ref()) {
+            failures.push(format!("{:#}", e));
+        }
+    }
+    if !failures.is_empty() {
+        for path in &write_paths {
+            let _ = std::fs::remove_file(path);
+        }
+        bail!("refresh failed:\n  {}", failures.join("\n  "));
+    }
+    drop(inputs);
+
+    let health = random.stats();
+    if in_place {
+        replace_all(&old_paths, &write_paths, &aside_paths)?;
+    }
+    for old in &aside_paths {
+        let deleted = match options.secure_delete_passes {
+            Some(passes) => crate::secure_delete(old, passes, random),
+            None if in_place => std::fs::remove_file(old)
+                .with_context(|| format!("failed to remove {}", old.display())),
+            None => Ok(()),
+        };
+        deleted.with_context(|| {
+            format!(
+                "the refreshed shares are complete, but {} still holds an old share",
+                old.display()
+            )
+        })?;
+    }
+
+    Ok(RefreshedShares {
+        old_paths,
+        share_paths,
+        old_split_id,
+        split_id,
+        health,
+    })
+}
+
+/// Move each old share aside and give its name to the refreshed share.
+///
+/// If any step fails, the shares already moved are put back, so that the
+/// names hold either every old share or every refreshed one.
+fn replace_all(
+    old_paths: &[PathBuf],
+    write_paths: &[PathBuf],
+    aside_paths: &[PathBuf],
+) -> Result<()> {
+    let moves = old_paths.iter().zip(write_paths).zip(aside_paths);
+    for (i, ((old, written), aside)) in moves.clone().enumerate() {
+        if let Err(e) = replace_one(old, written, aside) {
+            for ((old, written), aside) in moves.take(i) {
+                let _ = std::fs::rename(old, written);
+                let _ = std::fs::rename(aside, old);
+            }
+            let left: Vec<String> = write_paths
+                .iter()
+                .map(|path| path.display().to_string())
+                .collect();
+            return Err(e.context(format!(
+                "the old shares were put back; the refreshed ones are left as {}",
+                left.join(", ")
+            )));
+        }
+    }
+    Ok(())
+}
+
+/// Move `old` to `aside` and `written` to `old`, moving `old` back if the
+/// second step fails.
+fn replace_one(old: &Path, written: &Path, aside: &Path) -> Result<()> {
+    std::fs::rename(old, aside)
+        .with_context(|| format!("failed to move {} aside", old.display()))?;
+    if let Err(e) = std::fs::rename(written, old) {
+        let _ = std::fs::rename(aside, old);
+        return Err(e).with_context(|| {
+            format!(
+                "failed to replace {} with {}",
+                old.display(),
+                written.display()
+            )
+        });
+    }
+    Ok(())
+}
+
+/// Stream the old shares into the new ones, masking every block.
+fn stream_refreshed(
+    inputs: &mut [ShareInput],
+    outputs: &mut [ShareOutput],
+    random: &mut dyn RandomSource,
+) -> Result<()> {
+    let mut old = vec![vec![0u8; CHUNK_SIZE]; inputs.len()];
+    let mut new = vec![vec![0u8; CHUNK_SIZE]; inputs.len()];
+    let mut mask = vec![0u8; CHUNK_SIZE];
+
+    loop {
+        let mut len = None;
+        for (share, buf) in inputs.iter_mut().zip(old.iter_mut()) {
+            let n = share.read_payload(buf)?;
+            if *len.get_or_insert(n) != n {
+                bail!("unexpected read size mismatch during refresh");
+            }
+        }
+        let n = len.unwrap_or(0);
+        if n == 0 {
+            break;
+        }
+
+        let (old_last, old_rest) = old.split_last().expect("at least two shares");
+        let (new_last, new_rest) = new.split_last_mut().expect("at least two shares");
+        new_last[..n].copy_from_slice(&old_last[..n]);
+        for (old_share, new_share) in old_rest.iter().zip(new_rest.iter_mut()) {
+            random.fill(&mut mask[..n])?;
+            crate::xor_buffers(&old_share[..n], &mask[..n], &mut new_share[..n]);
+            crate::xor_into(&mut new_last[..n], &mask[..n]);
+        }
+
+        for (output, new_share) in outputs.iter_mut().zip(&new) {
+            output.write_block(&new_share[..n])?;
+        }
+    }
+
+    crate::check_blocks(inputs).context("refreshing would carry the damage into the new shares")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{SeededRandom, SplitOptions, combine_files_to, split_file};
+
+    #[test]
+    fn refreshed_shares_differ_but_combine_to_the_same_file() {
+        for (name, secure_delete_passes) in [("refresh", None), ("refresh-secure", Some(1))] {
+            let dir = crate::test_dir(name);
+            let input = dir.join("secret");
+            let content: Vec<u8> = (0..150_000u32).map(|i| (i % 253) as u8).collect();
+            std::fs::write(&input, &content).unwrap();
+            let options = SplitOptions {
+                mac: true,
+                ..SplitOptions::default()
+            };
+            let paths = split_file(&input, 3, &options, &mut SeededRandom::from_u64(1))
+                .unwrap()
+                .share_paths;
+            let old: Vec<Vec<u8>> = paths.iter().map(|p| std::fs::read(p).unwrap()).collect();
+
+            let options = RefreshOptions {
+                secure_delete_passes,
+                ..RefreshOptions::default()
+            };
+            let refreshed =
+                refresh_shares(&paths[..1], &options, &mut SeededRandom::from_u64(2)).unwrap();
+            assert_eq!(refreshed.share_paths, paths);
+            assert_ne!(refreshed.split_id, refreshed.old_split_id);
+            for (path, old) in paths.iter().zip(&old) {
+                let new = std::fs::read(path).unwrap();
+                assert_eq!(new.len(), old.len());
+                assert_ne!(&new, old, "{} was not refreshed", path.display());
+            }
+
+            let output = dir.join("restored");
+            combine_files_to(&paths, &output).unwrap();
+            assert_eq!(std::fs::read(&output).unwrap(), content);
+
+            // Only the input, the refreshed shares and the output are left:
+            // no `.old` asides and no `.refresh` temporaries.
+            let mut names: Vec<String> = std::fs::read_dir(&dir)
+                .unwrap()
+                .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+                .collect();
+            names.sort();
+            assert_eq!(
+                names,
+                [
+                    "restored",
+                    "secret",
+                    "secret.xor1",
+                    "secret.xor2",
+                    "secret.xor3"
+                ]
+            );
+            std::fs::remove_dir_all(&dir).unwrap();
+        }
+    }
+}
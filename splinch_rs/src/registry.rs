@@ -0,0 +1,250 @@
+//! A local registry of pad fingerprints, to catch a pad used twice.
+//!
+//! Reusing a pad across two files is the one fatal mistake with XOR
+//! splitting: XOR-ing the two other shares cancels the pad and leaves the
+//! XOR of the two files. With [`SplitOptions::pad_registry`] set, every split
+//! records a fingerprint of each XOR pad it produces (shares 1 to N-1, the
+//! seeded or one-time pad included), and warns when a fingerprint was
+//! already recorded. [`check_pad_registry`] warns when shares about to be
+//! combined carry a pad recorded for a different split.
+//!
+//! A fingerprint is a keyed SHA-256 of the first and of the last
+//! [`FINGERPRINT_BLOCK`] bytes of the pad, so a pad reused for a file of
+//! another length still matches on its first block. The key is drawn when
+//! the registry is created and stored in it, so the fingerprints reveal
+//! nothing about the pads to anyone without the registry. The file is text:
+//!
+//! ```text
+//! # splinch pad registry
+//! key <key>
+//! <first block> <last block> <split ID> <share index>
+//! ```
+//!
+//! [`SplitOptions::pad_registry`]: crate::SplitOptions::pad_registry
+//! [`check_pad_registry`]: crate::check_pad_registry
+
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result, bail};
+use sha2::{Digest, Sha256};
+
+use crate::RandomSource;
+use crate::manifest::from_hex;
+
+/// Bytes at each end of a pad that its fingerprint covers.
+pub const FINGERPRINT_BLOCK: usize = 32;
+
+const KEY_LEN: usize = 32;
+
+/// The first and last bytes of a pad, collected as it streams past.
+#[derive(Debug, Clone, Default)]
+pub struct PadBlocks {
+    first: Vec<u8>,
+    last: Vec<u8>,
+}
+
+impl PadBlocks {
+    /// The blocks of a pad read directly from its two ends.
+    pub fn new(first: Vec<u8>, last: Vec<u8>) -> Self {
+        Self { first, last }
+    }
+
+    /// Take in the next pad bytes.
+    pub fn update(&mut self, data: &[u8]) {
+        let wanted = FINGERPRINT_BLOCK.saturating_sub(self.first.len());
+        self.first
+            .extend_from_slice(&data[..wanted.min(data.len())]);
+
+        self.last
+            .extend_from_slice(&data[data.len().saturating_sub(FINGERPRINT_BLOCK)..]);
+        let excess = self.last.len().saturating_sub(FINGERPRINT_BLOCK);
+        self.last.drain(..excess);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.first.is_empty()
+    }
+}
+
+/// A pad that the registry has already seen in another split.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PadReuse {
+    /// The share, or one-time pad, that holds the pad.
+    pub share: PathBuf,
+    /// Its share index.
+    pub index: u16,
+    /// The split ID, as lowercase hex, that the pad was first recorded for.
+    pub earlier_split_id: String,
+    /// The share index it had in that split.
+    pub earlier_index: u16,
+}
+
+/// A pad of one split, to record or look up.
+pub struct Pad {
+    pub share: PathBuf,
+    pub index: u16,
+    /// The split ID as lowercase hex.
+    pub split_id: String,
+    pub blocks: PadBlocks,
+}
+
+struct Entry {
+    first: [u8; 32],
+    last: [u8; 32],
+    split_id: String,
+    index: u16,
+}
+
+struct Registry {
+    key: [u8; KEY_LEN],
+    entries: Vec<Entry>,
+}
+
+impl Registry {
+    fn fingerprint(&self, blocks: &PadBlocks) -> ([u8; 32], [u8; 32]) {
+        let hash = |label: &[u8], block: &[u8]| -> [u8; 32] {
+            let mut hasher = Sha256::new();
+            hasher.update(self.key);
+            hasher.update(label);
+            hasher.update(block);
+            hasher.finalize().into()
+        };
+        (hash(b"first", &blocks.first), hash(b"last", &blocks.last))
+    }
+
+    /// Where `pad` was seen before, if anywhere. With `same_split` unset,
+    /// sightings under the pad's own split ID do not count.
+    fn find_reuse(&self, pad: &Pad, same_split: bool) -> Option<PadReuse> {
+        let (first, last) = self.fingerprint(&pad.blocks);
+        self.entries
+            .iter()
+            .find(|entry| {
+                (same_split || entry.split_id != pad.split_id)
+                    && (entry.first == first || entry.last == last)
+            })
+            .map(|entry| PadReuse {
+                share: pad.share.clone(),
+                index: pad.index,
+                earlier_split_id: entry.split_id.clone(),
+                earlier_index: entry.index,
+            })
+    }
+}
+
+/// Read the registry at `path`; `None` if it does not exist yet.
+fn load(path: &Path) -> Result<Option<Registry>> {
+    let text = match std::fs::read_to_string(path) {
+        Ok(text) => text,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e).with_context(|| format!("failed to read {}", path.display())),
+    };
+
+    let mut key = None;
+    let mut entries = Vec::new();
+    for (i, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        if let Some(hex) = line.strip_prefix("key ") {
+            key = from_hex(hex.trim());
+            if key.is_none() {
+                bail!("{} line {} has a malformed key", path.display(), i + 1);
+            }
+            continue;
+        }
+        match parse_entry(line) {
+            Some(entry) => entries.push(entry),
+            None => bail!("{} line {} is malformed: {:?}", path.display(), i + 1, line),
+        }
+    }
+
+    let Some(key) = key else {
+        bail!("{} has no key; it is not a pad registry", path.display());
+    };
+    Ok(Some(Registry { key, entries }))
+}
+
+fn parse_entry(line: &str) -> Option<Entry> {
+    let mut fields = line.split_whitespace();
+    let first = from_hex(fields.next()?)?;
+    let last = from_hex(fields.next()?)?;
+    let split_id = fields.next()?.to_string();
+    let index = fields.next()?.parse().ok()?;
+    if fields.next().is_some() {
+        return None;
+    }
+    Some(Entry {
+        first,
+        last,
+        split_id,
+        index,
+    })
+}
+
+/// Record the pads of a new split in the registry at `path`, creating it
+/// with a fresh key from `random` if needed. Returns the pads the registry
+/// had already seen.
+///
+/// A new split's pads must never have been seen at all: even a sighting
+/// under the same split ID means the random source repeated itself, as a
+/// reused seed or random file does.
+pub fn record_pads(
+    path: &Path,
+    pads: &[Pad],
+    random: &mut dyn RandomSource,
+) -> Result<Vec<PadReuse>> {
+    let mut header = String::new();
+    let registry = match load(path)? {
+        Some(registry) => registry,
+        None => {
+            let mut key = [0u8; KEY_LEN];
+            random.fill(&mut key)?;
+            header = format!("# splinch pad registry\nkey {}\n", crate::to_hex(&key));
+            Registry {
+                key,
+                entries: Vec::new(),
+            }
+        }
+    };
+
+    let reuse = pads
+        .iter()
+        .filter_map(|pad| registry.find_reuse(pad, true))
+        .collect();
+
+    let mut text = header;
+    for pad in pads {
+        let (first, last) = registry.fingerprint(&pad.blocks);
+        text.push_str(&format!(
+            "{} {} {} {}\n",
+            crate::to_hex(&first),
+            crate::to_hex(&last),
+            pad.split_id,
+            pad.index
+        ));
+    }
+    let mut file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .with_context(|| format!("failed to open {}", path.display()))?;
+    file.write_all(text.as_bytes())
+        .and_then(|()| file.sync_all())
+        .with_context(|| format!("failed to write to {}", path.display()))?;
+    Ok(reuse)
+}
+
+/// Look up pads in the registry at `path` without recording them. A
+/// registry that does not exist has seen nothing.
+pub fn check_pads(path: &Path, pads: &[Pad]) -> Result<Vec<PadReuse>> {
+    let Some(registry) = load(path)? else {
+        return Ok(Vec::new());
+    };
+    Ok(pads
+        .iter()
+        .filter_map(|pad| registry.find_reuse(pad, false))
+        .collect())
+}
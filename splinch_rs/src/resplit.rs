@@ -0,0 +1,502 @@
+//! Splitting a single share again among a sub-team, and joining it back.
+//!
+//! A share can be split with XOR into sub-shares, so that it is only rebuilt
+//! when every holder of a sub-share is present: `secret.pdf.xor2` becomes
+//! `secret.pdf.xor2.sub1` and `secret.pdf.xor2.sub2`. A sub-share can be
+//! split again in turn. Each sub-share keeps the header of the share it
+//! came from and adds the path through the re-splits down to itself:
+//!
+//! ```text
+//! secret.pdf.xor2               share 2 of split S
+//! secret.pdf.xor2.sub1          share 2 of split S, sub-share 1 of 2 of re-split R
+//! secret.pdf.xor2.sub2.sub3     share 2 of split S, sub-share 2 of 2 of re-split R,
+//!                                                   sub-share 3 of 3 of re-split Q
+//! ```
+//!
+//! The MAC key share of the share is split the same way, so that no holder
+//! of a sub-share learns it, and every sub-share carries the share's tag.
+//! When combining, complete sets of sub-shares are joined back into the
+//! shares they came from, deepest re-split first, so the leaves of the whole
+//! tree can be given in one call.
+
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result, bail};
+
+use crate::header::{SPLIT_ID_LEN, ShareHeader, SubShare};
+use crate::health::HealthTested;
+use crate::share::{ShareInput, ShareOutput};
+use crate::{CHUNK_SIZE, HealthStats, RandomSource, Scheme, SplitOptions, mac};
+
+/// Most re-splits on the way from a share down to a sub-share.
+pub const MAX_DEPTH: usize = u8::MAX as usize;
+
+/// Options for [`resplit_share`].
+#[derive(Debug, Clone, Default)]
+pub struct ResplitOptions {
+    /// Number of sub-shares to split the share into.
+    pub sub_shares: usize,
+    /// Directories to write the sub-shares to under their default file
+    /// names: either one directory for every sub-share or one per sub-share.
+    pub output_dirs: Vec<PathBuf>,
+    /// Securely delete the share with this many overwrite passes once the
+    /// sub-shares are written.
+    pub secure_delete_passes: Option<u32>,
+}
+
+/// The sub-shares produced by a re-split.
+#[derive(Debug, Clone)]
+pub struct ResplitShares {
+    /// Sub-share file paths, in sub-share index order.
+    pub sub_share_paths: Vec<PathBuf>,
+    /// The ID common to the sub-shares of this re-split.
+    pub split_id: [u8; SPLIT_ID_LEN],
+    /// What the health tests saw of the random bytes the re-split drew.
+    pub health: HealthStats,
+}
+
+impl ResplitShares {
+    /// The re-split ID as lowercase hex, for messages.
+    pub fn split_id_hex(&self) -> String {
+        crate::to_hex(&self.split_id)
+    }
+}
+
+/// Split the share at `share_path` into XOR sub-shares, all of which are
+/// needed to rebuild it, drawing the pads and the re-split ID from `random`.
+///
+/// Produces `<share_path>.sub1` through `<share_path>.subN`, unless
+/// `options` gives output directories. The share may itself be a
+/// sub-share. A seeded share holds no pad to split, and a legacy raw share
+/// has no header to record the re-split in.
+pub fn resplit_share(
+    share_path: &Path,
+    options: &ResplitOptions,
+    random: &mut dyn RandomSource,
+) -> Result<ResplitShares> {
+    let sub_shares = options.sub_shares;
+    if sub_shares < 2 {
+        bail!("sub-share count must be at least 2, got {}", sub_shares);
+    }
+    if sub_shares > u16::MAX as usize {
+        bail!(
+            "sub-share count must be at most {}, got {}",
+            u16::MAX,
+            sub_shares
+        );
+    }
+
+    let mut share = ShareInput::open(share_path)?;
+    let Some(header) = share.header.clone() else {
+        bail!(
+            "{} is a legacy raw share without a header and cannot be split again",
+            share_path.display()
+        );
+    };
+    if header.seed.is_some() {
+        bail!(
+            "{} holds a seed in place of its pad and cannot be split again",
+            share_path.display()
+        );
+    }
+    if header.sub_share.len() >= MAX_DEPTH {
+        bail!(
+            "{} is already {} re-splits deep",
+            share_path.display(),
+            MAX_DEPTH
+        );
+    }
+
+    let random = &mut HealthTested::new(random);
+
+    let paths = crate::share_paths(
+        share_path,
+        "sub",
+        1..=sub_shares,
+        &SplitOptions {
+            output_dirs: options.output_dirs.clone(),
+            ..SplitOptions::default()
+        },
+        random,
+    )?;
+
+    let mut split_id = [0u8; SPLIT_ID_LEN];
+    random.fill(&mut split_id)?;
+
+    let mut key_shares = vec![header.mac_key_share; sub_shares];
+    if let Some((last, pads)) = key_shares.split_last_mut()
+        && let Some(last) = last
+    {
+        for key_share in pads.iter_mut().flatten() {
+            random.fill(key_share)?;
+            crate::xor_into(last, key_share);
+        }
+    }
+
+    let mut outputs = Vec::with_capacity(sub_shares);
+    let streamed = (|| -> Result<()> {
+        for (i, (path, key_share)) in paths.iter().zip(&key_shares).enumerate() {
+            let mut sub_share = header.sub_share.clone();
+            sub_share.push(SubShare {
+                index: (i + 1) as u16,
+                count: sub_shares as u16,
+                split_id,
+            });
+            let header = ShareHeader {
+                mac_key_share: *key_share,
+                block_len: Some(CHUNK_SIZE as u32),
+                sub_share,
+                ..header.clone()
+            };
+            outputs.push(ShareOutput::create(path, &header, false)?);
+        }
+
+        let mut chunk = vec![0u8; CHUNK_SIZE];
+        let mut sub_bufs = vec![vec![0u8; CHUNK_SIZE]; sub_shares];
+        loop {
+            let n = share.read_payload(&mut chunk)?;
+            if n == 0 {
+                break;
+            }
+            Scheme::Xor.encode(&chunk[..n], &mut [], &mut sub_bufs, None, random)?;
+            for (output, sub_buf) in outputs.iter_mut().zip(&sub_bufs) {
+                output.write_block(&sub_buf[..n])?;
+            }
+        }
+        crate::check_blocks(std::slice::from_ref(&share))
+            .context("splitting it again would carry the damage into the sub-shares")
+    })();
+    if let Err(e) = streamed {
+        let outcomes: Vec<String> = outputs.into_iter().map(ShareOutput::abort).collect();
+        bail!("re-split aborted: {:#}\n  {}", e, outcomes.join("\n  "));
+    }
+
+    let mut failures = Vec::new();
+    for output in outputs {
+        if let Err(e) = output.finish(share.tag.as_ref()) {
+            failures.push(format!("{:#}", e));
+        }
+    }
+    if !failures.is_empty() {
+        for path in &paths {
+            let _ = std::fs::remove_file(path);
+        }
+        bail!("re-split failed:\n  {}", failures.join("\n  "));
+    }
+    let health = random.stats();
+    drop(share);
+
+    if let Some(passes) = options.secure_delete_passes {
+        crate::secure_delete(share_path, passes, random)?;
+    }
+
+    Ok(ResplitShares {
+        sub_share_paths: paths,
+        split_id,
+        health,
+    })
+}
+
+/// The default path of sub-share `index` of the share at `path`.
+fn sub_share_path(path: &Path, index: usize) -> PathBuf {
+    crate::append_extension(path, &format!("sub{}", index))
+}
+
+/// The path of the share that a sub-share named by default came from, at
+/// the top of its tree.
+pub(crate) fn whole_share_path(path: &Path) -> PathBuf {
+    let mut path = path.to_path_buf();
+    while crate::share_index(&path, "sub").is_some() {
+        path.set_extension("");
+    }
+    path
+}
+
+/// Whether `path` is a file, or was split into sub-shares named after it.
+pub(crate) fn share_exists(path: &Path) -> bool {
+    let mut path = path.to_path_buf();
+    for _ in 0..=MAX_DEPTH {
+        if path.exists() {
+            return true;
+        }
+        path = sub_share_path(&path, 1);
+    }
+    false
+}
+
+/// Open the share at `path`, or join it from the sub-shares named after it
+/// if it was split again.
+pub(crate) fn open_share_tree(path: &Path) -> Result<ShareInput> {
+    open_sub_tree(path, 0)
+}
+
+/// Open the share or sub-share at `path`, `depth_wanted` re-splits down, joining
+/// it from the sub-shares named after it if it was split again.
+fn open_sub_tree(path: &Path, depth_wanted: usize) -> Result<ShareInput> {
+    let mut leaves = Vec::new();
+    find_leaves(path, &mut leaves)?;
+    let mut shares = join_up_to(leaves, depth_wanted)?;
+    match shares.pop() {
+        Some(share) if shares.is_empty() && depth(&share) == depth_wanted => Ok(share),
+        _ => bail!(
+            "the sub-shares named after {} do not rebuild one share",
+            path.display()
+        ),
+    }
+}
+
+fn find_leaves(path: &Path, leaves: &mut Vec<ShareInput>) -> Result<()> {
+    if path.exists() {
+        leaves.push(ShareInput::open(path)?);
+        return Ok(());
+    }
+    let mut index = 1;
+    while share_exists(&sub_share_path(path, index)) {
+        find_leaves(&sub_share_path(path, index), leaves)?;
+        index += 1;
+    }
+    if index == 1 {
+        bail!("partner file not found: {}", path.display());
+    }
+    Ok(())
+}
+
+/// Join every complete set of sub-shares among `shares` into the share it
+/// came from, deepest re-split first. Shares that were never split again
+/// are passed through. Each joined share takes the place of its first
+/// sub-share in the order given.
+pub(crate) fn join_sub_shares(shares: Vec<ShareInput>) -> Result<Vec<ShareInput>> {
+    join_up_to(shares, 0)
+}
+
+/// Join sub-shares as [`join_sub_shares`] does, but only up to `top`
+/// re-splits down.
+fn join_up_to(shares: Vec<ShareInput>, top: usize) -> Result<Vec<ShareInput>> {
+    let (mut joined, mut pending): (Vec<_>, Vec<_>) = shares
+        .into_iter()
+        .enumerate()
+        .partition(|(_, share)| depth(share) <= top);
+
+    while let Some(deepest) = pending.iter().map(|(_, share)| depth(share)).max() {
+        let (level, rest): (Vec<_>, Vec<_>) = pending
+            .into_iter()
+            .partition(|(_, share)| depth(share) == deepest);
+        pending = rest;
+
+        let mut groups: Vec<Vec<(usize, ShareInput)>> = Vec::new();
+        for (position, share) in level {
+            match groups
+                .iter_mut()
+                .find(|group| same_parent(sub_header(&group[0].1), sub_header(&share)))
+            {
+                Some(group) => group.push((position, share)),
+                None => groups.push(vec![(position, share)]),
+            }
+        }
+        for group in groups {
+            let (position, share) = join_group(group)?;
+            if depth(&share) <= top {
+                joined.push((position, share));
+            } else {
+                pending.push((position, share));
+            }
+        }
+    }
+
+    joined.sort_by_key(|(position, _)| *position);
+    Ok(joined.into_iter().map(|(_, share)| share).collect())
+}
+
+/// How many re-splits down from its share `share` is.
+fn depth(share: &ShareInput) -> usize {
+    share
+        .header
+        .as_ref()
+        .map_or(0, |header| header.sub_share.len())
+}
+
+fn sub_header(share: &ShareInput) -> &ShareHeader {
+    share.header.as_ref().expect("sub-shares have a header")
+}
+
+/// Whether two sub-shares at the same depth come from the same share.
+fn same_parent(a: &ShareHeader, b: &ShareHeader) -> bool {
+    let (a_last, a_path) = a.sub_share.split_last().expect("sub-share path");
+    let (b_last, b_path) = b.sub_share.split_last().expect("sub-share path");
+    a.split_id == b.split_id
+        && a.index == b.index
+        && a_path == b_path
+        && a_last.split_id == b_last.split_id
+}
+
+/// Join one complete set of sibling sub-shares into their share. Siblings
+/// that were not given are looked for under their default names.
+fn join_group(mut group: Vec<(usize, ShareInput)>) -> Result<(usize, ShareInput)> {
+    let level = |share: &ShareInput| *sub_header(share).sub_share.last().expect("sub-share path");
+    let header = sub_header(&group[0].1).clone();
+    let count = level(&group[0].1).count;
+    let parent = ShareHeader {
+        sub_share: header.sub_share[..header.sub_share.len() - 1].to_vec(),
+        ..header.clone()
+    };
+
+    let mut parent_path = whole_share_path(&group[0].1.path);
+    for step in &parent.sub_share {
+        parent_path = sub_share_path(&parent_path, step.index as usize);
+    }
+    for index in 1..=count {
+        let path = sub_share_path(&parent_path, index as usize);
+        if !group.iter().any(|(_, share)| level(share).index == index) && share_exists(&path) {
+            let sibling = open_sub_tree(&path, header.sub_share.len())?;
+            group.push((usize::MAX, sibling));
+        }
+    }
+    group.sort_by_key(|(_, share)| level(share).index);
+    let first = &group[0].1;
+
+    for pair in group.windows(2) {
+        let ((_, a), (_, b)) = (&pair[0], &pair[1]);
+        if !same_parent(sub_header(first), sub_header(b)) {
+            bail!(
+                "{} and {} are sub-shares of different shares",
+                first.path.display(),
+                b.path.display()
+            );
+        }
+        if level(b).count != count {
+            bail!(
+                "{} and {} disagree on the number of sub-shares of {}",
+                first.path.display(),
+                b.path.display(),
+                describe_share(&parent)
+            );
+        }
+        if level(a).index == level(b).index {
+            bail!(
+                "{} and {} are both sub-share {} of {}",
+                a.path.display(),
+                b.path.display(),
+                level(b).index,
+                describe_share(&parent)
+            );
+        }
+    }
+    // Indexes are distinct and within 1..=count, so enough of them are all.
+    if group.len() != count as usize {
+        let found: Vec<String> = group
+            .iter()
+            .map(|(_, share)| share.path.display().to_string())
+            .collect();
+        bail!(
+            "{} was split into {} sub-shares but only {} were found ({}); \
+             give every sub-share with -i",
+            describe_share(&parent),
+            count,
+            group.len(),
+            found.join(", ")
+        );
+    }
+
+    let mut mac_key_share = parent.mac_key_share.map(|_| [0u8; mac::KEY_LEN]);
+    for (_, share) in &group {
+        if share.tag != first.tag || share.payload_len != first.payload_len {
+            bail!(
+                "{} and {} are sub-shares of {} with different tags or lengths; \
+                 one was damaged or tampered with",
+                first.path.display(),
+                share.path.display(),
+                describe_share(&parent)
+            );
+        }
+        if let (Some(key), Some(key_share)) = (&mut mac_key_share, &sub_header(share).mac_key_share)
+        {
+            crate::xor_into(key, key_share);
+        }
+    }
+
+    let position = group
+        .iter()
+        .map(|(position, _)| *position)
+        .min()
+        .expect("a group is never empty");
+    let shares = group.into_iter().map(|(_, share)| share).collect();
+    let header = ShareHeader {
+        mac_key_share,
+        ..parent
+    };
+    Ok((position, ShareInput::join_sub_shares(shares, header)))
+}
+
+/// Name a share or sub-share for messages, e.g. `share 2.1 of split …`.
+fn describe_share(header: &ShareHeader) -> String {
+    let mut index = header.index.to_string();
+    for level in &header.sub_share {
+        index.push_str(&format!(".{}", level.index));
+    }
+    format!("share {} of split {}", index, header.split_id_hex())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{SeededRandom, combine_files_to, split_file};
+
+    #[test]
+    fn nested_sub_shares_combine_to_the_original() {
+        let dir = crate::test_dir("resplit");
+        let input = dir.join("secret");
+        let content: Vec<u8> = (0..150_000u32).map(|i| (i % 241) as u8).collect();
+        std::fs::write(&input, &content).unwrap();
+        let options = SplitOptions {
+            mac: true,
+            ..SplitOptions::default()
+        };
+        let shares = split_file(&input, 3, &options, &mut SeededRandom::from_u64(1))
+            .unwrap()
+            .share_paths;
+        let share = std::fs::read(&shares[1]).unwrap();
+
+        let resplit = |path: &Path, sub_shares, seed| {
+            let options = ResplitOptions {
+                sub_shares,
+                secure_delete_passes: Some(1),
+                ..ResplitOptions::default()
+            };
+            resplit_share(path, &options, &mut SeededRandom::from_u64(seed))
+                .unwrap()
+                .sub_share_paths
+        };
+        let subs = resplit(&shares[1], 2, 2);
+        assert_eq!(
+            subs,
+            [dir.join("secret.xor2.sub1"), dir.join("secret.xor2.sub2")]
+        );
+        assert!(!shares[1].exists());
+        let sub_payloads: Vec<Vec<u8>> = subs.iter().map(|p| std::fs::read(p).unwrap()).collect();
+        assert_ne!(sub_payloads[0], sub_payloads[1]);
+        assert_ne!(sub_payloads[0], share);
+
+        let nested = resplit(&subs[1], 3, 3);
+        assert_eq!(nested[2], dir.join("secret.xor2.sub2.sub3"));
+        assert!(!subs[1].exists());
+
+        // Given one share, the whole tree is found and joined back up.
+        let output = dir.join("restored");
+        combine_files_to(&shares[..1], &output).unwrap();
+        assert_eq!(std::fs::read(&output).unwrap(), content);
+
+        // Given the leaves, they are joined deepest first.
+        let mut leaves = vec![shares[0].clone(), subs[0].clone()];
+        leaves.extend(nested.iter().cloned());
+        leaves.push(shares[2].clone());
+        let output = dir.join("restored-from-leaves");
+        combine_files_to(&leaves, &output).unwrap();
+        assert_eq!(std::fs::read(&output).unwrap(), content);
+
+        // Without every sub-share the share cannot be joined.
+        std::fs::remove_file(&nested[2]).unwrap();
+        assert!(combine_files_to(&shares[..1], &dir.join("incomplete")).is_err());
+        assert!(!dir.join("incomplete").exists());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
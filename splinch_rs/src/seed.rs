@@ -0,0 +1,65 @@
+//! Seeded pads: the first share of an XOR split shrunk to a 256-bit seed.
+//!
+//! Normally every pad of an XOR split is as long as the input. In a seeded
+//! split the first pad is instead the ChaCha20 keystream of a random seed
+//! (with an all-zero nonce, as each seed is used once), and the first share
+//! stores only the seed in its header. That share is a few dozen bytes, small
+//! enough to read over the phone or print as a QR code, and combining
+//! regenerates the pad from it.
+//!
+//! The price is the security model: the other shares are only as hidden as
+//! ChaCha20 is strong, so a seeded split is computationally secure rather
+//! than information-theoretically secure.
+
+use anyhow::{Result, bail};
+use chacha20::ChaCha20;
+use chacha20::cipher::{KeyIvInit, StreamCipher, StreamCipherSeek};
+
+use crate::RandomSource;
+
+/// Length of the seed stored in the first share.
+pub const SEED_LEN: usize = 32;
+
+/// Longest pad a seed can drive: ChaCha20's 32-bit block counter runs out
+/// after 2^32 blocks of 64 bytes.
+pub const MAX_PAD_LEN: u64 = 64 << 32;
+
+/// A fresh random seed.
+pub fn generate_seed(random: &mut dyn RandomSource) -> Result<[u8; SEED_LEN]> {
+    let mut seed = [0u8; SEED_LEN];
+    random.fill(&mut seed)?;
+    Ok(seed)
+}
+
+/// The pad generated from a seed, readable from any offset.
+pub struct Keystream {
+    cipher: ChaCha20,
+}
+
+impl Keystream {
+    pub fn new(seed: &[u8; SEED_LEN]) -> Self {
+        Self {
+            cipher: ChaCha20::new(seed.into(), &[0u8; 12].into()),
+        }
+    }
+
+    /// Position the keystream `offset` bytes into the pad.
+    pub fn seek(&mut self, offset: u64) -> Result<()> {
+        if self.cipher.try_seek(offset).is_err() {
+            bail!("offset {} is beyond the end of a seeded pad", offset);
+        }
+        Ok(())
+    }
+
+    /// Fill `buf` with the next pad bytes.
+    pub fn fill(&mut self, buf: &mut [u8]) -> Result<()> {
+        buf.fill(0);
+        if self.cipher.try_apply_keystream(buf).is_err() {
+            bail!(
+                "a seeded pad covers at most {} bytes of split data",
+                MAX_PAD_LEN
+            );
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,288 @@
+//! Shamir k-of-n secret sharing over GF(256).
+//!
+//! Each byte of the input is the constant term of a random polynomial of
+//! degree `k - 1`; share `x` holds that polynomial evaluated at `x`. Any `k`
+//! shares determine the polynomial (and so the byte) by Lagrange
+//! interpolation at zero, while fewer than `k` reveal nothing about it.
+//!
+//! Arithmetic uses the AES field polynomial x^8 + x^4 + x^3 + x + 1 with
+//! generator 3. Multiplication by a fixed factor is done through a 256-byte
+//! lookup table so the per-byte work in the streaming loops is one table
+//! lookup and one XOR per term.
+
+use anyhow::{Result, bail};
+
+/// Largest number of shares: x-coordinates are the non-zero field elements.
+pub const MAX_SHARES: usize = 255;
+
+const fn build_tables() -> ([u8; 256], [u8; 256]) {
+    let mut exp = [0u8; 256];
+    let mut log = [0u8; 256];
+    let mut x: u8 = 1;
+    let mut i = 0;
+    while i < 255 {
+        exp[i] = x;
+        log[x as usize] = i as u8;
+        // Multiply by the generator 3 = x + 1, reducing by 0x11b.
+        let doubled = (x << 1) ^ if x & 0x80 != 0 { 0x1b } else { 0 };
+        x ^= doubled;
+        i += 1;
+    }
+    exp[255] = exp[0];
+    (exp, log)
+}
+
+const TABLES: ([u8; 256], [u8; 256]) = build_tables();
+const EXP: [u8; 256] = TABLES.0;
+const LOG: [u8; 256] = TABLES.1;
+
+fn mul(a: u8, b: u8) -> u8 {
+    if a == 0 || b == 0 {
+        return 0;
+    }
+    EXP[(LOG[a as usize] as usize + LOG[b as usize] as usize) % 255]
+}
+
+fn div(a: u8, b: u8) -> u8 {
+    assert_ne!(b, 0, "division by zero in GF(256)");
+    if a == 0 {
+        return 0;
+    }
+    EXP[(LOG[a as usize] as usize + 255 - LOG[b as usize] as usize) % 255]
+}
+
+/// Lookup table for multiplication by a fixed field element.
+fn mul_table(factor: u8) -> [u8; 256] {
+    let mut table = [0u8; 256];
+    for (v, entry) in table.iter_mut().enumerate() {
+        *entry = mul(v as u8, factor);
+    }
+    table
+}
+
+/// Check that `threshold` and `shares` describe a usable k-of-n scheme.
+pub fn check_parameters(threshold: usize, shares: usize) -> Result<()> {
+    if threshold < 2 {
+        bail!("threshold must be at least 2, got {}", threshold);
+    }
+    if shares < threshold {
+        bail!(
+            "share count ({}) must be at least the threshold ({})",
+            shares,
+            threshold
+        );
+    }
+    if shares > MAX_SHARES {
+        bail!("share count must be at most {}, got {}", MAX_SHARES, shares);
+    }
+    Ok(())
+}
+
+/// Evaluates the sharing polynomials at each share's x-coordinate.
+pub struct Splitter {
+    threshold: usize,
+    x_tables: Vec<[u8; 256]>,
+}
+
+impl Splitter {
+    /// Share `i` (zero-based) is evaluated at x = i + 1.
+    pub fn new(threshold: usize, shares: usize) -> Result<Self> {
+        check_parameters(threshold, shares)?;
+        let x_tables = (1..=shares).map(|x| mul_table(x as u8)).collect();
+        Ok(Self {
+            threshold,
+            x_tables,
+        })
+    }
+
+    /// Number of shares needed to rebuild the secret.
+    pub fn threshold(&self) -> usize {
+        self.threshold
+    }
+
+    /// Number of random bytes needed per input byte.
+    pub fn coefficients(&self) -> usize {
+        self.threshold - 1
+    }
+
+    /// Compute every share of `secret`.
+    ///
+    /// `coeffs` holds `coefficients()` random slices of `secret.len()` bytes,
+    /// laid out back to back with the highest-degree coefficient last.
+    /// `outputs[i][..secret.len()]` receives share `i`.
+    pub fn split(&self, secret: &[u8], coeffs: &[u8], outputs: &mut [Vec<u8>]) {
+        let len = secret.len();
+        assert_eq!(coeffs.len(), len * self.coefficients());
+        assert_eq!(outputs.len(), self.x_tables.len());
+
+        let degree = self.coefficients();
+        for (table, output) in self.x_tables.iter().zip(outputs.iter_mut()) {
+            let y = &mut output[..len];
+            // Horner's rule, starting from the highest-degree coefficient.
+            y.copy_from_slice(&coeffs[(degree - 1) * len..degree * len]);
+            for c in (0..degree - 1).rev() {
+                let coeff = &coeffs[c * len..(c + 1) * len];
+                for (v, &a) in y.iter_mut().zip(coeff) {
+                    *v = table[*v as usize] ^ a;
+                }
+            }
+            for (v, &s) in y.iter_mut().zip(secret) {
+                *v = table[*v as usize] ^ s;
+            }
+        }
+    }
+}
+
+/// Recovers the secret from exactly `threshold` shares by Lagrange
+/// interpolation at zero.
+pub struct Interpolator {
+    weight_tables: Vec<[u8; 256]>,
+}
+
+impl Interpolator {
+    /// `xs` are the x-coordinates of the shares that will be combined, in the
+    /// order they will be passed to `accumulate`.
+    pub fn new(xs: &[u8]) -> Result<Self> {
+        for (i, &xi) in xs.iter().enumerate() {
+            if xi == 0 {
+                bail!("share x-coordinate must be non-zero");
+            }
+            if xs[..i].contains(&xi) {
+                bail!("share {} was given more than once", xi);
+            }
+        }
+
+        let weight_tables = xs
+            .iter()
+            .enumerate()
+            .map(|(i, &xi)| {
+                let weight = xs
+                    .iter()
+                    .enumerate()
+                    .filter(|&(j, _)| j != i)
+                    .fold(1u8, |acc, (_, &xj)| mul(acc, div(xj, xj ^ xi)));
+                mul_table(weight)
+            })
+            .collect();
+
+        Ok(Self { weight_tables })
+    }
+
+    /// Add the contribution of share `index` to `output`.
+    ///
+    /// The first share overwrites `output`; later shares are XOR-ed in.
+    pub fn accumulate(&self, index: usize, share: &[u8], output: &mut [u8]) {
+        let table = &self.weight_tables[index];
+        if index == 0 {
+            for (o, &s) in output.iter_mut().zip(share) {
+                *o = table[s as usize];
+            }
+        } else {
+            for (o, &s) in output.iter_mut().zip(share) {
+                *o ^= table[s as usize];
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{RandomSource, SeededRandom};
+
+    #[test]
+    fn multiplication_matches_the_aes_field() {
+        // FIPS-197 section 4.2.
+        assert_eq!(mul(0x57, 0x83), 0xc1);
+        assert_eq!(mul(0x57, 0x13), 0xfe);
+        assert_eq!(mul(0x57, 0x02), 0xae);
+        assert_eq!(mul(0x57, 0x01), 0x57);
+        assert_eq!(mul(0x57, 0x00), 0x00);
+        for a in 0..=255u8 {
+            for b in 0..=255u8 {
+                assert_eq!(mul(a, b), mul(b, a));
+            }
+        }
+    }
+
+    #[test]
+    fn inverses_match_the_aes_field() {
+        // FIPS-197 section 5.1.1: the S-box starts from 0x53^-1 = 0xca.
+        assert_eq!(div(1, 0x53), 0xca);
+        assert_eq!(div(1, 0x01), 0x01);
+        assert_eq!(div(0, 0x53), 0x00);
+        for a in 1..=255u8 {
+            assert_eq!(mul(a, div(1, a)), 1, "inverse of {:#04x}", a);
+            assert_eq!(div(mul(a, 0x83), 0x83), a);
+        }
+    }
+
+    /// Split `secret` into `shares` shares of which `threshold` are needed.
+    fn split(secret: &[u8], threshold: usize, shares: usize) -> Vec<Vec<u8>> {
+        let splitter = Splitter::new(threshold, shares).unwrap();
+        let mut coeffs = vec![0u8; secret.len() * splitter.coefficients()];
+        SeededRandom::from_u64(threshold as u64 * 1000 + shares as u64)
+            .fill(&mut coeffs)
+            .unwrap();
+        let mut outputs = vec![vec![0u8; secret.len()]; shares];
+        splitter.split(secret, &coeffs, &mut outputs);
+        outputs
+    }
+
+    /// Interpolate the shares whose zero-based indexes are set in `subset`.
+    fn combine(outputs: &[Vec<u8>], subset: u32) -> Vec<u8> {
+        let chosen: Vec<usize> = (0..outputs.len())
+            .filter(|i| subset & (1 << i) != 0)
+            .collect();
+        let xs: Vec<u8> = chosen.iter().map(|&i| i as u8 + 1).collect();
+        let interpolator = Interpolator::new(&xs).unwrap();
+        let mut secret = vec![0u8; outputs[0].len()];
+        for (index, &i) in chosen.iter().enumerate() {
+            interpolator.accumulate(index, &outputs[i], &mut secret);
+        }
+        secret
+    }
+
+    #[test]
+    fn every_threshold_subset_rebuilds_the_secret() {
+        let secret: Vec<u8> = (0..64).map(|i| (i * 37 + 11) as u8).collect();
+        for shares in 2..=6 {
+            for threshold in 2..=shares {
+                let outputs = split(&secret, threshold, shares);
+                for subset in 0..1u32 << shares {
+                    let size = subset.count_ones() as usize;
+                    if size == threshold {
+                        assert_eq!(
+                            combine(&outputs, subset),
+                            secret,
+                            "{}-of-{} with shares {:#b}",
+                            threshold,
+                            shares,
+                            subset
+                        );
+                    } else if size == threshold - 1 && size > 0 {
+                        assert_ne!(
+                            combine(&outputs, subset),
+                            secret,
+                            "{}-of-{} rebuilt from {} shares {:#b}",
+                            threshold,
+                            shares,
+                            size,
+                            subset
+                        );
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn bad_parameters_and_coordinates_are_rejected() {
+        assert!(Splitter::new(1, 3).is_err());
+        assert!(Splitter::new(4, 3).is_err());
+        assert!(Splitter::new(2, MAX_SHARES + 1).is_err());
+        assert!(Splitter::new(2, MAX_SHARES).is_ok());
+        assert!(Interpolator::new(&[0, 1]).is_err());
+        assert!(Interpolator::new(&[2, 2]).is_err());
+    }
+}
@@ -0,0 +1,592 @@
+//! Reading and writing the payload of share files.
+//!
+//! A share is a header, the payload, and an optional trailer. When the
+//! header has block checksums, the payload is stored as blocks of
+//! `block_len` bytes, each followed by its CRC-32. The reader hides that
+//! framing and records every block whose checksum does not match, so callers
+//! can stream the payload as plain bytes and report damage afterwards.
+//!
+//! A share that was split again into sub-shares is read from the first of
+//! them, with the others XOR-ed in as it streams; see the `resplit` module.
+
+use std::fs::{File, OpenOptions};
+use std::io::{BufReader, BufWriter, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, Stdio};
+
+use anyhow::{Context, Result, bail};
+use sha2::{Digest, Sha256};
+
+use crate::header::{CHECKSUM_LEN, ShareHeader};
+use crate::mac;
+use crate::seed::Keystream;
+
+/// A share file opened for reading, positioned at the start of its payload.
+pub struct ShareInput {
+    pub path: PathBuf,
+    /// `None` for a legacy raw share written without a header.
+    pub header: Option<ShareHeader>,
+    pub payload_offset: u64,
+    pub payload_len: u64,
+    /// The MAC tag stored after the payload, if the split has a MAC.
+    pub tag: Option<[u8; mac::TAG_LEN]>,
+    /// Indexes of blocks read so far whose checksum did not match.
+    pub corrupt_blocks: Vec<u64>,
+    reader: BufReader<File>,
+    /// The pad of a seeded share, generated instead of read.
+    keystream: Option<Keystream>,
+    /// Whether this is a range of a one-time pad file rather than a share.
+    one_time_pad: bool,
+    /// Payload offset of the next raw byte or checksummed block to read.
+    next_offset: u64,
+    /// The current checksummed block and how much of it has been returned.
+    block: Vec<u8>,
+    block_pos: usize,
+    /// The other sub-shares of a share that was split again, XOR-ed into
+    /// this one's payload as it is read.
+    sub_shares: Vec<ShareInput>,
+    sub_buf: Vec<u8>,
+}
+
+impl ShareInput {
+    /// Open a share and position it at the start of its payload.
+    pub fn open(path: &Path) -> Result<Self> {
+        let file =
+            File::open(path).with_context(|| format!("failed to open {}", path.display()))?;
+        let file_len = file
+            .metadata()
+            .with_context(|| format!("failed to read metadata for {}", path.display()))?
+            .len();
+        let mut reader = BufReader::new(file);
+
+        let parsed = ShareHeader::read_from(&mut reader)
+            .with_context(|| format!("failed to read share header from {}", path.display()))?;
+        let (header, payload_offset) = match parsed {
+            Some((header, header_len)) => (Some(header), header_len),
+            None => (None, 0),
+        };
+
+        let trailer_len = header.as_ref().map_or(0, ShareHeader::trailer_len);
+        if file_len < payload_offset + trailer_len {
+            bail!("{} is truncated", path.display());
+        }
+        let stored_len = file_len - payload_offset - trailer_len;
+
+        // A seeded share stores no payload. Its length is that of the other
+        // shares, which the caller fills in once it has them.
+        let keystream = header
+            .as_ref()
+            .and_then(|h| h.seed.as_ref())
+            .map(Keystream::new);
+        if keystream.is_some() && stored_len != 0 {
+            bail!("{} has payload bytes after its seed", path.display());
+        }
+
+        let payload_len = match header.as_ref().and_then(|h| h.block_len) {
+            Some(block_len) => payload_len_from_stored(stored_len, block_len as u64)
+                .with_context(|| format!("{} is truncated", path.display()))?,
+            None => stored_len,
+        };
+
+        let mut tag = None;
+        if header.as_ref().is_some_and(|h| h.mac_key_share.is_some()) {
+            let mut buf = [0u8; mac::TAG_LEN];
+            reader
+                .seek(SeekFrom::Start(payload_offset + stored_len))
+                .and_then(|_| reader.read_exact(&mut buf))
+                .with_context(|| format!("failed to read MAC tag from {}", path.display()))?;
+            tag = Some(buf);
+        }
+
+        let mut share = ShareInput {
+            path: path.to_path_buf(),
+            header,
+            payload_offset,
+            payload_len,
+            tag,
+            corrupt_blocks: Vec::new(),
+            reader,
+            keystream,
+            one_time_pad: false,
+            next_offset: 0,
+            block: Vec::new(),
+            block_pos: 0,
+            sub_shares: Vec::new(),
+            sub_buf: Vec::new(),
+        };
+        share.seek_payload(0)?;
+        Ok(share)
+    }
+
+    /// Stand the first of a complete set of sub-shares in for the share they
+    /// were split from, whose header is `header`.
+    ///
+    /// Reading it then yields the XOR of all of their payloads.
+    pub fn join_sub_shares(mut sub_shares: Vec<ShareInput>, header: ShareHeader) -> Self {
+        let mut share = sub_shares.remove(0);
+        share.header = Some(header);
+        share.sub_shares.append(&mut sub_shares);
+        share
+    }
+
+    /// Whether this share was rebuilt from sub-shares.
+    pub fn is_joined(&self) -> bool {
+        !self.sub_shares.is_empty()
+    }
+
+    /// Payload bytes per checksummed block, if the share has block checksums.
+    pub fn block_len(&self) -> Option<u64> {
+        self.header.as_ref()?.block_len.map(u64::from)
+    }
+
+    /// Open the range of a one-time pad file that stands in for share 1 of
+    /// the split whose other shares carry `split`.
+    ///
+    /// The share's header is made up from `split`, with the MAC key share
+    /// read from the start of the range.
+    pub fn open_one_time_pad(path: &Path, split: &ShareHeader) -> Result<Self> {
+        let offset = split.one_time_pad.expect("split uses a one-time pad");
+        let file = File::open(path)
+            .with_context(|| format!("failed to open the one-time pad {}", path.display()))?;
+        let mut reader = BufReader::new(file);
+
+        let mut mac_key_share = None;
+        let mut payload_offset = offset;
+        if split.mac_key_share.is_some() {
+            let mut key_share = [0u8; mac::KEY_LEN];
+            reader
+                .seek(SeekFrom::Start(offset))
+                .and_then(|_| reader.read_exact(&mut key_share))
+                .with_context(|| {
+                    format!(
+                        "{} ends before the range used by split {}",
+                        path.display(),
+                        split.split_id_hex()
+                    )
+                })?;
+            mac_key_share = Some(key_share);
+            payload_offset += mac::KEY_LEN as u64;
+        }
+
+        Ok(ShareInput {
+            path: path.to_path_buf(),
+            header: Some(ShareHeader {
+                index: 1,
+                mac_key_share,
+                block_len: None,
+                seed: None,
+                ..split.clone()
+            }),
+            payload_offset,
+            payload_len: 0,
+            tag: None,
+            corrupt_blocks: Vec::new(),
+            reader,
+            next_offset: 0,
+            block: Vec::new(),
+            block_pos: 0,
+            keystream: None,
+            one_time_pad: true,
+            sub_shares: Vec::new(),
+            sub_buf: Vec::new(),
+        })
+    }
+
+    /// Whether this is a range of a one-time pad file.
+    pub fn is_one_time_pad(&self) -> bool {
+        self.one_time_pad
+    }
+
+    /// Whether the payload is a pad that the share does not store, from a
+    /// seed or a one-time pad file, so its length comes from the other shares.
+    pub fn is_pad(&self) -> bool {
+        self.keystream.is_some() || self.one_time_pad
+    }
+
+    /// Set the payload length of a pad, as taken from the other shares, and
+    /// start reading it from the beginning.
+    pub fn set_pad_len(&mut self, len: u64) -> Result<()> {
+        if self.one_time_pad {
+            let file_len = self
+                .reader
+                .get_ref()
+                .metadata()
+                .with_context(|| format!("failed to read metadata for {}", self.path.display()))?
+                .len();
+            if file_len < self.payload_offset + len {
+                bail!(
+                    "{} ends before the range used by split {}",
+                    self.path.display(),
+                    self.header.as_ref().expect("pad header").split_id_hex()
+                );
+            }
+        }
+        self.payload_len = len;
+        self.seek_payload(0)
+    }
+
+    /// Position the reader `offset` bytes into the payload.
+    pub fn seek_payload(&mut self, offset: u64) -> Result<()> {
+        for sub_share in &mut self.sub_shares {
+            sub_share.seek_payload(offset)?;
+        }
+        if let Some(keystream) = &mut self.keystream {
+            keystream.seek(offset)?;
+            self.next_offset = offset;
+            return Ok(());
+        }
+
+        self.block.clear();
+        self.block_pos = 0;
+        if offset >= self.payload_len {
+            // Nothing is left to read, even part-way into the last block.
+            self.next_offset = offset;
+            return Ok(());
+        }
+
+        let (physical, block_start) = match self.block_len() {
+            Some(block_len) => {
+                let block = offset / block_len;
+                (block * (block_len + CHECKSUM_LEN as u64), block * block_len)
+            }
+            None => (offset, offset),
+        };
+
+        self.reader
+            .seek(SeekFrom::Start(self.payload_offset + physical))
+            .with_context(|| format!("failed to seek in {}", self.path.display()))?;
+        self.next_offset = block_start;
+
+        if block_start < offset {
+            self.load_block()?;
+            self.block_pos = (offset - block_start) as usize;
+        }
+        Ok(())
+    }
+
+    /// Read payload bytes into `buf`, stopping at the end of the payload.
+    pub fn read_payload(&mut self, buf: &mut [u8]) -> Result<usize> {
+        let n = self.read_own_payload(buf)?;
+        self.sub_buf.resize(n, 0);
+        for sub_share in &mut self.sub_shares {
+            if sub_share.read_payload(&mut self.sub_buf)? != n {
+                bail!(
+                    "sub-shares {} and {} differ in length",
+                    self.path.display(),
+                    sub_share.path.display()
+                );
+            }
+            crate::xor_into(&mut buf[..n], &self.sub_buf);
+        }
+        Ok(n)
+    }
+
+    /// Read payload bytes from this file alone, without its sub-shares.
+    fn read_own_payload(&mut self, buf: &mut [u8]) -> Result<usize> {
+        let remaining = self.payload_len.saturating_sub(self.next_offset);
+        if let Some(keystream) = &mut self.keystream {
+            let len = buf.len().min(remaining as usize);
+            keystream.fill(&mut buf[..len])?;
+            self.next_offset += len as u64;
+            return Ok(len);
+        }
+        if self.block_len().is_none() {
+            let len = buf.len().min(remaining as usize);
+            let n = crate::read_exact_or_eof(&mut self.reader, &mut buf[..len])?;
+            self.next_offset += n as u64;
+            return Ok(n);
+        }
+
+        let mut total = 0;
+        while total < buf.len() {
+            if self.block_pos == self.block.len() {
+                if self.next_offset >= self.payload_len {
+                    break;
+                }
+                self.load_block()?;
+            }
+            let n = (buf.len() - total).min(self.block.len() - self.block_pos);
+            buf[total..total + n].copy_from_slice(&self.block[self.block_pos..self.block_pos + n]);
+            total += n;
+            self.block_pos += n;
+        }
+        Ok(total)
+    }
+
+    /// Read the next checksummed block and check its CRC-32.
+    fn load_block(&mut self) -> Result<()> {
+        let block_len = self.block_len().expect("checksummed share");
+        let len = (self.payload_len - self.next_offset).min(block_len) as usize;
+
+        self.block.resize(len + CHECKSUM_LEN, 0);
+        self.reader
+            .read_exact(&mut self.block)
+            .with_context(|| format!("{} is truncated", self.path.display()))?;
+
+        let stored = u32::from_le_bytes(self.block[len..].try_into().expect("checksum length"));
+        self.block.truncate(len);
+
+        if crc32fast::hash(&self.block) != stored {
+            let index = self.next_offset / block_len;
+            if !self.corrupt_blocks.contains(&index) {
+                self.corrupt_blocks.push(index);
+            }
+        }
+
+        self.next_offset += len as u64;
+        self.block_pos = 0;
+        Ok(())
+    }
+
+    /// Whether a block read so far from this share, or from any of its
+    /// sub-shares, had a bad checksum.
+    pub fn has_corrupt_blocks(&self) -> bool {
+        !self.corrupt_blocks.is_empty() || self.sub_shares.iter().any(Self::has_corrupt_blocks)
+    }
+
+    /// Describe the corrupt blocks found so far, e.g. for error messages, one
+    /// line per damaged file.
+    pub fn describe_corrupt_blocks(&self) -> String {
+        let mut damaged: Vec<String> = self
+            .sub_shares
+            .iter()
+            .filter(|sub_share| sub_share.has_corrupt_blocks())
+            .map(Self::describe_corrupt_blocks)
+            .collect();
+        if !self.corrupt_blocks.is_empty() {
+            damaged.insert(0, self.describe_own_corrupt_blocks());
+        }
+        damaged.join("\n  ")
+    }
+
+    fn describe_own_corrupt_blocks(&self) -> String {
+        let block_len = self.block_len().unwrap_or(0);
+        let blocks: Vec<String> = self
+            .corrupt_blocks
+            .iter()
+            .map(|&index| {
+                let start = index * block_len;
+                let end = (start + block_len).min(self.payload_len);
+                format!("block {} (bytes {}..{})", index, start, end)
+            })
+            .collect();
+        format!("{}: {}", self.path.display(), blocks.join(", "))
+    }
+}
+
+/// Recover the payload length from the stored length of a checksummed payload.
+fn payload_len_from_stored(stored_len: u64, block_len: u64) -> Result<u64> {
+    let stored_block = block_len + CHECKSUM_LEN as u64;
+    let blocks = stored_len.div_ceil(stored_block);
+    let last = stored_len - (blocks.saturating_sub(1)) * stored_block;
+    if blocks > 0 && last <= CHECKSUM_LEN as u64 {
+        bail!("last checksummed block is incomplete");
+    }
+    Ok(stored_len - blocks * CHECKSUM_LEN as u64)
+}
+
+/// A share being written, either to a file or to the stdin of a command.
+pub struct ShareOutput {
+    /// The share's file name, or the name handed to its command.
+    pub path: PathBuf,
+    /// The shell command receiving the share, if it is not written to a file.
+    command: Option<(String, Child)>,
+    writer: BufWriter<Box<dyn Write>>,
+    checksums: bool,
+    /// Running SHA-256 of every byte written, when a manifest is wanted.
+    hasher: Option<Sha256>,
+    len: u64,
+}
+
+/// Size and optional SHA-256 of a share that has been fully written.
+pub struct WrittenShare {
+    pub path: PathBuf,
+    pub len: u64,
+    pub sha256: Option<[u8; 32]>,
+    /// The shell command the share was handed to, if it is not a file.
+    command: Option<String>,
+}
+
+impl WrittenShare {
+    /// Take back the share after another share of the split failed, and
+    /// describe what became of it.
+    ///
+    /// A share file is deleted. A share already handed to a command cannot
+    /// be recalled, so it is only reported.
+    pub fn discard(self) -> String {
+        match self.command {
+            Some(command) => format!(
+                "{} (`{}`): complete and already delivered",
+                self.path.display(),
+                command
+            ),
+            None => match std::fs::remove_file(&self.path) {
+                Ok(()) => format!("{}: complete, deleted", self.path.display()),
+                Err(e) => format!("{}: complete, could not delete: {}", self.path.display(), e),
+            },
+        }
+    }
+}
+
+impl ShareOutput {
+    /// Create the share file and write its header.
+    ///
+    /// Fails if `path` already exists rather than overwriting it. With `hash`
+    /// set, the SHA-256 of the whole file is computed as it is written and
+    /// returned by [`ShareOutput::finish`].
+    pub fn create(path: &Path, header: &ShareHeader, hash: bool) -> Result<Self> {
+        let file = OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(path)
+            .with_context(|| format!("failed to create {}", path.display()))?;
+        Self::start(path, None, Box::new(file), header, hash)
+    }
+
+    /// Run `command` with `sh -c` and stream the share into its stdin.
+    ///
+    /// `path` is the name the share would have had as a file. The command sees
+    /// it, the share index and count, and the split ID in `SPLINCH_SHARE_NAME`,
+    /// `SPLINCH_SHARE_INDEX`, `SPLINCH_SHARE_COUNT` and `SPLINCH_SPLIT_ID`.
+    pub fn spawn(command: &str, path: &Path, header: &ShareHeader, hash: bool) -> Result<Self> {
+        let file_name = path.file_name().unwrap_or(path.as_os_str());
+        let mut child = Command::new("sh")
+            .arg("-c")
+            .arg(command)
+            .env("SPLINCH_SHARE_NAME", file_name)
+            .env("SPLINCH_SHARE_INDEX", header.index.to_string())
+            .env("SPLINCH_SHARE_COUNT", header.count.to_string())
+            .env("SPLINCH_SPLIT_ID", header.split_id_hex())
+            .stdin(Stdio::piped())
+            .spawn()
+            .with_context(|| format!("failed to start `{}` for share {}", command, header.index))?;
+        let stdin = child.stdin.take().expect("stdin is piped");
+        Self::start(
+            path,
+            Some((command.to_string(), child)),
+            Box::new(stdin),
+            header,
+            hash,
+        )
+    }
+
+    fn start(
+        path: &Path,
+        command: Option<(String, Child)>,
+        sink: Box<dyn Write>,
+        header: &ShareHeader,
+        hash: bool,
+    ) -> Result<Self> {
+        let mut share = Self {
+            path: path.to_path_buf(),
+            command,
+            writer: BufWriter::new(sink),
+            checksums: header.block_len.is_some(),
+            hasher: hash.then(Sha256::new),
+            len: 0,
+        };
+
+        let mut encoded = Vec::new();
+        header
+            .write_to(&mut encoded)
+            .expect("writing to a Vec cannot fail");
+        share.write_raw(&encoded)?;
+
+        Ok(share)
+    }
+
+    /// Where the share goes, for messages.
+    pub fn describe(&self) -> String {
+        match &self.command {
+            Some((command, _)) => format!("{} (`{}`)", self.path.display(), command),
+            None => self.path.display().to_string(),
+        }
+    }
+
+    fn write_raw(&mut self, data: &[u8]) -> Result<()> {
+        if let Err(e) = self.writer.write_all(data) {
+            return Err(e).with_context(|| format!("failed to write to {}", self.describe()));
+        }
+        if let Some(hasher) = &mut self.hasher {
+            hasher.update(data);
+        }
+        self.len += data.len() as u64;
+        Ok(())
+    }
+
+    /// Write one payload block, followed by its checksum if the share has them.
+    ///
+    /// Every block except the last must be exactly the header's block length.
+    pub fn write_block(&mut self, data: &[u8]) -> Result<()> {
+        self.write_raw(data)?;
+        if self.checksums {
+            self.write_raw(&crc32fast::hash(data).to_le_bytes())?;
+        }
+        Ok(())
+    }
+
+    /// Write the trailer, if any, and flush the share to disk.
+    ///
+    /// For a command, closes its stdin and fails unless it exits successfully.
+    pub fn finish(mut self, tag: Option<&[u8; mac::TAG_LEN]>) -> Result<WrittenShare> {
+        if let Some(tag) = tag {
+            self.write_raw(tag)?;
+        }
+        let description = self.describe();
+        if let Err(e) = self.writer.flush() {
+            return Err(e).with_context(|| format!("failed to flush {}", description));
+        }
+        drop(self.writer);
+
+        let command = match self.command {
+            Some((command, mut child)) => {
+                let status = child
+                    .wait()
+                    .with_context(|| format!("failed to wait for {}", description))?;
+                if !status.success() {
+                    bail!("{} failed: {}", description, status);
+                }
+                Some(command)
+            }
+            None => None,
+        };
+
+        Ok(WrittenShare {
+            path: self.path,
+            len: self.len,
+            sha256: self.hasher.map(|hasher| hasher.finalize().into()),
+            command,
+        })
+    }
+
+    /// Give up on the share after the split failed, and describe what became
+    /// of it.
+    ///
+    /// A partial share file is deleted, and a command that is still running
+    /// is killed, so a courier never delivers a partial share as if it were
+    /// complete.
+    pub fn abort(self) -> String {
+        let description = self.describe();
+        let Some((_, mut child)) = self.command else {
+            drop(self.writer);
+            return match std::fs::remove_file(&self.path) {
+                Ok(()) => format!("{}: incomplete, deleted", description),
+                Err(e) => format!("{}: incomplete, could not delete: {}", description, e),
+            };
+        };
+
+        // Check and kill before closing stdin, so that the command cannot
+        // mistake the end of input for the end of a complete share.
+        let outcome = match child.try_wait() {
+            Ok(Some(status)) if !status.success() => format!("failed: {}", status),
+            Ok(Some(_)) => "exited before the split finished".to_string(),
+            _ => {
+                let _ = child.kill();
+                "stopped because the split was aborted".to_string()
+            }
+        };
+        drop(self.writer);
+        let _ = child.wait();
+        format!("{}: {}", description, outcome)
+    }
+}